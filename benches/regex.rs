@@ -11,7 +11,7 @@ struct SearchData {
     path: JpQuery,
 }
 
-const PATH: &str = "$[?search(@.author,'.*(?i)d\\\\(Rees\\\\)')]";
+const PATH: &str = "$[?search(@.author,'.*[dD]\\\\(Rees\\\\)')]";
 
 fn regex_perf_test_with_reuse(cfg: &SearchData) {
     let _v = cfg.path.process(State::root(&cfg.json)).data;
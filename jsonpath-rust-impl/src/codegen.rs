@@ -0,0 +1,287 @@
+use jsonpath_rust::parser::model::{
+    Comparable, Comparison, Filter, FilterAtom, FnArg, JpQuery, Literal, Segment, Selector,
+    SingularQuery, SingularQuerySegment, Test, TestFunction,
+};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// Turns a node of the query model into the expression that constructs it.
+pub(crate) trait ToRust {
+    fn to_rust(&self) -> TokenStream;
+}
+
+impl<T: ToRust> ToRust for Vec<T> {
+    fn to_rust(&self) -> TokenStream {
+        let items = self.iter().map(ToRust::to_rust);
+        quote! { ::std::vec![#(#items),*] }
+    }
+}
+
+impl<T: ToRust> ToRust for Box<T> {
+    fn to_rust(&self) -> TokenStream {
+        let item = self.as_ref().to_rust();
+        quote! { ::std::boxed::Box::new(#item) }
+    }
+}
+
+impl ToRust for Option<i64> {
+    fn to_rust(&self) -> TokenStream {
+        match self {
+            Some(v) => quote! { ::std::option::Option::Some(#v) },
+            None => quote! { ::std::option::Option::None },
+        }
+    }
+}
+
+impl ToRust for String {
+    fn to_rust(&self) -> TokenStream {
+        quote! { ::std::string::String::from(#self) }
+    }
+}
+
+impl ToRust for JpQuery {
+    fn to_rust(&self) -> TokenStream {
+        let segments = self.segments.to_rust();
+        quote! { ::jsonpath_rust::parser::model::JpQuery::new(#segments) }
+    }
+}
+
+impl ToRust for Segment {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Segment };
+        match self {
+            Segment::Descendant(s) => {
+                let s = s.to_rust();
+                quote! { #model::Descendant(#s) }
+            }
+            Segment::Selector(s) => {
+                let s = s.to_rust();
+                quote! { #model::Selector(#s) }
+            }
+            Segment::Selectors(s) => {
+                let s = s.to_rust();
+                quote! { #model::Selectors(#s) }
+            }
+        }
+    }
+}
+
+impl ToRust for Selector {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Selector };
+        match self {
+            Selector::Name(name) => {
+                let name = name.to_rust();
+                quote! { #model::Name(#name) }
+            }
+            Selector::Wildcard => quote! { #model::Wildcard },
+            Selector::Index(idx) => quote! { #model::Index(#idx) },
+            Selector::Slice(start, end, step) => {
+                let (start, end, step) = (start.to_rust(), end.to_rust(), step.to_rust());
+                quote! { #model::Slice(#start, #end, #step) }
+            }
+            Selector::Filter(filter) => {
+                let filter = filter.to_rust();
+                quote! { #model::Filter(#filter) }
+            }
+        }
+    }
+}
+
+impl ToRust for Filter {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Filter };
+        match self {
+            Filter::Or(items) => {
+                let items = items.to_rust();
+                quote! { #model::Or(#items) }
+            }
+            Filter::And(items) => {
+                let items = items.to_rust();
+                quote! { #model::And(#items) }
+            }
+            Filter::Atom(atom) => {
+                let atom = atom.to_rust();
+                quote! { #model::Atom(#atom) }
+            }
+        }
+    }
+}
+
+impl ToRust for FilterAtom {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::FilterAtom };
+        match self {
+            FilterAtom::Filter { expr, not } => {
+                let expr = expr.to_rust();
+                quote! { #model::Filter { expr: #expr, not: #not } }
+            }
+            FilterAtom::Test { expr, not } => {
+                let expr = expr.to_rust();
+                quote! { #model::Test { expr: #expr, not: #not } }
+            }
+            FilterAtom::Comparison(cmp) => {
+                let cmp = cmp.to_rust();
+                quote! { #model::Comparison(#cmp) }
+            }
+        }
+    }
+}
+
+impl ToRust for Comparison {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Comparison };
+        let (lhs, rhs) = self.vals();
+        let (lhs, rhs) = (lhs.to_rust(), rhs.to_rust());
+        let variant = match self {
+            Comparison::Eq(..) => quote! { Eq },
+            Comparison::Ne(..) => quote! { Ne },
+            Comparison::Gt(..) => quote! { Gt },
+            Comparison::Gte(..) => quote! { Gte },
+            Comparison::Lt(..) => quote! { Lt },
+            Comparison::Lte(..) => quote! { Lte },
+        };
+        quote! { #model::#variant(#lhs, #rhs) }
+    }
+}
+
+impl ToRust for Comparable {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Comparable };
+        match self {
+            Comparable::Literal(lit) => {
+                let lit = lit.to_rust();
+                quote! { #model::Literal(#lit) }
+            }
+            Comparable::Function(tf) => {
+                let tf = tf.to_rust();
+                quote! { #model::Function(#tf) }
+            }
+            Comparable::SingularQuery(query) => {
+                let query = query.to_rust();
+                quote! { #model::SingularQuery(#query) }
+            }
+        }
+    }
+}
+
+impl ToRust for SingularQuery {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::SingularQuery };
+        match self {
+            SingularQuery::Current(segments) => {
+                let segments = segments.to_rust();
+                quote! { #model::Current(#segments) }
+            }
+            SingularQuery::Root(segments) => {
+                let segments = segments.to_rust();
+                quote! { #model::Root(#segments) }
+            }
+        }
+    }
+}
+
+impl ToRust for SingularQuerySegment {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::SingularQuerySegment };
+        match self {
+            SingularQuerySegment::Index(idx) => quote! { #model::Index(#idx) },
+            SingularQuerySegment::Name(name) => {
+                let name = name.to_rust();
+                quote! { #model::Name(#name) }
+            }
+        }
+    }
+}
+
+impl ToRust for Test {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Test };
+        match self {
+            Test::RelQuery(segments) => {
+                let segments = segments.to_rust();
+                quote! { #model::RelQuery(#segments) }
+            }
+            Test::AbsQuery(query) => {
+                let query = query.to_rust();
+                quote! { #model::AbsQuery(#query) }
+            }
+            Test::Function(tf) => {
+                let tf = tf.to_rust();
+                quote! { #model::Function(#tf) }
+            }
+        }
+    }
+}
+
+impl ToRust for TestFunction {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::TestFunction };
+        match self {
+            TestFunction::Custom(name, args) => {
+                let (name, args) = (name.to_rust(), args.to_rust());
+                quote! { #model::Custom(#name, #args) }
+            }
+            TestFunction::Length(arg) => {
+                let arg = arg.to_rust();
+                quote! { #model::Length(#arg) }
+            }
+            TestFunction::Value(arg) => {
+                let arg = arg.to_rust();
+                quote! { #model::Value(#arg) }
+            }
+            TestFunction::Count(arg) => {
+                let arg = arg.to_rust();
+                quote! { #model::Count(#arg) }
+            }
+            TestFunction::Search(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.to_rust(), rhs.to_rust());
+                quote! { #model::Search(#lhs, #rhs) }
+            }
+            TestFunction::Match(lhs, rhs) => {
+                let (lhs, rhs) = (lhs.to_rust(), rhs.to_rust());
+                quote! { #model::Match(#lhs, #rhs) }
+            }
+        }
+    }
+}
+
+impl ToRust for FnArg {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::FnArg };
+        match self {
+            FnArg::Literal(lit) => {
+                let lit = lit.to_rust();
+                quote! { #model::Literal(#lit) }
+            }
+            FnArg::Test(test) => {
+                let test = test.to_rust();
+                quote! { #model::Test(#test) }
+            }
+            FnArg::Filter(filter) => {
+                let filter = filter.to_rust();
+                quote! { #model::Filter(#filter) }
+            }
+        }
+    }
+}
+
+impl ToRust for Literal {
+    fn to_rust(&self) -> TokenStream {
+        let model = quote! { ::jsonpath_rust::parser::model::Literal };
+        match self {
+            Literal::Int(v) => quote! { #model::Int(#v) },
+            // the bits keep the value exact, whatever the textual form of the literal was
+            Literal::Float(v) => {
+                let bits = v.to_bits();
+                quote! { #model::Float(f64::from_bits(#bits)) }
+            }
+            Literal::String(v) => {
+                let v = v.to_rust();
+                quote! { #model::String(#v) }
+            }
+            Literal::Bool(v) => quote! { #model::Bool(#v) },
+            Literal::Null => quote! { #model::Null },
+        }
+    }
+}
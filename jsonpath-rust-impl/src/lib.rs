@@ -0,0 +1,56 @@
+//! # jsonpath-rust-impl
+//! The companion crate of [`jsonpath-rust`](https://crates.io/crates/jsonpath-rust)
+//! providing the [`jsonpath!`] macro that validates a query while the program is compiled.
+//!
+//! A query given to the macro is parsed with the same grammar as
+//! `jsonpath_rust::parser::parse_json_path`, so a typo is reported by the compiler
+//! instead of surfacing as an error at runtime.
+//! The macro expands into an expression that builds the parsed
+//! `jsonpath_rust::parser::model::JpQuery` right away, without parsing the string again.
+//!
+//! Both crates need to be added to the dependencies, since the expansion refers to `jsonpath_rust`.
+//!
+//! ```
+//! use jsonpath_rust::parser::parse_json_path;
+//! use jsonpath_rust::query::js_path_process;
+//! use jsonpath_rust_impl::jsonpath;
+//! use serde_json::json;
+//!
+//! let query = jsonpath!("$.books[?@.price < 10].title");
+//! assert_eq!(query, parse_json_path("$.books[?@.price < 10].title").unwrap());
+//!
+//! let json = json!({"books": [{"title": "a", "price": 5}, {"title": "b", "price": 15}]});
+//! let titles = js_path_process(&query, &json).unwrap();
+//! assert_eq!(titles.len(), 1);
+//! ```
+//!
+//! An invalid query does not compile:
+//!
+//! ```compile_fail
+//! use jsonpath_rust_impl::jsonpath;
+//!
+//! let query = jsonpath!("$.books[?@.price = 10]");
+//! ```
+mod codegen;
+
+use codegen::ToRust;
+use jsonpath_rust::parser::diagnostics::Diagnostic;
+use jsonpath_rust::parser::parse_json_path;
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, LitStr};
+
+/// Parses a JSONPath query at compile time and expands to the `JpQuery` it denotes.
+///
+/// An invalid query is reported with `compile_error!`, explaining the problem in the query.
+#[proc_macro]
+pub fn jsonpath(input: TokenStream) -> TokenStream {
+    let lit = parse_macro_input!(input as LitStr);
+    let query = lit.value();
+
+    match parse_json_path(&query) {
+        Ok(jq) => jq.to_rust().into(),
+        Err(e) => syn::Error::new(lit.span(), Diagnostic::new(&query, &e))
+            .to_compile_error()
+            .into(),
+    }
+}
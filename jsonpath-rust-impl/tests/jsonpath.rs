@@ -0,0 +1,45 @@
+use jsonpath_rust::parser::parse_json_path;
+use jsonpath_rust::query::js_path_process;
+use jsonpath_rust_impl::jsonpath;
+use serde_json::json;
+
+macro_rules! same_as_runtime {
+    ($($query:literal),* $(,)?) => {
+        $(
+            assert_eq!(jsonpath!($query), parse_json_path($query).unwrap(), "{}", $query);
+        )*
+    };
+}
+
+#[test]
+fn expands_to_the_parsed_query() {
+    same_as_runtime!(
+        "$",
+        "$.a.b",
+        "$..*",
+        "$..['a',1][*]",
+        "$[1:5:2]",
+        "$[::-1]",
+        "$[-1]",
+        "$['a\\nb']",
+        "$[?@.a == 1.5e3 || @.b != 'x' && !@.c]",
+        "$[?(@.a > $.limit) && @.b <= -2]",
+        "$[?length(@.a) == count(@.b[*])]",
+        "$[?value(@..c) == null]",
+        "$[?match(@.a, 'a.*') && search(@.b, 'x')]",
+        "$.elems[?in(@, $.list)]",
+        "$[?!(@.a == true)]",
+    );
+}
+
+#[test]
+fn evaluates() {
+    let json = json!({"a": [1, 2, 3], "limit": 2});
+
+    let res = js_path_process(&jsonpath!("$.a[?@ >= $.limit]"), &json).unwrap();
+
+    assert_eq!(
+        res.into_iter().map(|r| r.val).collect::<Vec<_>>(),
+        vec![&json!(2), &json!(3)]
+    );
+}
@@ -93,8 +93,11 @@ extern crate pest_derive;
 extern crate core;
 extern crate pest;
 
+use crate::parser::errors::JsonPathError;
 use crate::query::queryable::Queryable;
-use crate::query::{Queried, QueryPath, QueryRef};
+use crate::query::{Queried, QueryIter, QueryPath, QueryRef};
+use serde::de::DeserializeOwned;
+use serde::Deserializer;
 use serde_json::Value;
 
 /// A trait for types that can be queried with JSONPath.
@@ -109,10 +112,137 @@ pub trait JsonPath: Queryable {
         query::js_path_path(path, self)
     }
 
+    /// Queries the value with a JSONPath expression and returns the locations of the matches
+    /// as JSON Pointers (RFC 6901), like `/store/book/0`.
+    fn query_pointers(&self, path: &str) -> Queried<Vec<String>> {
+        query::js_path_pointers(path, self)
+    }
+
     /// Queries the value with a JSONPath expression and returns a vector of values, omitting the path.
     fn query(&self, path: &str) -> Queried<Vec<&Self>> {
         query::js_path_vals(path, self)
     }
+
+    /// Queries the value with a JSONPath expression and returns copies of the matches with their paths,
+    /// so the results outlive the value.
+    fn query_owned(&self, path: &str) -> Queried<Vec<(QueryPath, Self)>> {
+        Ok(query::js_path(path, self)?
+            .into_iter()
+            .map(QueryRef::into_owned)
+            .collect())
+    }
+
+    /// Queries the value with a JSONPath expression and deserializes every match into `D`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JsonPathError::Deserialization`] with the path of the first match that fails to deserialize.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonpath_rust::JsonPath;
+    /// use serde_json::json;
+    ///
+    /// let data = json!({"items": [{"qty": 1}, {"qty": 2}]});
+    /// let qty: Vec<u32> = data.query_as("$.items[*].qty").unwrap();
+    /// assert_eq!(qty, vec![1, 2]);
+    /// ```
+    fn query_as<D>(&self, path: &str) -> Queried<Vec<D>>
+    where
+        D: DeserializeOwned,
+        for<'a> &'a Self: Deserializer<'a>,
+    {
+        query::js_path(path, self)?
+            .into_iter()
+            .map(deserialize)
+            .collect()
+    }
+
+    /// Queries the value with a JSONPath expression that has to select exactly one node
+    /// and deserializes it into `D`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`JsonPath::query_one`],
+    /// and [`JsonPathError::Deserialization`] if the node fails to deserialize.
+    fn query_one_as<D>(&self, path: &str) -> Queried<D>
+    where
+        D: DeserializeOwned,
+        for<'a> &'a Self: Deserializer<'a>,
+    {
+        single(query::js_path_iter(path, self)?)?
+            .ok_or(JsonPathError::NoMatch)
+            .and_then(deserialize)
+    }
+
+    /// Queries the value with a JSONPath expression that has to select exactly one node.
+    /// The evaluation goes past the first match only to find whether there is a second one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JsonPathError::NoMatch`] if the query selects nothing
+    /// and [`JsonPathError::MultipleMatches`] if it selects more than one node.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonpath_rust::JsonPath;
+    /// use serde_json::json;
+    ///
+    /// let data = json!({"users": [{"id": 1}, {"id": 2}]});
+    /// assert_eq!(data.query_one("$.users[?@.id == 2].id").unwrap(), &json!(2));
+    /// assert!(data.query_one("$.users[*].id").is_err());
+    /// ```
+    fn query_one(&self, path: &str) -> Queried<&Self> {
+        single(query::js_path_iter(path, self)?)?
+            .map(|found| found.val)
+            .ok_or(JsonPathError::NoMatch)
+    }
+
+    /// Queries the value with a JSONPath expression that has to select at most one node.
+    /// The evaluation goes past the first match only to find whether there is a second one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JsonPathError::MultipleMatches`] if the query selects more than one node.
+    fn query_opt(&self, path: &str) -> Queried<Option<&Self>> {
+        Ok(single(query::js_path_iter(path, self)?)?.map(|found| found.val))
+    }
+
+    /// Checks whether the JSONPath expression selects anything.
+    /// The evaluation stops at the first match.
+    fn exists(&self, path: &str) -> Queried<bool> {
+        Ok(query::js_path_iter(path, self)?.next().is_some())
+    }
+
+    /// Queries the value with a JSONPath expression and returns a lazy iterator over the matches.
+    fn query_iter(&self, path: &str) -> Queried<QueryIter<'_, 'static, Self>> {
+        query::js_path_iter(path, self)
+    }
 }
 
 impl JsonPath for Value {}
+
+/// Takes the only match, the evaluation stops at the second one if there is any.
+pub(crate) fn single<'a, T: Queryable>(
+    mut matches: impl Iterator<Item = QueryRef<'a, T>>,
+) -> Queried<Option<QueryRef<'a, T>>> {
+    match (matches.next(), matches.next()) {
+        (Some(_), Some(_)) => Err(JsonPathError::MultipleMatches { at_least: 2 }),
+        (found, _) => Ok(found),
+    }
+}
+
+/// Deserializes the node reporting the failure with its path.
+fn deserialize<'a, T, D>(found: QueryRef<'a, T>) -> Queried<D>
+where
+    T: Queryable,
+    D: DeserializeOwned,
+    &'a T: Deserializer<'a>,
+{
+    D::deserialize(found.val).map_err(|e| JsonPathError::Deserialization {
+        path: found.path,
+        message: e.to_string(),
+    })
+}
@@ -1,14 +1,24 @@
 #![allow(clippy::empty_docs)]
+pub mod diagnostics;
 pub mod errors;
+pub mod functions;
 mod macros;
 pub mod model;
+pub mod options;
+pub mod spans;
 mod tests;
+pub mod typing;
 
 use crate::parser::errors::JsonPathError;
+use crate::parser::functions::{BuiltinFunctions, FunctionSignatures};
 use crate::parser::model::{
     Comparable, Comparison, Filter, FilterAtom, FnArg, JpQuery, Literal, Segment, Selector,
     SingularQuery, SingularQuerySegment, Test, TestFunction,
 };
+use crate::parser::options::{Measure, ParserOptions};
+use crate::parser::spans::{NodeKind, SpanRecorder, SpannedQuery};
+use crate::parser::typing::type_check;
+use crate::query::regexes::{compile_literals, Regexes};
 
 use pest::iterators::Pair;
 use pest::Parser;
@@ -21,39 +31,140 @@ pub(super) struct JSPathParser;
 
 pub type Parsed<T> = Result<T, JsonPathError>;
 
-/// The maximum nesting depth of parentheses and brackets accepted in a query.
-///
-/// Parsing is recursive (both in the underlying PEG parser and in the AST
-/// construction that follows), so a query that nests grouping constructs
-/// unboundedly would recurse until the stack overflows and the process aborts.
-/// Real-world queries nest only a handful of levels, so this limit is generous
-/// while still keeping stack usage bounded.
-const MAX_NESTING_DEPTH: usize = 128;
-
 /// Parses a string into a [JsonPath].
 ///
 /// # Errors
 ///
 /// Returns a variant of [crate::JsonPathParserError] if the parsing operation failed.
 pub fn parse_json_path(jp_str: &str) -> Parsed<JpQuery> {
-    check_nesting_depth(jp_str)?;
+    parse_json_path_with_functions(jp_str, &BuiltinFunctions)
+}
+
+/// Parses a string into a [JsonPath] accepting the function extensions known to `functions`
+/// besides the builtin ones.
+///
+/// Every function call is checked against the signature of the function,
+/// see [`typing::type_check`] for the rules.
+///
+/// # Errors
+///
+/// Returns the same errors as [`parse_json_path`].
+pub fn parse_json_path_with_functions(
+    jp_str: &str,
+    functions: &dyn FunctionSignatures,
+) -> Parsed<JpQuery> {
+    parse_json_path_with_options(jp_str, functions, &ParserOptions::default())
+}
+
+/// Parses a string into a [JsonPath] within the limits of the options.
+///
+/// # Errors
+///
+/// Returns the same errors as [`parse_json_path`]
+/// and [`JsonPathError::QueryLimitExceeded`] if the query exceeds a limit of the options.
+pub fn parse_json_path_with(jp_str: &str, options: &ParserOptions) -> Parsed<JpQuery> {
+    parse_json_path_with_options(jp_str, &BuiltinFunctions, options)
+}
+
+pub(crate) fn parse_json_path_with_options(
+    jp_str: &str,
+    functions: &dyn FunctionSignatures,
+    options: &ParserOptions,
+) -> Parsed<JpQuery> {
+    parse_compiled(jp_str, functions, options).map(|(query, _)| query)
+}
+
+/// Parses the query along with the regular expressions of its `match` and `search` calls
+/// compiled in the mode of the options, for the queries evaluated many times.
+pub(crate) fn parse_compiled(
+    jp_str: &str,
+    functions: &dyn FunctionSignatures,
+    options: &ParserOptions,
+) -> Parsed<(JpQuery, Regexes)> {
+    let query = Walk::new(options).jp_query(parse_main(jp_str, options)?)?;
+    let regexes = check_query(&query, functions, options)?;
+    Ok((query, regexes))
+}
+
+/// Parses a string into a [JsonPath] and records the position of every node of it.
+///
+/// The returned [`spans::SpanNode`] tree mirrors the query model,
+/// so a node of the model can be matched with the part of the string it was parsed from.
+///
+/// # Errors
+///
+/// Returns the same errors as [`parse_json_path`].
+pub fn parse_json_path_with_spans(jp_str: &str) -> Parsed<SpannedQuery> {
+    parse_json_path_with_spans_and(jp_str, &ParserOptions::default(), &BuiltinFunctions)
+}
+
+/// Parses a string into a [JsonPath] recording the position of every node of it,
+/// within the limits of the options and accepting the function extensions known to `functions`.
+///
+/// # Errors
+///
+/// Returns the same errors as [`parse_json_path_with`] and [`parse_json_path_with_functions`].
+pub fn parse_json_path_with_spans_and(
+    jp_str: &str,
+    options: &ParserOptions,
+    functions: &dyn FunctionSignatures,
+) -> Parsed<SpannedQuery> {
+    let mut walk = Walk::recording(options);
+    let query = walk.jp_query(parse_main(jp_str, options)?)?;
+    check_query(&query, functions, options)?;
+    let spans = walk
+        .spans
+        .and_then(SpanRecorder::finish)
+        .ok_or(JsonPathError::UnexpectedPestOutput)?;
+    Ok(SpannedQuery { query, spans })
+}
+
+/// Checks the limits known before parsing and parses the string into the query rule.
+fn parse_main<'i>(jp_str: &'i str, options: &ParserOptions) -> Parsed<Pair<'i, Rule>> {
+    options.check_input(jp_str)?;
+    check_nesting_depth(jp_str, options.max_nesting_depth)?;
     JSPathParser::parse(Rule::main, jp_str)
         .map_err(Box::new)?
         .next()
         .ok_or(JsonPathError::UnexpectedPestOutput)
         .and_then(next_down)
-        .and_then(jp_query)
+}
+
+/// Checks the parsed query against the function signatures
+/// and compiles the regular expressions of `match` and `search`, they have to be valid.
+fn check_query(
+    query: &JpQuery,
+    functions: &dyn FunctionSignatures,
+    options: &ParserOptions,
+) -> Parsed<Regexes> {
+    type_check(query, functions)?;
+    compile_literals(query, options.regex_mode, options.regex_cache)
+}
+
+/// Parses a singular query (RFC 9535, section 2.3.5.1), like `$.a[1]` or `@['b']`:
+/// a query made of name and index segments only, selecting at most one node.
+///
+/// # Errors
+///
+/// Returns a variant of [crate::JsonPathParserError] if the string is not a singular query.
+pub fn parse_singular_query(jp_str: &str) -> Parsed<SingularQuery> {
+    JSPathParser::parse(Rule::singular_main, jp_str)
+        .map_err(Box::new)?
+        .next()
+        .ok_or(JsonPathError::UnexpectedPestOutput)
+        .and_then(next_down)
+        .and_then(singular_query)
 }
 
 /// Rejects queries whose parentheses/brackets nest deeper than
-/// [`MAX_NESTING_DEPTH`] before they reach the recursive parser.
+/// `max_depth` (see [`options::DEFAULT_MAX_NESTING_DEPTH`]) before they reach the recursive parser.
 ///
 /// Grouping (`(...)` in filters and `[...]` selections/filters) is the only
 /// source of parser recursion, so scanning the raw input for the deepest run of
 /// still-open `(`/`[` bounds the recursion depth without first having to build
 /// the parse tree. Brackets and parentheses that appear inside a string literal
 /// are data rather than grouping tokens, so string contents are skipped.
-fn check_nesting_depth(jp_str: &str) -> Parsed<()> {
+fn check_nesting_depth(jp_str: &str, max_depth: usize) -> Parsed<()> {
     let mut depth: usize = 0;
     let mut string_delim: Option<char> = None;
     let mut escaped = false;
@@ -74,8 +185,8 @@ fn check_nesting_depth(jp_str: &str) -> Parsed<()> {
             '\'' | '"' => string_delim = Some(c),
             '(' | '[' => {
                 depth += 1;
-                if depth > MAX_NESTING_DEPTH {
-                    return Err(JsonPathError::MaxNestingDepthExceeded(MAX_NESTING_DEPTH));
+                if depth > max_depth {
+                    return Err(JsonPathError::MaxNestingDepthExceeded(max_depth));
                 }
             }
             ')' | ']' => depth = depth.saturating_sub(1),
@@ -87,204 +198,500 @@ fn check_nesting_depth(jp_str: &str) -> Parsed<()> {
 }
 
 pub fn jp_query(rule: Pair<Rule>) -> Parsed<JpQuery> {
-    Ok(JpQuery::new(segments(next_down(rule)?)?))
+    Walk::default().jp_query(rule)
 }
 pub fn rel_query(rule: Pair<Rule>) -> Parsed<Vec<Segment>> {
-    segments(next_down(rule)?)
+    Walk::default().rel_query(rule)
 }
 
 pub fn segments(rule: Pair<Rule>) -> Parsed<Vec<Segment>> {
-    let mut segments = vec![];
-    for r in rule.into_inner() {
-        segments.push(segment(next_down(r)?)?);
-    }
-    Ok(segments)
+    Walk::default().segments(rule)
 }
 
 pub fn child_segment(rule: Pair<Rule>) -> Parsed<Segment> {
-    match rule.as_rule() {
-        Rule::wildcard_selector => Ok(Segment::Selector(Selector::Wildcard)),
-        Rule::member_name_shorthand => Ok(Segment::name(rule.as_str().trim())),
-        Rule::bracketed_selection => {
-            let mut selectors = vec![];
-            for r in rule.into_inner() {
-                selectors.push(selector(r)?);
+    Walk::default().child_segment(rule)
+}
+
+pub fn segment(child: Pair<Rule>) -> Parsed<Segment> {
+    Walk::default().segment(child)
+}
+
+pub fn selector(rule: Pair<Rule>) -> Parsed<Selector> {
+    Walk::default().selector(rule)
+}
+
+pub fn function_expr(rule: Pair<Rule>) -> Parsed<TestFunction> {
+    Walk::default().function_expr(rule)
+}
+
+pub fn test(rule: Pair<Rule>) -> Parsed<Test> {
+    Walk::default().test(rule)
+}
+
+pub fn logical_expr(rule: Pair<Rule>) -> Parsed<Filter> {
+    Walk::default().logical_expr(rule)
+}
+
+pub fn logical_expr_and(rule: Pair<Rule>) -> Parsed<Filter> {
+    Walk::default().logical_expr_and(rule)
+}
+
+pub fn singular_query_segments(rule: Pair<Rule>) -> Parsed<Vec<SingularQuerySegment>> {
+    Walk::default().singular_query_segments(rule)
+}
+
+pub fn singular_query(rule: Pair<Rule>) -> Parsed<SingularQuery> {
+    Walk::default().singular_query(rule)
+}
+
+pub fn comp_expr(rule: Pair<Rule>) -> Parsed<Comparison> {
+    Walk::default().comp_expr(rule)
+}
+
+pub fn literal(rule: Pair<Rule>) -> Parsed<Literal> {
+    Walk::default().literal(rule)
+}
+
+pub fn filter_atom(pair: Pair<Rule>) -> Parsed<FilterAtom> {
+    Walk::default().filter_atom(pair)
+}
+
+pub fn comparable(rule: Pair<Rule>) -> Parsed<Comparable> {
+    Walk::default().comparable(rule)
+}
+
+/// The walk over the parse tree building the query,
+/// it checks the limits of the options as the query grows
+/// and records the position of every node it builds if it is asked to.
+#[derive(Default)]
+struct Walk {
+    measure: Measure,
+    spans: Option<SpanRecorder>,
+}
+
+impl Walk {
+    fn new(options: &ParserOptions) -> Self {
+        Walk {
+            measure: options.measure(),
+            spans: None,
+        }
+    }
+
+    fn recording(options: &ParserOptions) -> Self {
+        Walk {
+            spans: Some(SpanRecorder::default()),
+            ..Walk::new(options)
+        }
+    }
+
+    fn open(&mut self, kind: NodeKind, rule: &Pair<Rule>) {
+        if let Some(spans) = &mut self.spans {
+            spans.open(kind, rule)
+        }
+    }
+
+    fn close(&mut self, collapse: bool) {
+        if let Some(spans) = &mut self.spans {
+            spans.close(collapse)
+        }
+    }
+
+    fn leaf(&mut self, kind: NodeKind, rule: &Pair<Rule>) {
+        self.open(kind, rule);
+        self.close(false)
+    }
+
+    /// Counts a test standing for a whole filter, a bare test is counted by its parent
+    /// since it is not a filter when it is the argument of a function.
+    fn count_test(&mut self, filter: &Filter) -> Parsed<()> {
+        if bare_test(filter) {
+            self.measure.filter_nodes(1)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn jp_query(&mut self, rule: Pair<Rule>) -> Parsed<JpQuery> {
+        self.open(NodeKind::Query, &rule);
+        let query = JpQuery::new(self.segments(next_down(rule)?)?);
+        self.close(false);
+        Ok(query)
+    }
+
+    fn rel_query(&mut self, rule: Pair<Rule>) -> Parsed<Vec<Segment>> {
+        self.segments(next_down(rule)?)
+    }
+
+    fn segments(&mut self, rule: Pair<Rule>) -> Parsed<Vec<Segment>> {
+        let mut segments = vec![];
+        for r in rule.into_inner() {
+            self.measure.segment()?;
+            segments.push(self.segment(next_down(r)?)?);
+        }
+        Ok(segments)
+    }
+
+    fn child_segment(&mut self, rule: Pair<Rule>) -> Parsed<Segment> {
+        match rule.as_rule() {
+            Rule::wildcard_selector => {
+                self.leaf(NodeKind::Selector, &rule);
+                Ok(Segment::Selector(Selector::Wildcard))
             }
-            if selectors.len() == 1 {
-                Ok(Segment::Selector(
-                    selectors
-                        .into_iter()
-                        .next()
-                        .ok_or(JsonPathError::empty("selector"))?,
-                ))
-            } else {
-                Ok(Segment::Selectors(selectors))
+            Rule::member_name_shorthand => {
+                self.leaf(NodeKind::Selector, &rule);
+                Ok(Segment::name(rule.as_str().trim()))
             }
+            Rule::bracketed_selection => {
+                let mut selectors = vec![];
+                for r in rule.into_inner() {
+                    selectors.push(self.selector(r)?);
+                }
+                if selectors.len() == 1 {
+                    Ok(Segment::Selector(
+                        selectors
+                            .into_iter()
+                            .next()
+                            .ok_or(JsonPathError::empty("selector"))?,
+                    ))
+                } else {
+                    Ok(Segment::Selectors(selectors))
+                }
+            }
+            _ => Err(rule.into()),
         }
-        _ => Err(rule.into()),
     }
-}
 
-pub fn segment(child: Pair<Rule>) -> Parsed<Segment> {
-    match child.as_rule() {
-        Rule::child_segment => {
-            let val = child.as_str().strip_prefix(".").unwrap_or_default();
-            if val != val.trim_start() {
-                Err(JsonPathError::InvalidJsonPath(format!(
-                    "Invalid child segment `{}`",
-                    child.as_str()
-                )))
-            } else {
-                child_segment(next_down(child)?)
+    fn segment(&mut self, child: Pair<Rule>) -> Parsed<Segment> {
+        self.open(NodeKind::Segment, &child);
+        let segment = match child.as_rule() {
+            Rule::child_segment => {
+                let val = child.as_str().strip_prefix(".").unwrap_or_default();
+                if val != val.trim_start() {
+                    Err(JsonPathError::InvalidJsonPath(format!(
+                        "Invalid child segment `{}`",
+                        child.as_str()
+                    )))
+                } else {
+                    self.child_segment(next_down(child)?)
+                }
             }
-        }
-        Rule::descendant_segment => {
-            if child
-                .as_str()
-                .chars()
-                .nth(2)
-                .ok_or(JsonPathError::empty(child.as_str()))?
-                .is_whitespace()
-            {
-                Err(JsonPathError::InvalidJsonPath(format!(
-                    "Invalid descendant segment `{}`",
-                    child.as_str()
-                )))
-            } else {
-                Ok(Segment::Descendant(Box::new(child_segment(next_down(
-                    child,
-                )?)?)))
+            Rule::descendant_segment => {
+                if child
+                    .as_str()
+                    .chars()
+                    .nth(2)
+                    .ok_or(JsonPathError::empty(child.as_str()))?
+                    .is_whitespace()
+                {
+                    Err(JsonPathError::InvalidJsonPath(format!(
+                        "Invalid descendant segment `{}`",
+                        child.as_str()
+                    )))
+                } else {
+                    let inner = next_down(child)?;
+                    self.open(NodeKind::Segment, &inner);
+                    let segment = self.child_segment(inner)?;
+                    self.close(false);
+                    Ok(Segment::Descendant(Box::new(segment)))
+                }
             }
-        }
-        _ => Err(child.into()),
+            _ => Err(child.into()),
+        }?;
+        self.close(false);
+        Ok(segment)
     }
-}
 
-pub fn selector(rule: Pair<Rule>) -> Parsed<Selector> {
-    let child = next_down(rule)?;
-    match child.as_rule() {
-        Rule::name_selector => Ok(Selector::Name(unquote_and_unescape(validate_js_str(
-            child.as_str().trim(),
-        )?)?)),
-        Rule::wildcard_selector => Ok(Selector::Wildcard),
-        Rule::index_selector => Ok(Selector::Index(
-            child
-                .as_str()
-                .trim()
-                .parse::<i64>()
-                .map_err(|e| (e, "wrong integer"))?,
-        )),
-        Rule::slice_selector => {
-            let (start, end, step) = slice_selector(child)?;
-            Ok(Selector::Slice(start, end, step))
-        }
-        Rule::filter_selector => Ok(Selector::Filter(logical_expr(next_down(child)?)?)),
-        _ => Err(child.into()),
+    fn selector(&mut self, rule: Pair<Rule>) -> Parsed<Selector> {
+        self.open(NodeKind::Selector, &rule);
+        let child = next_down(rule)?;
+        let selector = match child.as_rule() {
+            Rule::name_selector => Selector::Name(unquote_and_unescape(validate_js_str(
+                child.as_str().trim(),
+            )?)?),
+            Rule::wildcard_selector => Selector::Wildcard,
+            Rule::index_selector => Selector::Index(
+                child
+                    .as_str()
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|e| (e, "wrong integer"))?,
+            ),
+            Rule::slice_selector => {
+                let (start, end, step) = slice_selector(child)?;
+                Selector::Slice(start, end, step)
+            }
+            Rule::filter_selector => {
+                let filter = self.logical_expr(next_down(child)?)?;
+                self.count_test(&filter)?;
+                Selector::Filter(filter)
+            }
+            _ => return Err(child.into()),
+        };
+        self.close(false);
+        Ok(selector)
     }
-}
 
-pub fn function_expr(rule: Pair<Rule>) -> Parsed<TestFunction> {
-    let fn_str = rule.as_str();
-    let mut elems = rule.into_inner();
-    let name = elems
-        .next()
-        .map(|e| e.as_str())
-        .ok_or(JsonPathError::empty("function expression"))?;
-
-    // Check if the function name is valid namely nothing between the name and the opening parenthesis
-    if fn_str
-        .chars()
-        .nth(name.len())
-        .map(|c| c != '(')
-        .unwrap_or_default()
-    {
-        Err(JsonPathError::InvalidJsonPath(format!(
-            "Invalid function expression `{}`",
-            fn_str
-        )))
-    } else {
-        let mut args = vec![];
-        for arg in elems {
-            let next = next_down(arg)?;
-            match next.as_rule() {
-                Rule::literal => args.push(FnArg::Literal(literal(next)?)),
-                Rule::test => args.push(FnArg::Test(Box::new(test(next)?))),
-                Rule::logical_expr => args.push(FnArg::Filter(logical_expr(next)?)),
-
-                _ => return Err(next.into()),
+    fn function_expr(&mut self, rule: Pair<Rule>) -> Parsed<TestFunction> {
+        self.open(NodeKind::Function, &rule);
+        self.measure.enter_function()?;
+        let fn_str = rule.as_str();
+        let mut elems = rule.into_inner();
+        let name = elems
+            .next()
+            .map(|e| e.as_str())
+            .ok_or(JsonPathError::empty("function expression"))?;
+
+        // Check if the function name is valid namely nothing between the name and the opening parenthesis
+        if fn_str
+            .chars()
+            .nth(name.len())
+            .map(|c| c != '(')
+            .unwrap_or_default()
+        {
+            Err(JsonPathError::InvalidJsonPath(format!(
+                "Invalid function expression `{}`",
+                fn_str
+            )))
+        } else {
+            let mut args = vec![];
+            for arg in elems {
+                self.open(NodeKind::FnArg, &arg);
+                let next = next_down(arg)?;
+                match next.as_rule() {
+                    Rule::literal => args.push(FnArg::Literal(self.literal(next)?)),
+                    // a bare query or function call is kept apart from the logical expressions,
+                    // since it can stand for a value or nodes depending on the parameter
+                    Rule::logical_expr => args.push(match self.logical_expr(next)? {
+                        Filter::Atom(FilterAtom::Test { expr, not: false }) => {
+                            // the span of the test takes the place of the filter and the atom
+                            if let Some(spans) = &mut self.spans {
+                                spans.lift(2)
+                            }
+                            FnArg::Test(expr)
+                        }
+                        filter => FnArg::Filter(filter),
+                    }),
+
+                    _ => return Err(next.into()),
+                }
+                self.close(false);
             }
+            self.close(false);
+            self.measure.leave_function();
+
+            TestFunction::try_new(name, args)
         }
+    }
 
-        TestFunction::try_new(name, args)
+    fn test(&mut self, rule: Pair<Rule>) -> Parsed<Test> {
+        self.open(NodeKind::Test, &rule);
+        let child = next_down(rule)?;
+        let test = match child.as_rule() {
+            Rule::jp_query => Test::AbsQuery(self.jp_query(child)?),
+            Rule::rel_query => Test::RelQuery(self.rel_query(child)?),
+            Rule::function_expr => Test::Function(Box::new(self.function_expr(child)?)),
+            _ => return Err(child.into()),
+        };
+        self.close(false);
+        Ok(test)
     }
-}
 
-pub fn test(rule: Pair<Rule>) -> Parsed<Test> {
-    let child = next_down(rule)?;
-    match child.as_rule() {
-        Rule::jp_query => Ok(Test::AbsQuery(jp_query(child)?)),
-        Rule::rel_query => Ok(Test::RelQuery(rel_query(child)?)),
-        Rule::function_expr => Ok(Test::Function(Box::new(function_expr(child)?))),
-        _ => Err(child.into()),
+    fn logical_expr(&mut self, rule: Pair<Rule>) -> Parsed<Filter> {
+        self.open(NodeKind::Filter, &rule);
+        let mut ors = vec![];
+        for r in rule.into_inner() {
+            ors.push(self.logical_expr_and(r)?);
+        }
+        if ors.len() > 1 {
+            let tests = ors.iter().filter(|f| bare_test(f)).count();
+            self.measure.filter_nodes(tests + 1)?;
+        }
+        self.close(ors.len() == 1);
+        if ors.len() == 1 {
+            Ok(ors
+                .into_iter()
+                .next()
+                .ok_or(JsonPathError::empty("logical expression"))?)
+        } else {
+            Ok(Filter::Or(ors))
+        }
     }
-}
 
-pub fn logical_expr(rule: Pair<Rule>) -> Parsed<Filter> {
-    let mut ors = vec![];
-    for r in rule.into_inner() {
-        ors.push(logical_expr_and(r)?);
+    fn logical_expr_and(&mut self, rule: Pair<Rule>) -> Parsed<Filter> {
+        self.open(NodeKind::Filter, &rule);
+        let mut ands = vec![];
+        for r in rule.into_inner() {
+            self.open(NodeKind::Filter, &r);
+            let atom = Filter::Atom(self.filter_atom(r)?);
+            self.close(false);
+            if !bare_test(&atom) {
+                self.measure.filter_nodes(1)?;
+            }
+            ands.push(atom);
+        }
+        if ands.len() > 1 {
+            let tests = ands.iter().filter(|f| bare_test(f)).count();
+            self.measure.filter_nodes(tests + 1)?;
+        }
+        self.close(ands.len() == 1);
+        if ands.len() == 1 {
+            Ok(ands
+                .into_iter()
+                .next()
+                .ok_or(JsonPathError::empty("logical expression"))?)
+        } else {
+            Ok(Filter::And(ands))
+        }
     }
-    if ors.len() == 1 {
-        Ok(ors
-            .into_iter()
-            .next()
-            .ok_or(JsonPathError::empty("logical expression"))?)
-    } else {
-        Ok(Filter::Or(ors))
+
+    fn singular_query_segments(&mut self, rule: Pair<Rule>) -> Parsed<Vec<SingularQuerySegment>> {
+        let mut segments = vec![];
+        for r in rule.into_inner() {
+            self.measure.segment()?;
+            self.leaf(NodeKind::SingularQuerySegment, &r);
+            match r.as_rule() {
+                Rule::name_segment => {
+                    let name = next_down(r)?;
+                    segments.push(SingularQuerySegment::Name(match name.as_rule() {
+                        Rule::name_selector => {
+                            unquote_and_unescape(validate_js_str(name.as_str().trim())?)?
+                        }
+                        _ => name.as_str().trim().to_string(),
+                    }));
+                }
+                Rule::index_segment => {
+                    segments.push(SingularQuerySegment::Index(
+                        next_down(r)?
+                            .as_str()
+                            .trim()
+                            .parse::<i64>()
+                            .map_err(|e| (e, "int"))?,
+                    ));
+                }
+                _ => return Err(r.into()),
+            }
+        }
+        Ok(segments)
     }
-}
 
-pub fn logical_expr_and(rule: Pair<Rule>) -> Parsed<Filter> {
-    let mut ands = vec![];
-    for r in rule.into_inner() {
-        ands.push(Filter::Atom(filter_atom(r)?));
+    fn singular_query(&mut self, rule: Pair<Rule>) -> Parsed<SingularQuery> {
+        self.open(NodeKind::SingularQuery, &rule);
+        let query = next_down(rule)?;
+        let segments = self.singular_query_segments(next_down(query.clone())?)?;
+        self.close(false);
+        match query.as_rule() {
+            Rule::rel_singular_query => Ok(SingularQuery::Current(segments)),
+            Rule::abs_singular_query => Ok(SingularQuery::Root(segments)),
+            _ => Err(query.into()),
+        }
     }
-    if ands.len() == 1 {
-        Ok(ands
-            .into_iter()
+
+    fn comp_expr(&mut self, rule: Pair<Rule>) -> Parsed<Comparison> {
+        self.open(NodeKind::Comparison, &rule);
+        let mut children = rule.into_inner();
+
+        let lhs = self.comparable(children.next().ok_or(JsonPathError::empty("comparison"))?)?;
+        let op = children
             .next()
-            .ok_or(JsonPathError::empty("logical expression"))?)
-    } else {
-        Ok(Filter::And(ands))
+            .ok_or(JsonPathError::empty("comparison"))?
+            .as_str();
+        let rhs = self.comparable(children.next().ok_or(JsonPathError::empty("comparison"))?)?;
+        self.close(false);
+
+        Comparison::try_new(op, lhs, rhs)
     }
-}
 
-pub fn singular_query_segments(rule: Pair<Rule>) -> Parsed<Vec<SingularQuerySegment>> {
-    let mut segments = vec![];
-    for r in rule.into_inner() {
-        match r.as_rule() {
-            Rule::name_segment => {
-                let name = next_down(r)?;
-                segments.push(SingularQuerySegment::Name(match name.as_rule() {
-                    Rule::name_selector => {
-                        unquote_and_unescape(validate_js_str(name.as_str().trim())?)?
+    fn literal(&mut self, rule: Pair<Rule>) -> Parsed<Literal> {
+        fn parse_number(num: &str) -> Parsed<Literal> {
+            let num = num.trim();
+
+            if num.contains('.') || num.contains('e') || num.contains('E') {
+                Ok(Literal::Float(num.parse::<f64>().map_err(|e| (e, num))?))
+            } else {
+                Ok(Literal::Int(
+                    num.trim().parse::<i64>().map_err(|e| (e, num))?,
+                ))
+            }
+        }
+
+        fn parse_string(string: &str) -> Parsed<Literal> {
+            Ok(Literal::String(unquote_and_unescape(validate_js_str(
+                string.trim(),
+            )?)?))
+        }
+
+        self.leaf(NodeKind::Literal, &rule);
+        let first = next_down(rule)?;
+
+        match first.as_rule() {
+            Rule::string => parse_string(first.as_str()),
+            Rule::number => parse_number(first.as_str()),
+            Rule::bool => Ok(Literal::Bool(first.as_str().parse::<bool>()?)),
+            Rule::null => Ok(Literal::Null),
+
+            _ => Err(first.into()),
+        }
+    }
+
+    fn filter_atom(&mut self, pair: Pair<Rule>) -> Parsed<FilterAtom> {
+        self.open(NodeKind::FilterAtom, &pair);
+        let rule = next_down(pair)?;
+
+        let atom: Parsed<FilterAtom> = match rule.as_rule() {
+            Rule::paren_expr => {
+                let mut not = false;
+                let mut logic_expr = None;
+                for r in rule.into_inner() {
+                    match r.as_rule() {
+                        Rule::not_op => not = true,
+                        Rule::logical_expr => {
+                            let filter = self.logical_expr(r)?;
+                            self.count_test(&filter)?;
+                            logic_expr = Some(filter)
+                        }
+                        _ => (),
                     }
-                    _ => name.as_str().trim().to_string(),
-                }));
+                }
+
+                logic_expr
+                    .map(|expr| FilterAtom::filter(expr, not))
+                    .ok_or("Logical expression is absent".into())
             }
-            Rule::index_segment => {
-                segments.push(SingularQuerySegment::Index(
-                    next_down(r)?
-                        .as_str()
-                        .trim()
-                        .parse::<i64>()
-                        .map_err(|e| (e, "int"))?,
-                ));
+            Rule::comp_expr => Ok(FilterAtom::cmp(Box::new(self.comp_expr(rule)?))),
+            Rule::test_expr => {
+                let mut not = false;
+                let mut test_expr = None;
+                for r in rule.into_inner() {
+                    match r.as_rule() {
+                        Rule::not_op => not = true,
+                        Rule::test => test_expr = Some(self.test(r)?),
+                        _ => (),
+                    }
+                }
+
+                test_expr
+                    .map(|expr| FilterAtom::test(expr, not))
+                    .ok_or("Logical expression is absent".into())
             }
-            _ => return Err(r.into()),
-        }
+            _ => Err(rule.into()),
+        };
+        self.close(false);
+        atom
+    }
+
+    fn comparable(&mut self, rule: Pair<Rule>) -> Parsed<Comparable> {
+        self.open(NodeKind::Comparable, &rule);
+        let rule = next_down(rule)?;
+        let comparable = match rule.as_rule() {
+            Rule::literal => Comparable::Literal(self.literal(rule)?),
+            Rule::singular_query => Comparable::SingularQuery(self.singular_query(rule)?),
+            // whether the function returns a comparable value is checked against its signature
+            Rule::function_expr => Comparable::Function(self.function_expr(rule)?),
+            _ => return Err(rule.into()),
+        };
+        self.close(false);
+        Ok(comparable)
     }
-    Ok(segments)
 }
 
 pub fn slice_selector(rule: Pair<Rule>) -> Parsed<(Option<i64>, Option<i64>, Option<i64>)> {
@@ -313,29 +720,6 @@ pub fn slice_selector(rule: Pair<Rule>) -> Parsed<(Option<i64>, Option<i64>, Opt
     Ok((start, end, step))
 }
 
-pub fn singular_query(rule: Pair<Rule>) -> Parsed<SingularQuery> {
-    let query = next_down(rule)?;
-    let segments = singular_query_segments(next_down(query.clone())?)?;
-    match query.as_rule() {
-        Rule::rel_singular_query => Ok(SingularQuery::Current(segments)),
-        Rule::abs_singular_query => Ok(SingularQuery::Root(segments)),
-        _ => Err(query.into()),
-    }
-}
-
-pub fn comp_expr(rule: Pair<Rule>) -> Parsed<Comparison> {
-    let mut children = rule.into_inner();
-
-    let lhs = comparable(children.next().ok_or(JsonPathError::empty("comparison"))?)?;
-    let op = children
-        .next()
-        .ok_or(JsonPathError::empty("comparison"))?
-        .as_str();
-    let rhs = comparable(children.next().ok_or(JsonPathError::empty("comparison"))?)?;
-
-    Comparison::try_new(op, lhs, rhs)
-}
-
 /// Validates a JSONPath string literal according to RFC 9535
 /// Control characters (U+0000 through U+001F and U+007F) are not allowed unescaped
 /// in string literals, whether single-quoted or double-quoted
@@ -423,94 +807,9 @@ fn unescape_unicode(chars: &mut std::str::Chars) -> Option<char> {
     }
 }
 
-pub fn literal(rule: Pair<Rule>) -> Parsed<Literal> {
-    fn parse_number(num: &str) -> Parsed<Literal> {
-        let num = num.trim();
-
-        if num.contains('.') || num.contains('e') || num.contains('E') {
-            Ok(Literal::Float(num.parse::<f64>().map_err(|e| (e, num))?))
-        } else {
-            Ok(Literal::Int(
-                num.trim().parse::<i64>().map_err(|e| (e, num))?,
-            ))
-        }
-    }
-
-    fn parse_string(string: &str) -> Parsed<Literal> {
-        Ok(Literal::String(unquote_and_unescape(validate_js_str(
-            string.trim(),
-        )?)?))
-    }
-
-    let first = next_down(rule)?;
-
-    match first.as_rule() {
-        Rule::string => parse_string(first.as_str()),
-        Rule::number => parse_number(first.as_str()),
-        Rule::bool => Ok(Literal::Bool(first.as_str().parse::<bool>()?)),
-        Rule::null => Ok(Literal::Null),
-
-        _ => Err(first.into()),
-    }
-}
-
-pub fn filter_atom(pair: Pair<Rule>) -> Parsed<FilterAtom> {
-    let rule = next_down(pair)?;
-
-    match rule.as_rule() {
-        Rule::paren_expr => {
-            let mut not = false;
-            let mut logic_expr = None;
-            for r in rule.into_inner() {
-                match r.as_rule() {
-                    Rule::not_op => not = true,
-                    Rule::logical_expr => logic_expr = Some(logical_expr(r)?),
-                    _ => (),
-                }
-            }
-
-            logic_expr
-                .map(|expr| FilterAtom::filter(expr, not))
-                .ok_or("Logical expression is absent".into())
-        }
-        Rule::comp_expr => Ok(FilterAtom::cmp(Box::new(comp_expr(rule)?))),
-        Rule::test_expr => {
-            let mut not = false;
-            let mut test_expr = None;
-            for r in rule.into_inner() {
-                match r.as_rule() {
-                    Rule::not_op => not = true,
-                    Rule::test => test_expr = Some(test(r)?),
-                    _ => (),
-                }
-            }
-
-            test_expr
-                .map(|expr| FilterAtom::test(expr, not))
-                .ok_or("Logical expression is absent".into())
-        }
-        _ => Err(rule.into()),
-    }
-}
-
-pub fn comparable(rule: Pair<Rule>) -> Parsed<Comparable> {
-    let rule = next_down(rule)?;
-    match rule.as_rule() {
-        Rule::literal => Ok(Comparable::Literal(literal(rule)?)),
-        Rule::singular_query => Ok(Comparable::SingularQuery(singular_query(rule)?)),
-        Rule::function_expr => {
-            let tf = function_expr(rule)?;
-            if tf.is_comparable() {
-                Ok(Comparable::Function(tf))
-            } else {
-                Err(JsonPathError::InvalidJsonPath(format!(
-                    "Function {} is not comparable",
-                    tf.to_string()
-                )))
-            }
-        }
-        _ => Err(rule.into()),
-    }
+/// Whether the filter is a test alone, like `@.a` or `length(@.b)`.
+fn bare_test(filter: &Filter) -> bool {
+    matches!(filter, Filter::Atom(FilterAtom::Test { not: false, .. }))
 }
 
 fn next_down(rule: Pair<Rule>) -> Parsed<Pair<Rule>> {
@@ -0,0 +1,379 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::spans::Span;
+use crate::parser::Rule;
+use pest::error::{ErrorVariant, InputLocation};
+use std::fmt::{Display, Formatter};
+
+/// A parse error explained in the terms of the query language rather than the grammar.
+///
+/// It points at the place in the query where parsing went wrong,
+/// lists what could have been written there and adds hints for the common mistakes.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::parser::diagnostics::Diagnostic;
+/// use jsonpath_rust::parser::parse_json_path;
+///
+/// let query = "$[?@.a = 1]";
+/// let err = parse_json_path(query).unwrap_err();
+/// let diagnostic = Diagnostic::new(query, &err);
+///
+/// assert_eq!(diagnostic.column, 8);
+/// assert!(diagnostic.hints.iter().any(|h| h.contains("`==`")));
+/// println!("{}", diagnostic);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The query the diagnostic is about.
+    pub query: String,
+    /// A short description of the problem.
+    pub message: String,
+    /// The part of the query the problem is in.
+    pub span: Span,
+    /// The line of the beginning of the span, starting from 1.
+    pub line: usize,
+    /// The column (in characters) of the beginning of the span, starting from 1.
+    pub column: usize,
+    /// What the parser would have accepted at the position.
+    pub expected: Vec<String>,
+    /// Suggestions on how to fix the query.
+    pub hints: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Explains the error that the parsing of `query` resulted in.
+    pub fn new(query: &str, err: &JsonPathError) -> Self {
+        let (message, span, expected) = match err {
+            JsonPathError::PestError(e) => {
+                let span = match e.location {
+                    InputLocation::Pos(p) => Span::new(p, next_char_boundary(query, p)),
+                    InputLocation::Span((s, e)) => Span::new(s, e),
+                };
+                match &e.variant {
+                    ErrorVariant::ParsingError { positives, .. } => {
+                        let expected = expected_tokens(positives);
+                        let message = match query.get(span.start..).map(|r| r.chars().next()) {
+                            Some(Some(c)) => format!("unexpected `{}`", c),
+                            Some(None) => "unexpected end of the query".to_string(),
+                            None => "unexpected input".to_string(),
+                        };
+                        (message, span, expected)
+                    }
+                    ErrorVariant::CustomError { message } => (message.clone(), span, vec![]),
+                }
+            }
+            e => (e.to_string(), Span::new(0, query.len()), vec![]),
+        };
+        let (line, column) = line_col(query, span.start);
+
+        Diagnostic {
+            query: query.to_string(),
+            message,
+            span,
+            line,
+            column,
+            expected,
+            hints: hints(query),
+        }
+    }
+
+    /// Renders the line of the query the problem is in with the span underlined.
+    ///
+    /// The span is not underlined if it does not point into the query.
+    pub fn snippet(&self) -> String {
+        let line = self.query.lines().nth(self.line - 1).unwrap_or_default();
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        if self.query.get(self.span.start..).is_none() {
+            return format!("{gutter} |\n{number} | {line}");
+        }
+
+        let line_start = self
+            .query
+            .lines()
+            .take(self.line - 1)
+            .map(|l| l.len() + 1)
+            .sum::<usize>();
+        let span_end = self.span.end.min(line_start + line.len());
+        let width = self
+            .query
+            .get(self.span.start..span_end)
+            .map(|s| s.chars().count())
+            .unwrap_or_default()
+            .max(1);
+
+        format!(
+            "{gutter} |\n{number} | {line}\n{gutter} | {}{}",
+            " ".repeat(self.column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "error: {} at line {}, column {}",
+            self.message, self.line, self.column
+        )?;
+        write!(f, "{}", self.snippet())?;
+        if !self.expected.is_empty() {
+            write!(f, "\n  = expected {}", self.expected.join(", "))?;
+        }
+        for hint in self.hints.iter() {
+            write!(f, "\n  = help: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+fn next_char_boundary(query: &str, pos: usize) -> usize {
+    query
+        .get(pos..)
+        .and_then(|rest| rest.chars().next())
+        .map(|c| pos + c.len_utf8())
+        .unwrap_or(pos)
+}
+
+fn line_col(query: &str, pos: usize) -> (usize, usize) {
+    let before = query.get(..pos).unwrap_or(query);
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map(|l| l.chars().count())
+        .unwrap_or_default()
+        + 1;
+    (line, column)
+}
+
+/// Describes a grammar rule in the words a user of the query language would use.
+fn describe(rule: &Rule) -> Option<&'static str> {
+    match rule {
+        Rule::main | Rule::jp_query => Some("`$`"),
+        Rule::segments | Rule::segment | Rule::child_segment | Rule::descendant_segment => {
+            Some("a segment like `.name` or `[...]`")
+        }
+        Rule::bracketed_selection
+        | Rule::name_segment
+        | Rule::index_segment
+        | Rule::singular_query_segments => Some("`[` or `.`"),
+        Rule::selector
+        | Rule::slice_selector
+        | Rule::index_selector
+        | Rule::start
+        | Rule::end
+        | Rule::step => Some("a selector like `'name'`, `0`, `1:3`, `*` or `?<filter>`"),
+        Rule::wildcard_selector => Some("`*`"),
+        Rule::name_selector | Rule::string => Some("a quoted string"),
+        Rule::member_name_shorthand | Rule::ALPHA => Some("a member name"),
+        Rule::filter_selector => Some("a filter like `?@.name`"),
+        Rule::logical_expr
+        | Rule::logical_expr_and
+        | Rule::atom_expr
+        | Rule::paren_expr
+        | Rule::test_expr
+        | Rule::test
+        | Rule::rel_query
+        | Rule::not_op => Some("a filter expression"),
+        Rule::comp_expr | Rule::comparable => Some("a value to compare"),
+        Rule::comp_op => Some("a comparison operator (`==`, `!=`, `<`, `<=`, `>`, `>=`)"),
+        Rule::literal => Some("a literal like `1`, `'text'`, `true` or `null`"),
+        Rule::number | Rule::int | Rule::frac | Rule::exp => Some("a number"),
+        Rule::bool => Some("`true` or `false`"),
+        Rule::null => Some("`null`"),
+        Rule::singular_query | Rule::rel_singular_query | Rule::abs_singular_query => {
+            Some("a query selecting at most one node like `@.name` or `$.list[0]`")
+        }
+        Rule::function_expr | Rule::function_name | Rule::function_argument => {
+            Some("a function call")
+        }
+        Rule::EOI => Some("the end of the query"),
+        _ => None,
+    }
+}
+
+fn expected_tokens(rules: &[Rule]) -> Vec<String> {
+    let mut expected: Vec<String> = vec![];
+    for d in rules.iter().filter_map(describe) {
+        if !expected.iter().any(|e| e == d) {
+            expected.push(d.to_string());
+        }
+    }
+    expected
+}
+
+/// Replaces the contents of the string literals with spaces,
+/// so the hints are looking only at the syntax of the query.
+fn blank_strings(query: &str) -> (String, bool) {
+    let mut string_delim: Option<char> = None;
+    let mut escaped = false;
+    let mut res = String::with_capacity(query.len());
+
+    for c in query.chars() {
+        if let Some(delim) = string_delim {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delim {
+                string_delim = None;
+                res.push(c);
+                continue;
+            }
+            res.push(' ');
+            continue;
+        }
+        if c == '\'' || c == '"' {
+            string_delim = Some(c);
+        }
+        res.push(c);
+    }
+
+    (res, string_delim.is_some())
+}
+
+fn hints(query: &str) -> Vec<String> {
+    let (syntax, open_string) = blank_strings(query);
+    let mut hints = vec![];
+
+    if !syntax.trim_start().starts_with('$') {
+        hints.push("a query starts with `$` that stands for the root of the document".to_string());
+    } else if syntax.trim() != syntax {
+        hints.push("a query can not start or end with whitespace".to_string());
+    }
+    if open_string {
+        hints.push("a string literal is not closed with a matching quote".to_string());
+    }
+
+    let chars: Vec<char> = syntax.chars().collect();
+    let at = |i: usize| chars.get(i).copied().unwrap_or(' ');
+    let mut single_eq = false;
+    let mut single_and = false;
+    let mut single_or = false;
+    for (i, c) in chars.iter().enumerate() {
+        let prev = if i == 0 { ' ' } else { at(i - 1) };
+        let next = at(i + 1);
+        match c {
+            '=' if !"=!<>~".contains(prev) && next != '=' => single_eq = true,
+            '&' if prev != '&' && next != '&' => single_and = true,
+            '|' if prev != '|' && next != '|' => single_or = true,
+            _ => {}
+        }
+    }
+    if single_eq {
+        hints.push("use `==` to check for equality, `=` is not an operator".to_string());
+    }
+    if syntax.contains("~=") {
+        hints.push(
+            "use `match(@.name, 'regex')` or `search(@.name, 'regex')` instead of `~=`".to_string(),
+        );
+    }
+    if single_and {
+        hints.push("use `&&` for the logical AND".to_string());
+    }
+    if single_or {
+        hints.push("use `||` for the logical OR".to_string());
+    }
+
+    let filter_without_mark = syntax
+        .match_indices('[')
+        .any(|(i, _)| matches!(syntax[i + 1..].trim_start().chars().next(), Some('@' | '(')));
+    if filter_without_mark {
+        hints.push("a filter starts with `?`, like `[?@.name]`".to_string());
+    }
+
+    for (open, close) in [('(', ')'), ('[', ']')] {
+        if syntax.matches(open).count() > syntax.matches(close).count() {
+            hints.push(format!("`{}` is not closed with `{}`", open, close));
+        }
+    }
+
+    hints
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::diagnostics::Diagnostic;
+    use crate::parser::parse_json_path;
+
+    fn diagnose(query: &str) -> Diagnostic {
+        Diagnostic::new(
+            query,
+            &parse_json_path(query).expect_err("the query is invalid"),
+        )
+    }
+
+    #[test]
+    fn single_equals() {
+        let d = diagnose("$[?@.a = 1]");
+
+        assert_eq!((d.line, d.column), (1, 8));
+        assert_eq!(d.message, "unexpected `=`");
+        assert!(d.expected.iter().any(|e| e.contains("comparison operator")));
+        assert!(d.expected.iter().all(|e| !e.contains("ALPHA")));
+        assert_eq!(
+            d.hints,
+            vec!["use `==` to check for equality, `=` is not an operator"]
+        );
+        assert_eq!(d.snippet(), "  |\n1 | $[?@.a = 1]\n  |        ^");
+    }
+
+    #[test]
+    fn missing_root() {
+        let d = diagnose("a.b");
+
+        assert_eq!(d.expected, vec!["`$`"]);
+        assert_eq!(
+            d.hints,
+            vec!["a query starts with `$` that stands for the root of the document"]
+        );
+    }
+
+    #[test]
+    fn unclosed() {
+        let d = diagnose("$.a[?(@.b == 1]");
+        assert_eq!(d.hints, vec!["`(` is not closed with `)`"]);
+
+        let d = diagnose("$[?@.a=='x]");
+        assert!(d
+            .hints
+            .contains(&"a string literal is not closed with a matching quote".to_string()));
+    }
+
+    #[test]
+    fn strings_are_not_syntax() {
+        let d = diagnose("$[?@.a == 'a = b' & @.c]");
+        assert_eq!(d.hints, vec!["use `&&` for the logical AND"]);
+    }
+
+    #[test]
+    fn filter_without_question_mark() {
+        let d = diagnose("$.a[@.b]");
+        assert_eq!(d.hints, vec!["a filter starts with `?`, like `[?@.name]`"]);
+    }
+
+    #[test]
+    fn not_a_pest_error() {
+        let d = diagnose("$[?count(1) == 1]");
+
+        assert_eq!(d.span.start, 0);
+        assert_eq!(d.span.end, 17);
+        assert!(d.expected.is_empty());
+        assert!(d.to_string().starts_with("error: Invalid json path"));
+    }
+
+    #[test]
+    fn error_of_another_query() {
+        let err = parse_json_path("$.abcdef[?@ = 1]").expect_err("the query is invalid");
+
+        let d = Diagnostic::new("$.é", &err);
+        assert_eq!(d.message, "unexpected input");
+        assert_eq!(d.snippet(), "  |\n1 | $.é");
+
+        let d = Diagnostic::new("$[é", &parse_json_path("$[é").unwrap_err());
+        assert_eq!(d.message, "unexpected `é`");
+    }
+}
@@ -1,6 +1,10 @@
+use crate::parser::options::QueryLimit;
 use crate::parser::Rule;
+use crate::query::limits::Abort;
+use crate::query::path::NormalizedPath;
 use crate::query::queryable::Queryable;
 use pest::iterators::Pair;
+use std::io::ErrorKind;
 use std::num::{ParseFloatError, ParseIntError};
 use std::str::ParseBoolError;
 use thiserror::Error;
@@ -30,8 +34,28 @@ pub enum JsonPathError {
     EmptyInner(String),
     #[error("Invalid json path: {0}")]
     InvalidJsonPath(String),
+    #[error("Ill-typed function expression: {0}")]
+    IllTyped(String),
     #[error("JSONPath nesting depth exceeds the maximum of {0}")]
     MaxNestingDepthExceeded(usize),
+    #[error("Invalid JSON Patch: {0}")]
+    InvalidPatch(String),
+    #[error("Failed to deserialize the node at {path}: {message}")]
+    Deserialization {
+        path: NormalizedPath,
+        message: String,
+    },
+    #[error("The query selects no node")]
+    NoMatch,
+    /// The evaluation stops at the second match, so only a lower bound of the matches is known.
+    #[error("The query selects at least {at_least} nodes, one is expected")]
+    MultipleMatches { at_least: usize },
+    #[error("The query exceeds a limit of the parser: {0}")]
+    QueryLimitExceeded(QueryLimit),
+    #[error("The evaluation is aborted: {0}")]
+    EvaluationAborted(Abort),
+    #[error("Failed to read the input: {message}")]
+    Io { kind: ErrorKind, message: String },
 }
 
 impl JsonPathError {
@@ -46,6 +70,15 @@ impl<T: Queryable> From<T> for JsonPathError {
     }
 }
 
+impl From<std::io::Error> for JsonPathError {
+    fn from(err: std::io::Error) -> Self {
+        JsonPathError::Io {
+            kind: err.kind(),
+            message: err.to_string(),
+        }
+    }
+}
+
 impl From<&str> for JsonPathError {
     fn from(val: &str) -> Self {
         JsonPathError::EmptyInner(val.to_string())
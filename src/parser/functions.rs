@@ -0,0 +1,93 @@
+use std::fmt::{Display, Formatter};
+
+/// The types of the function parameters and results (RFC 9535, section 2.4.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FunctionType {
+    /// A JSON value or `Nothing`.
+    Value,
+    /// `LogicalTrue` or `LogicalFalse`.
+    Logical,
+    /// A list of nodes.
+    Nodes,
+}
+
+impl Display for FunctionType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FunctionType::Value => write!(f, "ValueType"),
+            FunctionType::Logical => write!(f, "LogicalType"),
+            FunctionType::Nodes => write!(f, "NodesType"),
+        }
+    }
+}
+
+/// The declared types of the parameters and the result of a function extension.
+/// The number of parameters is the arity of the function.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionSignature {
+    pub params: Vec<FunctionType>,
+    pub result: FunctionType,
+}
+
+impl FunctionSignature {
+    pub fn new(params: Vec<FunctionType>, result: FunctionType) -> Self {
+        FunctionSignature { params, result }
+    }
+}
+
+impl Display for FunctionSignature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "({}) -> {}",
+            self.params
+                .iter()
+                .map(|p| p.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.result
+        )
+    }
+}
+
+/// A source of the signatures of the function extensions the parser accepts.
+///
+/// It is implemented by [`crate::query::functions::FunctionRegistry`]
+/// and by [`BuiltinFunctions`] that knows only the functions shipped with the library.
+pub trait FunctionSignatures {
+    /// Returns the signature of the function with the given name if the function is known.
+    fn signature(&self, name: &str) -> Option<FunctionSignature>;
+}
+
+/// The functions defined by RFC 9535 and the extensions shipped with the library.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuiltinFunctions;
+
+/// The extensions shipped with the library on top of the functions defined by RFC 9535.
+pub(crate) const BUILTIN_EXTENSIONS: [&str; 5] = ["in", "nin", "none_of", "any_of", "subset_of"];
+
+/// The functions defined by RFC 9535 that have dedicated variants in [`crate::parser::model::TestFunction`].
+pub(crate) const RFC_FUNCTIONS: [&str; 5] = ["length", "count", "value", "match", "search"];
+
+impl FunctionSignatures for BuiltinFunctions {
+    fn signature(&self, name: &str) -> Option<FunctionSignature> {
+        use FunctionType::*;
+        match name {
+            "length" => Some(FunctionSignature::new(vec![Value], Value)),
+            "count" => Some(FunctionSignature::new(vec![Nodes], Value)),
+            "value" => Some(FunctionSignature::new(vec![Nodes], Value)),
+            "match" | "search" => Some(FunctionSignature::new(vec![Value, Value], Logical)),
+            n if BUILTIN_EXTENSIONS.contains(&n) => {
+                Some(FunctionSignature::new(vec![Value, Value], Logical))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Checks whether the name can be used for a function extension (RFC 9535, section 2.4).
+pub(crate) fn is_function_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
@@ -0,0 +1,231 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::Parsed;
+use crate::query::regexes::{RegexMode, DEFAULT_CACHE_CAPACITY};
+use std::fmt::{Display, Formatter};
+
+/// The maximum nesting depth of parentheses and brackets accepted in a query by default.
+///
+/// Parsing is recursive (both in the underlying PEG parser and in the AST
+/// construction that follows), so a query that nests grouping constructs
+/// unboundedly would recurse until the stack overflows and the process aborts.
+/// Real-world queries nest only a handful of levels, so this limit is generous
+/// while still keeping stack usage bounded.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 128;
+
+/// The highest nesting depth [`ParserOptions::max_nesting_depth`] accepts,
+/// a bigger limit is lowered to it so the recursive parser stays within the stack of a thread.
+pub const MAX_NESTING_DEPTH: usize = 512;
+
+/// The limits and the settings of the parser, see [`crate::parser::parse_json_path_with`].
+///
+/// Only the nesting depth is bounded by default,
+/// the other limits are meant for the services parsing the queries of their users.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::parser::errors::JsonPathError;
+/// use jsonpath_rust::parser::options::{ParserOptions, QueryLimit};
+/// use jsonpath_rust::parser::parse_json_path_with;
+///
+/// let options = ParserOptions::default().max_length(64).max_segments(3);
+///
+/// assert!(parse_json_path_with("$.a.b[0]", &options).is_ok());
+/// assert_eq!(
+///     parse_json_path_with("$.a.b[?@.c].d", &options),
+///     Err(JsonPathError::QueryLimitExceeded(QueryLimit::Segments(3)))
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserOptions {
+    max_length: Option<usize>,
+    pub(crate) max_nesting_depth: usize,
+    max_segments: Option<usize>,
+    max_filter_complexity: Option<usize>,
+    max_function_depth: Option<usize>,
+    pub(crate) regex_mode: RegexMode,
+    pub(crate) regex_cache: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            max_length: None,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+            max_segments: None,
+            max_filter_complexity: None,
+            max_function_depth: None,
+            regex_mode: RegexMode::default(),
+            regex_cache: DEFAULT_CACHE_CAPACITY,
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Bounds the length of the query in bytes.
+    pub fn max_length(self, max: usize) -> Self {
+        ParserOptions {
+            max_length: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds the nesting of parentheses and brackets, [`DEFAULT_MAX_NESTING_DEPTH`] by default.
+    ///
+    /// The parser is recursive, so the limit is at most [`MAX_NESTING_DEPTH`].
+    pub fn max_nesting_depth(self, max: usize) -> Self {
+        ParserOptions {
+            max_nesting_depth: max.min(MAX_NESTING_DEPTH),
+            ..self
+        }
+    }
+
+    /// Bounds the number of the segments, the ones of the queries in the filters included.
+    pub fn max_segments(self, max: usize) -> Self {
+        ParserOptions {
+            max_segments: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds the number of the logical expressions, comparisons, tests
+    /// and function calls of the filters.
+    pub fn max_filter_complexity(self, max: usize) -> Self {
+        ParserOptions {
+            max_filter_complexity: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds how deep the function calls nest, `length(value(@.a))` has the depth 2.
+    pub fn max_function_depth(self, max: usize) -> Self {
+        ParserOptions {
+            max_function_depth: Some(max),
+            ..self
+        }
+    }
+
+    /// Sets how the patterns of `match` and `search` are read, the literal ones are validated
+    /// along with the query.
+    ///
+    /// The mode is kept by the [`crate::query::compiled::CompiledQuery`]
+    /// and the [`crate::query::set::QuerySet`] parsing with the options,
+    /// a [`JpQuery`](crate::parser::model::JpQuery) evaluated on its own reads the patterns as I-Regexps.
+    pub fn regex_mode(self, mode: RegexMode) -> Self {
+        ParserOptions {
+            regex_mode: mode,
+            ..self
+        }
+    }
+
+    /// Sets how many regular expressions built from the documents (like `match(@.a, $.pattern)`)
+    /// the compiled query keeps compiled, [`DEFAULT_CACHE_CAPACITY`] by default.
+    pub fn regex_cache(self, capacity: usize) -> Self {
+        ParserOptions {
+            regex_cache: capacity,
+            ..self
+        }
+    }
+
+    /// Checks the limits that are known before parsing.
+    pub(crate) fn check_input(&self, jp_str: &str) -> Parsed<()> {
+        match self.max_length {
+            Some(max) if jp_str.len() > max => {
+                Err(JsonPathError::QueryLimitExceeded(QueryLimit::Length(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The counters of the limits checked while the query is built.
+    pub(crate) fn measure(&self) -> Measure {
+        Measure {
+            max_segments: self.max_segments,
+            max_filter_complexity: self.max_filter_complexity,
+            max_function_depth: self.max_function_depth,
+            ..Measure::default()
+        }
+    }
+}
+
+/// The limit of [`ParserOptions`] a query exceeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryLimit {
+    /// The query is longer than the given number of bytes.
+    Length(usize),
+    /// The query has more than the given number of segments.
+    Segments(usize),
+    /// The filters have more than the given number of expressions.
+    FilterComplexity(usize),
+    /// The function calls nest deeper than the given depth.
+    FunctionDepth(usize),
+}
+
+impl Display for QueryLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueryLimit::Length(max) => write!(f, "the query is longer than {} bytes", max),
+            QueryLimit::Segments(max) => write!(f, "the query has more than {} segments", max),
+            QueryLimit::FilterComplexity(max) => {
+                write!(f, "the filters have more than {} expressions", max)
+            }
+            QueryLimit::FunctionDepth(max) => {
+                write!(f, "the function calls nest deeper than {}", max)
+            }
+        }
+    }
+}
+
+/// The size of the query built so far, checked against the limits as it grows.
+///
+/// The nesting of the query is bounded by the parser already.
+#[derive(Debug, Default)]
+pub(crate) struct Measure {
+    max_segments: Option<usize>,
+    max_filter_complexity: Option<usize>,
+    max_function_depth: Option<usize>,
+    segments: usize,
+    filter_nodes: usize,
+    function_depth: usize,
+}
+
+impl Measure {
+    /// Counts a segment, the ones of the singular queries included.
+    pub(crate) fn segment(&mut self) -> Parsed<()> {
+        self.segments += 1;
+        Self::check(self.max_segments, self.segments, QueryLimit::Segments)
+    }
+
+    /// Counts the logical expressions, comparisons and tests of a filter.
+    pub(crate) fn filter_nodes(&mut self, count: usize) -> Parsed<()> {
+        self.filter_nodes += count;
+        Self::check(
+            self.max_filter_complexity,
+            self.filter_nodes,
+            QueryLimit::FilterComplexity,
+        )
+    }
+
+    /// Counts a function call and goes into its arguments.
+    pub(crate) fn enter_function(&mut self) -> Parsed<()> {
+        self.filter_nodes(1)?;
+        self.function_depth += 1;
+        Self::check(
+            self.max_function_depth,
+            self.function_depth,
+            QueryLimit::FunctionDepth,
+        )
+    }
+
+    /// Goes out of the arguments of a function call.
+    pub(crate) fn leave_function(&mut self) {
+        self.function_depth -= 1;
+    }
+
+    fn check(max: Option<usize>, value: usize, limit: fn(usize) -> QueryLimit) -> Parsed<()> {
+        match max {
+            Some(max) if value > max => Err(JsonPathError::QueryLimitExceeded(limit(max))),
+            _ => Ok(()),
+        }
+    }
+}
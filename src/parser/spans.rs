@@ -0,0 +1,175 @@
+use crate::parser::model::JpQuery;
+use crate::parser::Rule;
+use pest::iterators::Pair;
+use std::fmt::{Display, Formatter};
+
+/// A byte range `start..end` of the query string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Returns the part of the query the span points at.
+    pub fn as_str<'a>(&self, query: &'a str) -> &'a str {
+        query.get(self.start..self.end).unwrap_or_default()
+    }
+
+    /// Checks whether the byte offset is inside the span.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+impl From<pest::Span<'_>> for Span {
+    fn from(span: pest::Span<'_>) -> Self {
+        Span::new(span.start(), span.end())
+    }
+}
+
+/// The kind of the AST node a [`SpanNode`] stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// A [`JpQuery`].
+    Query,
+    /// A [`crate::parser::model::Segment`].
+    Segment,
+    /// A [`crate::parser::model::Selector`].
+    Selector,
+    /// A [`crate::parser::model::Filter`].
+    Filter,
+    /// A [`crate::parser::model::FilterAtom`].
+    FilterAtom,
+    /// A [`crate::parser::model::Comparison`].
+    Comparison,
+    /// A [`crate::parser::model::Comparable`].
+    Comparable,
+    /// A [`crate::parser::model::SingularQuery`].
+    SingularQuery,
+    /// A [`crate::parser::model::SingularQuerySegment`].
+    SingularQuerySegment,
+    /// A [`crate::parser::model::Test`].
+    Test,
+    /// A [`crate::parser::model::TestFunction`].
+    Function,
+    /// A [`crate::parser::model::FnArg`].
+    FnArg,
+    /// A [`crate::parser::model::Literal`].
+    Literal,
+}
+
+/// The position of an AST node in the query string.
+///
+/// The tree mirrors the shape of the [`JpQuery`] it was parsed along with:
+/// every node has one child per nested AST node, in the same order the model keeps them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanNode {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<SpanNode>,
+}
+
+impl SpanNode {
+    fn new(kind: NodeKind, span: Span, children: Vec<SpanNode>) -> Self {
+        SpanNode {
+            kind,
+            span,
+            children,
+        }
+    }
+
+    /// Returns the deepest node covering the byte offset.
+    pub fn innermost(&self, offset: usize) -> Option<&SpanNode> {
+        if !self.span.contains(offset) {
+            return None;
+        }
+        self.children
+            .iter()
+            .find_map(|c| c.innermost(offset))
+            .or(Some(self))
+    }
+
+    /// Returns the node itself and all the nested nodes in the depth-first order.
+    pub fn descendants(&self) -> Vec<&SpanNode> {
+        let mut nodes = vec![self];
+        for c in self.children.iter() {
+            nodes.extend(c.descendants());
+        }
+        nodes
+    }
+}
+
+/// A parsed query along with the positions of its nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedQuery {
+    pub query: JpQuery,
+    pub spans: SpanNode,
+}
+
+/// The span of a rule without the whitespace the grammar lets it swallow at its edges.
+fn span_of(rule: &Pair<Rule>) -> Span {
+    let span = rule.as_span();
+    let text = span.as_str();
+    Span::new(
+        span.start() + text.len() - text.trim_start().len(),
+        span.end() - (text.len() - text.trim_end().len()),
+    )
+}
+
+/// Records the spans of the nodes as the parser builds them, see [`crate::parser`].
+#[derive(Debug, Default)]
+pub(super) struct SpanRecorder {
+    /// The nodes being built, every one is nested in the one before it.
+    open: Vec<SpanNode>,
+    root: Option<SpanNode>,
+}
+
+impl SpanRecorder {
+    /// Starts a node the following ones are nested in.
+    pub(super) fn open(&mut self, kind: NodeKind, rule: &Pair<Rule>) {
+        self.open.push(SpanNode::new(kind, span_of(rule), vec![]))
+    }
+
+    /// Ends the last started node, `collapse` puts its only child in its place,
+    /// the way the model drops a group of a single expression.
+    pub(super) fn close(&mut self, collapse: bool) {
+        let Some(mut node) = self.open.pop() else {
+            return;
+        };
+        if collapse && node.children.len() == 1 {
+            node = node.children.remove(0);
+        }
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => self.root = Some(node),
+        }
+    }
+
+    /// Puts the node `levels` below the last ended one in its place.
+    pub(super) fn lift(&mut self, levels: usize) {
+        if let Some(node) = self.open.last_mut().and_then(|p| p.children.last_mut()) {
+            for _ in 0..levels {
+                if node.children.is_empty() {
+                    return;
+                }
+                *node = node.children.remove(0);
+            }
+        }
+    }
+
+    /// The node of the query, once it is ended.
+    pub(super) fn finish(self) -> Option<SpanNode> {
+        self.root
+    }
+}
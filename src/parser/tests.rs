@@ -1,3 +1,5 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::functions::{FunctionSignature, FunctionType};
 use crate::parser::model::slice_from;
 use crate::parser::model::Comparison;
 use crate::parser::model::FilterAtom;
@@ -10,12 +12,16 @@ use crate::parser::model::SingularQuery;
 use crate::parser::model::SingularQuerySegment;
 use crate::parser::model::TestFunction;
 use crate::parser::model::{Comparable, Filter};
+use crate::parser::options::{ParserOptions, QueryLimit, MAX_NESTING_DEPTH};
+use crate::parser::spans::{NodeKind, Span, SpannedQuery};
 use crate::parser::Test;
 use crate::parser::{
-    comp_expr, comparable, filter_atom, function_expr, jp_query, literal, parse_json_path, segment,
-    selector, singular_query, singular_query_segments, slice_selector, test, JSPathParser, Parsed,
-    Rule,
+    comp_expr, comparable, filter_atom, function_expr, jp_query, literal, parse_json_path,
+    parse_json_path_with, parse_json_path_with_spans, parse_json_path_with_spans_and,
+    parse_singular_query, segment, selector, singular_query, singular_query_segments,
+    slice_selector, test, JSPathParser, Parsed, Rule,
 };
+use crate::query::functions::{FnValue, FunctionRegistry};
 use crate::{
     arg, atom, cmp, comparable, jq, lit, or, q_segment, q_segments, segment, selector,
     singular_query, slice, test, test_fn,
@@ -99,6 +105,25 @@ fn singular_query_test() {
         .assert("$[\"a\"].b[3]", singular_query!([a] b [3]));
 }
 
+#[test]
+fn parse_singular_query_test() {
+    assert_eq!(
+        parse_singular_query("$.a['b c'][-1]"),
+        Ok(SingularQuery::Root(vec![
+            SingularQuerySegment::Name("a".to_string()),
+            SingularQuerySegment::Name("b c".to_string()),
+            SingularQuerySegment::Index(-1),
+        ]))
+    );
+    assert_eq!(
+        parse_singular_query("@"),
+        Ok(SingularQuery::Current(vec![]))
+    );
+    assert!(parse_singular_query("$.a[*]").is_err());
+    assert!(parse_singular_query("$..a").is_err());
+    assert!(parse_singular_query("$.a ").is_err());
+}
+
 #[test]
 fn slice_selector_test() {
     TestPair::new(Rule::slice_selector, slice_selector)
@@ -275,6 +300,110 @@ fn deeply_nested_filter_is_rejected_instead_of_overflowing_stack() {
     ));
 }
 
+#[test]
+fn parser_options() {
+    let parse = |query: &str, options: ParserOptions| parse_json_path_with(query, &options).err();
+    let exceeded = |limit: QueryLimit| Some(JsonPathError::QueryLimitExceeded(limit));
+    let query = "$.a[?@.b && length(value(@..c)) > 1 || $.d[?@.e]]";
+
+    assert_eq!(parse(query, ParserOptions::default()), None);
+    assert_eq!(
+        parse(query, ParserOptions::default().max_length(10)),
+        exceeded(QueryLimit::Length(10))
+    );
+    assert_eq!(parse(query, ParserOptions::default().max_segments(7)), None);
+    assert_eq!(
+        parse(query, ParserOptions::default().max_segments(6)),
+        exceeded(QueryLimit::Segments(6))
+    );
+    assert_eq!(
+        parse(query, ParserOptions::default().max_filter_complexity(8)),
+        None
+    );
+    assert_eq!(
+        parse(query, ParserOptions::default().max_filter_complexity(7)),
+        exceeded(QueryLimit::FilterComplexity(7))
+    );
+    assert_eq!(
+        parse(query, ParserOptions::default().max_function_depth(2)),
+        None
+    );
+    assert_eq!(
+        parse(query, ParserOptions::default().max_function_depth(1)),
+        exceeded(QueryLimit::FunctionDepth(1))
+    );
+
+    let nested = format!("$[?{}@.a{}]", "(".repeat(200), ")".repeat(200));
+    assert_eq!(
+        parse(&nested, ParserOptions::default()),
+        Some(JsonPathError::MaxNestingDepthExceeded(128))
+    );
+    assert_eq!(
+        parse(&nested, ParserOptions::default().max_nesting_depth(256)),
+        None
+    );
+    assert_eq!(
+        parse("$[?(@.a)]", ParserOptions::default().max_nesting_depth(1)),
+        Some(JsonPathError::MaxNestingDepthExceeded(1))
+    );
+}
+
+#[test]
+fn limits_are_checked_before_the_query_is_complete() {
+    let parse = |query: &str, options: ParserOptions| parse_json_path_with(query, &options).err();
+    let exceeded = |limit: QueryLimit| Some(JsonPathError::QueryLimitExceeded(limit));
+
+    // the length is checked before the parser sees the string
+    let garbage = "$[?(".repeat(100_000);
+    assert_eq!(
+        parse(&garbage, ParserOptions::default().max_length(1024)),
+        exceeded(QueryLimit::Length(1024))
+    );
+
+    // the walk stops at the first segment over the limit, before the functions are checked
+    let query = format!("$.a.b.c[?unknown({})]", vec!["@.d"; 1000].join(", "));
+    assert_eq!(
+        parse(&query, ParserOptions::default().max_segments(3)),
+        exceeded(QueryLimit::Segments(3))
+    );
+    assert_eq!(
+        parse(&query, ParserOptions::default().max_filter_complexity(0)),
+        exceeded(QueryLimit::FilterComplexity(0))
+    );
+}
+
+#[test]
+fn nesting_depth_is_capped() {
+    let nested = format!("$[?{}@.a{}]", "(".repeat(10_000), ")".repeat(10_000));
+    assert_eq!(
+        parse_json_path_with(
+            &nested,
+            &ParserOptions::default().max_nesting_depth(usize::MAX)
+        )
+        .err(),
+        Some(JsonPathError::MaxNestingDepthExceeded(MAX_NESTING_DEPTH))
+    );
+
+    // the deepest query accepted by the cap fails to parse without overflowing the stack
+    let deepest = format!(
+        "$[?{}@.a{}]",
+        "(".repeat(MAX_NESTING_DEPTH - 1),
+        ")".repeat(MAX_NESTING_DEPTH - 1)
+    );
+    let deepest_brackets = format!(
+        "${}{}",
+        "[?@".repeat(MAX_NESTING_DEPTH),
+        "]".repeat(MAX_NESTING_DEPTH)
+    );
+    for query in [deepest, deepest_brackets] {
+        let options = ParserOptions::default().max_nesting_depth(MAX_NESTING_DEPTH);
+        assert!(!matches!(
+            parse_json_path_with(&query, &options),
+            Err(JsonPathError::MaxNestingDepthExceeded(_))
+        ));
+    }
+}
+
 #[test]
 fn deeply_nested_brackets_are_rejected_instead_of_overflowing_stack() {
     let depth = 100_000;
@@ -295,3 +424,150 @@ fn brackets_inside_string_literals_do_not_count_towards_nesting() {
 fn moderate_nesting_still_parses() {
     assert!(parse_json_path("$[?((@.a == 1) && (@.b == 2))]").is_ok());
 }
+
+#[test]
+fn spans_follow_the_model() -> Parsed<()> {
+    let query = "$.a[1, 'b'][?@.c > 1 && length(@.d) == 2]";
+    let SpannedQuery { query: jq, spans } = parse_json_path_with_spans(query)?;
+
+    assert_eq!(jq, parse_json_path(query)?);
+    assert_eq!(spans.kind, NodeKind::Query);
+    assert_eq!(spans.span.as_str(query), query);
+    assert_eq!(spans.children.len(), jq.segments.len());
+
+    let texts = |kind| {
+        spans
+            .descendants()
+            .into_iter()
+            .filter(|n| n.kind == kind)
+            .map(|n| n.span.as_str(query))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        texts(NodeKind::Segment),
+        vec![".a", "[1, 'b']", "[?@.c > 1 && length(@.d) == 2]", ".d"]
+    );
+    assert_eq!(
+        texts(NodeKind::Selector),
+        vec!["a", "1", "'b'", "?@.c > 1 && length(@.d) == 2", "d"]
+    );
+    assert_eq!(
+        texts(NodeKind::Comparison),
+        vec!["@.c > 1", "length(@.d) == 2"]
+    );
+    assert_eq!(texts(NodeKind::Function), vec!["length(@.d)"]);
+    assert_eq!(texts(NodeKind::Literal), vec!["1", "2"]);
+
+    Ok(())
+}
+
+#[test]
+fn spans_with_options_and_functions() -> Parsed<()> {
+    let mut functions = FunctionRegistry::<serde_json::Value>::new();
+    functions.register(
+        "even",
+        FunctionSignature::new(vec![FunctionType::Value], FunctionType::Logical),
+        |_| FnValue::Logical(true),
+    )?;
+    let query = "$.a[?even(@.b)]";
+
+    assert!(parse_json_path_with_spans(query).is_err());
+    let SpannedQuery { spans, .. } =
+        parse_json_path_with_spans_and(query, &ParserOptions::default(), &functions)?;
+    assert_eq!(
+        spans
+            .descendants()
+            .into_iter()
+            .filter(|n| n.kind == NodeKind::Function)
+            .map(|n| n.span.as_str(query))
+            .collect::<Vec<_>>(),
+        vec!["even(@.b)"]
+    );
+
+    let options = ParserOptions::default().max_segments(1);
+    assert_eq!(
+        parse_json_path_with_spans_and(query, &options, &functions).err(),
+        Some(JsonPathError::QueryLimitExceeded(QueryLimit::Segments(1)))
+    );
+    assert_eq!(
+        parse_json_path_with_spans_and(query, &ParserOptions::default().max_length(4), &functions)
+            .err(),
+        Some(JsonPathError::QueryLimitExceeded(QueryLimit::Length(4)))
+    );
+
+    Ok(())
+}
+
+#[test]
+fn spans_innermost() -> Parsed<()> {
+    let query = "$..b[?@.c == 'x']";
+    let SpannedQuery { spans, .. } = parse_json_path_with_spans(query)?;
+
+    let node = spans.innermost(query.find("'x'").unwrap()).unwrap();
+    assert_eq!(node.kind, NodeKind::Literal);
+    assert_eq!(node.span, Span::new(13, 16));
+
+    let node = spans.innermost(query.find('b').unwrap()).unwrap();
+    assert_eq!(node.kind, NodeKind::Selector);
+    assert_eq!(node.span.as_str(query), "b");
+
+    assert!(spans.innermost(query.len()).is_none());
+
+    Ok(())
+}
+
+#[test]
+fn well_typed_functions() {
+    for query in [
+        "$[?length(@.a) > 1]",
+        "$[?length('abc') == 3]",
+        "$[?count(@.*) == 1]",
+        "$[?value(@..a) == 1]",
+        "$[?match(@.a, 'x.*')]",
+        "$[?!search(value(@..a), 'x')]",
+        "$[?length(value($.a)) == 1]",
+        "$[?in(@, $.list)]",
+    ] {
+        assert!(parse_json_path(query).is_ok(), "{}", query);
+    }
+}
+
+#[test]
+fn invalid_regex_literals() {
+    assert!(parse_json_path("$[?match(@, 'a[')]").is_err());
+    assert!(parse_json_path("$[?count(@[?search(@.b, '(')]) > 0]").is_err());
+    assert!(parse_json_path("$[?match(@, 'a.*') && search(@, $.re)]").is_ok());
+}
+
+#[test]
+fn ill_typed_functions() {
+    let ill_typed = |query: &str| match parse_json_path(query) {
+        Err(JsonPathError::IllTyped(msg)) => msg,
+        res => panic!("{} is expected to be ill-typed, got {:?}", query, res),
+    };
+
+    assert_eq!(
+        ill_typed("$[?length(@.*) < 3]"),
+        "the argument 1 of the function `length` expects ValueType, \
+         got a query that can select more than one node"
+    );
+    assert_eq!(
+        ill_typed("$[?length(@.a)]"),
+        "the function `length` returns ValueType and can not be used as a test, \
+         compare the result instead"
+    );
+    assert_eq!(
+        ill_typed("$[?match(@.a, 'a') == true]"),
+        "the function `match` returns LogicalType and can not be compared"
+    );
+    assert_eq!(
+        ill_typed("$[?count(length(@.a)) == 1]"),
+        "the argument 1 of the function `count` expects NodesType, \
+         got the function `length` returning ValueType"
+    );
+    assert_eq!(
+        ill_typed("$[?match(@.a == 1, 'a')]"),
+        "the argument 1 of the function `match` expects ValueType, got a logical expression"
+    );
+}
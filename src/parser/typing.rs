@@ -0,0 +1,206 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::functions::{FunctionSignature, FunctionSignatures, FunctionType};
+use crate::parser::model::{
+    Comparable, Filter, FilterAtom, FnArg, JpQuery, Segment, Selector, Test, TestFunction,
+};
+use crate::parser::Parsed;
+
+/// Checks that the function expressions of the query are well-typed (RFC 9535, section 2.4.3).
+///
+/// Every called function has to be known to `functions` and get the declared number of arguments.
+/// Besides that
+/// - a function used as a test has to return `LogicalType` or `NodesType`,
+/// - a function used in a comparison has to return `ValueType`,
+/// - an argument has to fit the declared type of the parameter:
+///   a `ValueType` parameter takes a literal, a singular query or a function returning `ValueType`,
+///   a `LogicalType` parameter takes a logical expression, a query
+///   or a function returning `LogicalType` or `NodesType`,
+///   a `NodesType` parameter takes a query or a function returning `NodesType`.
+///
+/// # Errors
+///
+/// Returns [`JsonPathError::IllTyped`] describing the first ill-typed expression,
+/// or [`JsonPathError::InvalidJsonPath`] for an unknown function or a wrong number of arguments.
+pub fn type_check(query: &JpQuery, functions: &dyn FunctionSignatures) -> Parsed<()> {
+    TypeChecker { functions }.segments(&query.segments)
+}
+
+struct TypeChecker<'a> {
+    functions: &'a dyn FunctionSignatures,
+}
+
+impl TypeChecker<'_> {
+    fn segments(&self, segments: &[Segment]) -> Parsed<()> {
+        segments.iter().try_for_each(|s| self.segment(s))
+    }
+
+    fn segment(&self, segment: &Segment) -> Parsed<()> {
+        match segment {
+            Segment::Descendant(s) => self.segment(s),
+            Segment::Selector(s) => self.selector(s),
+            Segment::Selectors(ss) => ss.iter().try_for_each(|s| self.selector(s)),
+        }
+    }
+
+    fn selector(&self, selector: &Selector) -> Parsed<()> {
+        match selector {
+            Selector::Filter(f) => self.filter(f),
+            _ => Ok(()),
+        }
+    }
+
+    fn filter(&self, filter: &Filter) -> Parsed<()> {
+        match filter {
+            Filter::Or(fs) | Filter::And(fs) => fs.iter().try_for_each(|f| self.filter(f)),
+            Filter::Atom(atom) => self.atom(atom),
+        }
+    }
+
+    fn atom(&self, atom: &FilterAtom) -> Parsed<()> {
+        match atom {
+            FilterAtom::Filter { expr, .. } => self.filter(expr),
+            FilterAtom::Test { expr, .. } => match expr.as_ref() {
+                Test::Function(f) => match self.function(f)? {
+                    FunctionType::Value => Err(ill_typed(format!(
+                        "the function `{}` returns ValueType and can not be used as a test, \
+                         compare the result instead",
+                        name(f)
+                    ))),
+                    _ => Ok(()),
+                },
+                test => self.test(test),
+            },
+            FilterAtom::Comparison(cmp) => {
+                let (lhs, rhs) = cmp.vals();
+                self.comparable(lhs)?;
+                self.comparable(rhs)
+            }
+        }
+    }
+
+    fn comparable(&self, comparable: &Comparable) -> Parsed<()> {
+        match comparable {
+            Comparable::Function(f) => match self.function(f)? {
+                FunctionType::Value => Ok(()),
+                tpe => Err(ill_typed(format!(
+                    "the function `{}` returns {} and can not be compared",
+                    name(f),
+                    tpe
+                ))),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn test(&self, test: &Test) -> Parsed<()> {
+        match test {
+            Test::RelQuery(segments) => self.segments(segments),
+            Test::AbsQuery(query) => self.segments(&query.segments),
+            Test::Function(f) => self.function(f).map(|_| ()),
+        }
+    }
+
+    /// Checks the call and returns the declared result type of the function.
+    fn function(&self, function: &TestFunction) -> Parsed<FunctionType> {
+        let name = name(function);
+        let args = args(function);
+        let FunctionSignature { params, result } =
+            self.functions.signature(name).ok_or_else(|| {
+                JsonPathError::InvalidJsonPath(format!("Unknown function `{}`", name))
+            })?;
+        if params.len() != args.len() {
+            return Err(JsonPathError::InvalidJsonPath(format!(
+                "Invalid number of arguments for the function `{}`: expected {}, got {}",
+                name,
+                params.len(),
+                args.len()
+            )));
+        }
+        for (idx, (arg, param)) in args.into_iter().zip(params).enumerate() {
+            self.arg(name, idx + 1, arg, param)?;
+        }
+        Ok(result)
+    }
+
+    fn arg(&self, function: &str, position: usize, arg: &FnArg, param: FunctionType) -> Parsed<()> {
+        let fits = match arg {
+            FnArg::Literal(_) => Ok(param == FunctionType::Value),
+            FnArg::Filter(filter) => self.filter(filter).map(|_| param == FunctionType::Logical),
+            FnArg::Test(test) => match test.as_ref() {
+                Test::Function(f) => self.function(f).map(|res| match param {
+                    FunctionType::Value => res == FunctionType::Value,
+                    FunctionType::Logical => res != FunctionType::Value,
+                    FunctionType::Nodes => res == FunctionType::Nodes,
+                }),
+                query => self
+                    .test(query)
+                    .map(|_| param != FunctionType::Value || is_singular(query)),
+            },
+        }?;
+
+        if fits {
+            Ok(())
+        } else {
+            Err(ill_typed(format!(
+                "the argument {} of the function `{}` expects {}, got {}",
+                position,
+                function,
+                param,
+                describe(arg, self.functions)
+            )))
+        }
+    }
+}
+
+fn ill_typed(message: String) -> JsonPathError {
+    JsonPathError::IllTyped(message)
+}
+
+fn name(function: &TestFunction) -> &str {
+    match function {
+        TestFunction::Custom(name, _) => name,
+        TestFunction::Length(_) => "length",
+        TestFunction::Value(_) => "value",
+        TestFunction::Count(_) => "count",
+        TestFunction::Search(..) => "search",
+        TestFunction::Match(..) => "match",
+    }
+}
+
+fn args(function: &TestFunction) -> Vec<&FnArg> {
+    match function {
+        TestFunction::Custom(_, args) => args.iter().collect(),
+        TestFunction::Length(arg) => vec![arg.as_ref()],
+        TestFunction::Value(arg) | TestFunction::Count(arg) => vec![arg],
+        TestFunction::Search(lhs, rhs) | TestFunction::Match(lhs, rhs) => vec![lhs, rhs],
+    }
+}
+
+/// A query is singular if it consists of name and index selectors only (RFC 9535, section 2.3.5.1).
+fn is_singular(test: &Test) -> bool {
+    let singular = |segments: &[Segment]| {
+        segments
+            .iter()
+            .all(|s| matches!(s, Segment::Selector(Selector::Name(_) | Selector::Index(_))))
+    };
+    match test {
+        Test::RelQuery(segments) => singular(segments),
+        Test::AbsQuery(query) => singular(&query.segments),
+        Test::Function(_) => false,
+    }
+}
+
+fn describe(arg: &FnArg, functions: &dyn FunctionSignatures) -> String {
+    match arg {
+        FnArg::Literal(_) => "a literal".to_string(),
+        FnArg::Filter(_) => "a logical expression".to_string(),
+        FnArg::Test(test) => match test.as_ref() {
+            Test::Function(f) => match functions.signature(name(f)) {
+                Some(s) => format!("the function `{}` returning {}", name(f), s.result),
+                None => format!("the function `{}`", name(f)),
+            },
+            query if is_singular(query) => "a singular query".to_string(),
+            _ => "a query that can select more than one node".to_string(),
+        },
+    }
+}
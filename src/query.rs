@@ -1,25 +1,42 @@
 mod atom;
 mod comparable;
 mod comparison;
+pub mod compiled;
+pub mod explain;
 mod filter;
+pub mod functions;
+pub mod iregexp;
+pub mod iter;
 mod jp_query;
+pub mod limits;
+pub mod lines;
+pub mod parallel;
+pub mod patch;
+pub mod path;
 pub mod queryable;
+pub mod regexes;
 mod segment;
 mod selector;
+pub mod set;
 pub mod state;
+pub mod stream;
 mod test;
 mod test_function;
 
 use crate::parser::errors::JsonPathError;
 use crate::parser::model::JpQuery;
 use crate::parser::{parse_json_path, Parsed};
+use crate::query::functions::FunctionRegistry;
+use crate::query::path::NormalizedPath;
 use crate::query::queryable::Queryable;
-use crate::query::state::{Data, Pointer};
+use crate::query::state::{Context, Data, Pointer};
 use state::State;
+
+pub use iter::{js_path_iter, js_path_process_iter, QueryIter};
 use std::borrow::Cow;
 
-/// A type that can be queried with JSONPath, typically string
-pub type QueryPath = String;
+/// The location of a query result in the document, see [`NormalizedPath`].
+pub type QueryPath = NormalizedPath;
 
 /// A type that can be queried with JSONPath, typically Result
 pub type Queried<T> = Result<T, JsonPathError>;
@@ -44,6 +61,10 @@ impl<'a, T: Queryable> QueryRef<'a, T> {
     pub fn path(self) -> QueryPath {
         self.path
     }
+    /// Clones the value, so the result no longer borrows the document.
+    pub fn into_owned(self) -> (QueryPath, T) {
+        (self.path, self.val.clone())
+    }
 }
 
 impl<'a, T: Queryable> From<(&'a T, QueryPath)> for QueryRef<'a, T> {
@@ -51,12 +72,22 @@ impl<'a, T: Queryable> From<(&'a T, QueryPath)> for QueryRef<'a, T> {
         QueryRef { val, path }
     }
 }
-impl<'a, T: Queryable> From<(&'a T, &str)> for QueryRef<'a, T> {
-    fn from((val, path): (&'a T, &str)) -> Self {
-        QueryRef {
+impl<'a, T: Queryable> TryFrom<(&'a T, &str)> for QueryRef<'a, T> {
+    type Error = JsonPathError;
+
+    fn try_from((val, path): (&'a T, &str)) -> Result<Self, Self::Error> {
+        Ok(QueryRef {
             val,
-            path: path.to_string(),
-        }
+            path: path.parse()?,
+        })
+    }
+}
+
+impl<'a, T: Queryable> TryFrom<(&'a T, String)> for QueryRef<'a, T> {
+    type Error = JsonPathError;
+
+    fn try_from((val, path): (&'a T, String)) -> Result<Self, Self::Error> {
+        (val, path.as_str()).try_into()
     }
 }
 
@@ -64,7 +95,7 @@ impl<'a, T: Queryable> From<Pointer<'a, T>> for QueryRef<'a, T> {
     fn from(pointer: Pointer<'a, T>) -> Self {
         QueryRef {
             val: pointer.inner,
-            path: pointer.path,
+            path: pointer.path.to_path(),
         }
     }
 }
@@ -81,20 +112,73 @@ pub fn js_path_process<'a, 'b, T: Queryable>(
     path: &'b JpQuery,
     value: &'a T,
 ) -> Queried<Vec<QueryRef<'a, T>>> {
-    match path.process(State::root(value)).data {
-        Data::Ref(p) => Ok(vec![p.into()]),
-        Data::Refs(refs) => Ok(refs.into_iter().map(Into::into).collect()),
-        Data::Value(v) => Err(v.into()),
-        Data::Nothing => Ok(vec![]),
+    Ok(process_pointers(path, State::root(value))?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+/// Processes the query keeping the matches as pointers,
+/// so the paths are rendered only by the callers that need them.
+pub(crate) fn process_pointers<'a, T: Queryable>(
+    path: &JpQuery,
+    state: State<'a, T>,
+) -> Queried<Vec<Pointer<'a, T>>> {
+    let budget = state.ctx.budget.clone();
+    let ctx = state.ctx.or_default_regexes();
+    let last = path.segments.len().checked_sub(1);
+    // the matches are accounted for as the last segment picks them
+    let state =
+        path.segments
+            .iter()
+            .enumerate()
+            .fold(State { ctx, ..state }, |state, (i, segment)| {
+                if Some(i) == last {
+                    let ctx = state.ctx.clone().picking_results();
+                    segment.process(state.with_context(ctx))
+                } else {
+                    segment.process(state)
+                }
+            });
+    let found = match state.data {
+        Data::Ref(p) => vec![p],
+        Data::Refs(refs) => refs,
+        Data::Value(v) => return Err(v.into()),
+        Data::Nothing => vec![],
+    };
+    if let Some(budget) = budget {
+        if last.is_none() {
+            budget.select(found.len());
+        }
+        budget.check()?;
     }
+    Ok(found)
+}
+
+/// Processes a JSONPath query that calls the functions of the registry.
+///
+/// The query is expected to be parsed with [`FunctionRegistry::parse`]
+/// (or [`crate::parser::parse_json_path_with_functions`]) so the calls are checked against the registry.
+pub fn js_path_process_with_functions<'a, T: Queryable>(
+    path: &JpQuery,
+    value: &'a T,
+    functions: &'a FunctionRegistry<T>,
+) -> Queried<Vec<QueryRef<'a, T>>> {
+    let state = State::root(value).with_context(Context::with_functions(functions));
+    Ok(process_pointers(path, state)?
+        .into_iter()
+        .map(Into::into)
+        .collect())
 }
 
 /// A convenience function to process a JSONPath query and return a vector of values, omitting the path.
 pub fn js_path_vals<'a, T: Queryable>(path: &str, value: &'a T) -> Queried<Vec<&'a T>> {
-    Ok(js_path(path, value)?
-        .into_iter()
-        .map(|r| r.val)
-        .collect::<Vec<_>>())
+    Ok(
+        process_pointers(&parse_json_path(path)?, State::root(value))?
+            .into_iter()
+            .map(|p| p.inner)
+            .collect::<Vec<_>>(),
+    )
 }
 
 /// A convenience function to process a JSONPath query and return a vector of paths, omitting the values.
@@ -105,21 +189,40 @@ pub fn js_path_path<T: Queryable>(path: &str, value: &T) -> Queried<Vec<QueryPat
         .collect::<Vec<_>>())
 }
 
+/// A convenience function to process a JSONPath query
+/// and return the locations of the matches as JSON Pointers (RFC 6901).
+pub fn js_path_pointers<T: Queryable>(path: &str, value: &T) -> Queried<Vec<String>> {
+    Ok(
+        process_pointers(&parse_json_path(path)?, State::root(value))?
+            .into_iter()
+            .map(|p| p.path.to_path().to_json_pointer())
+            .collect(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use crate::parser::errors::JsonPathError;
-    use crate::parser::{parse_json_path, Parsed};
+    use crate::parser::options::ParserOptions;
+    use crate::parser::{parse_json_path, parse_json_path_with, Parsed};
+    use crate::query::compiled::CompiledQuery;
+    use crate::query::iter::js_path_process_iter;
+    use crate::query::limits::Limits;
     use crate::query::queryable::Queryable;
-    use crate::query::{js_path, js_path_process, Queried, QueryRef};
-    use crate::JsonPath;
+    use crate::query::regexes::RegexMode;
+    use crate::query::{js_path, js_path_iter, js_path_process, Queried, QueryRef};
+    use crate::{single, JsonPath};
     use serde_json::{json, Value};
 
     fn test<'a, R>(json: &'a str, path: &str, expected: Vec<R>) -> Parsed<()>
     where
-        R: Into<QueryRef<'a, Value>>,
+        R: TryInto<QueryRef<'a, Value>, Error = JsonPathError>,
     {
         let json: Value = serde_json::from_str(json).map_err(|v| JsonPathError::NoRulePath)?;
-        let expected: Vec<QueryRef<'a, Value>> = expected.into_iter().map(|v| v.into()).collect();
+        let expected: Vec<QueryRef<'a, Value>> = expected
+            .into_iter()
+            .map(|v| v.try_into())
+            .collect::<Parsed<_>>()?;
         assert_eq!(json.query_with_path(path)?, expected);
 
         Ok(())
@@ -212,6 +315,19 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn query_ref_from_string() {
+        let val = json!(1);
+        let found: Parsed<QueryRef<Value>> = (&val, "$['a'][0]").try_into();
+        assert_eq!(
+            found.map(|r| r.path().to_string()),
+            Ok("$['a'][0]".to_string())
+        );
+
+        let failed: Parsed<QueryRef<Value>> = (&val, "$.a[*]".to_string()).try_into();
+        assert!(failed.is_err());
+    }
+
     #[test]
     fn simple_test() {
         let j1 = json!(2);
@@ -278,10 +394,10 @@ mod tests {
             template_json(),
             "$..book.[*].category",
             vec![
-                (&js1, "$['store']['book'][0].['category']"),
-                (&js2, "$['store']['book'][1].['category']"),
-                (&js2, "$['store']['book'][2].['category']"),
-                (&js2, "$['store']['book'][3].['category']"),
+                (&js1, "$['store']['book'][0]['category']"),
+                (&js2, "$['store']['book'][1]['category']"),
+                (&js2, "$['store']['book'][2]['category']"),
+                (&js2, "$['store']['book'][3]['category']"),
             ],
         );
         let js1 = json!("Nigel Rees");
@@ -491,8 +607,8 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!("ab"), "$['a']".to_string()).into(),
-                (&json!("bc"), "$['b']".to_string()).into(),
+                (&json!("ab"), "$['a']".to_string()).try_into().unwrap(),
+                (&json!("bc"), "$['b']".to_string()).try_into().unwrap(),
             ]
         );
 
@@ -509,7 +625,12 @@ mod tests {
 
         let vec = js_path("$['a'] \r['b']", &json)?;
 
-        assert_eq!(vec, vec![(&json!("ab"), "$['a']['b']".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("ab"), "$['a']['b']".to_string())
+                .try_into()
+                .unwrap(),]
+        );
 
         Ok(())
     }
@@ -519,7 +640,10 @@ mod tests {
 
         let vec = js_path("$[?search(@\n,'[a-z]+')]", &json)?;
 
-        assert_eq!(vec, vec![(&json!("foo"), "$[0]".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("foo"), "$[0]".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -540,7 +664,9 @@ mod tests {
 
         assert_eq!(
             vec,
-            vec![(&json!({"a":1, "d":"f"}), "$[1]".to_string()).into(),]
+            vec![(&json!({"a":1, "d":"f"}), "$[1]".to_string())
+                .try_into()
+                .unwrap(),]
         );
 
         Ok(())
@@ -567,7 +693,9 @@ mod tests {
 
         assert_eq!(
             vec,
-            vec![(&json!("bab"), "$['values'][2]".to_string()).into(),]
+            vec![(&json!("bab"), "$['values'][2]".to_string())
+                .try_into()
+                .unwrap(),]
         );
 
         Ok(())
@@ -580,7 +708,10 @@ mod tests {
 
         let vec = js_path("$['\\/']", &json)?;
 
-        assert_eq!(vec, vec![(&json!("A"), "$['/']".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['/']".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -590,7 +721,10 @@ mod tests {
 
         let vec = js_path("$[?match(@, '\\\\p{Lu}')]", &json)?;
 
-        assert_eq!(vec, vec![(&json!("Ж"), "$[1]".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("Ж"), "$[1]".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -622,7 +756,11 @@ mod tests {
           "c": 3
         });
 
-        let vec: Vec<String> = json.query_only_path("$[?@<3]")?.into_iter().collect();
+        let vec: Vec<String> = json
+            .query_only_path("$[?@<3]")?
+            .into_iter()
+            .map(String::from)
+            .collect();
 
         assert_eq!(vec, vec!["$['a']".to_string(), "$['b']".to_string()]);
 
@@ -658,7 +796,10 @@ mod tests {
         });
 
         let vec = js_path("$[\"a'\"]", &json)?;
-        assert_eq!(vec, vec![(&json!("A"), "$['a\\'']".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['a\\'']".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -670,9 +811,9 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!(1), "$[1]".to_string()).into(),
-                (&json!(5), "$[5]".to_string()).into(),
-                (&json!(6), "$[6]".to_string()).into(),
+                (&json!(1), "$[1]".to_string()).try_into().unwrap(),
+                (&json!(5), "$[5]".to_string()).try_into().unwrap(),
+                (&json!(6), "$[6]".to_string()).try_into().unwrap(),
             ]
         );
 
@@ -696,8 +837,8 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!(1), "$['o'][1]".to_string()).into(),
-                (&json!(3), "$['o'][2][1]".to_string()).into(),
+                (&json!(1), "$['o'][1]".to_string()).try_into().unwrap(),
+                (&json!(3), "$['o'][2][1]".to_string()).try_into().unwrap(),
             ]
         );
 
@@ -716,7 +857,9 @@ mod tests {
         let vec = js_path("$[?@.absent==@.list[9]]", &json)?;
         assert_eq!(
             vec,
-            vec![(&json!({"list": [1]}), "$[0]".to_string()).into(),]
+            vec![(&json!({"list": [1]}), "$[0]".to_string())
+                .try_into()
+                .unwrap(),]
         );
 
         Ok(())
@@ -730,8 +873,8 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!([2]), "$[2]".to_string()).into(),
-                (&json!({"a": 3}), "$[4]".to_string()).into(),
+                (&json!([2]), "$[2]".to_string()).try_into().unwrap(),
+                (&json!({"a": 3}), "$[4]".to_string()).try_into().unwrap(),
             ]
         );
 
@@ -743,7 +886,10 @@ mod tests {
         let json = json!({ " ": "A"});
 
         let vec = json.query_with_path("$[' ']")?;
-        assert_eq!(vec, vec![(&json!("A"), "$[\' \']".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$[\' \']".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -752,7 +898,10 @@ mod tests {
         let json = json!(["first", "second"]);
 
         let vec = json.query_with_path("$[-2]")?;
-        assert_eq!(vec, vec![(&json!("first"), "$[0]".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!("first"), "$[0]".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -780,8 +929,8 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!([2]), "$[2]").into(),
-                (&json!([2, 3, 4]), "$[3]").into(),
+                (&json!([2]), "$[2]").try_into().unwrap(),
+                (&json!([2, 3, 4]), "$[3]").try_into().unwrap(),
             ]
         );
 
@@ -794,7 +943,10 @@ mod tests {
           "𝄞": "A"
         });
         let vec = json.query_with_path("$['𝄞']")?;
-        assert_eq!(vec, vec![(&json!("A"), "$['𝄞']".to_string()).into()]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['𝄞']".to_string()).try_into().unwrap()]
+        );
 
         Ok(())
     }
@@ -804,7 +956,10 @@ mod tests {
           "\t": "A"
         });
         let vec = json.query_with_path("$['\\t']")?;
-        assert_eq!(vec, vec![(&json!("A"), "$['\\t']".to_string()).into()]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['\\t']".to_string()).try_into().unwrap()]
+        );
 
         Ok(())
     }
@@ -814,7 +969,10 @@ mod tests {
           "☺": "A"
         });
         let vec = json.query_with_path("$['☺']")?;
-        assert_eq!(vec, vec![(&json!("A"), "$['☺']".to_string()).into()]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['☺']".to_string()).try_into().unwrap()]
+        );
 
         Ok(())
     }
@@ -824,7 +982,10 @@ mod tests {
           "\r": "A"
         });
         let vec = json.query_with_path("$['\\r']")?;
-        assert_eq!(vec, vec![(&json!("A"), "$['\\r']".to_string()).into()]);
+        assert_eq!(
+            vec,
+            vec![(&json!("A"), "$['\\r']".to_string()).try_into().unwrap()]
+        );
 
         Ok(())
     }
@@ -946,7 +1107,7 @@ mod tests {
             let paths = json.query_only_path("$[*]")?;
             assert_eq!(paths.len(), 1, "{:?}", name);
 
-            let found: Vec<&Value> = json.query(&paths[0])?;
+            let found: Vec<&Value> = json.query(&paths[0].to_string())?;
             assert_eq!(found, vec![&json!("A")], "{:?} -> {}", name, paths[0]);
             assert_eq!(json.reference(&paths[0]), Some(&json!("A")), "{}", paths[0]);
         }
@@ -992,4 +1153,109 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn query_as_test() -> Queried<()> {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Book {
+            title: String,
+            price: f64,
+        }
+
+        let json = json!({"books": [
+            {"title": "a", "price": 1.5},
+            {"title": "b", "price": 2},
+            {"title": "c"}
+        ]});
+
+        let books: Vec<Book> = json.query_as("$.books[?@.price]")?;
+        assert_eq!(books.len(), 2);
+        assert_eq!(books[1].title, "b");
+
+        let book: Book = json.query_one_as("$.books[0]")?;
+        assert_eq!(book.price, 1.5);
+
+        match json.query_as::<Book>("$.books[*]") {
+            Err(JsonPathError::Deserialization { path, .. }) => {
+                assert_eq!(path, "$['books'][2]")
+            }
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(
+            json.query_one_as::<Book>("$.books[*]"),
+            Err(JsonPathError::MultipleMatches { at_least: 2 })
+        );
+        assert_eq!(
+            json.query_one_as::<Book>("$.shelves"),
+            Err(JsonPathError::NoMatch)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_one_test() -> Queried<()> {
+        let json = json!({"a": [1, 2, 3], "b": {"c": null}});
+
+        assert_eq!(json.query_one("$.b.c")?, &Value::Null);
+        assert_eq!(json.query_opt("$.b.d")?, None);
+        assert_eq!(json.query_opt("$.a[?@ > 2]")?, Some(&json!(3)));
+        assert_eq!(json.query_one("$.b.d"), Err(JsonPathError::NoMatch));
+        assert_eq!(
+            json.query_one("$.a[1:]"),
+            Err(JsonPathError::MultipleMatches { at_least: 2 })
+        );
+        assert_eq!(
+            json.query_opt("$.a[*]"),
+            Err(JsonPathError::MultipleMatches { at_least: 2 })
+        );
+
+        assert!(json.exists("$..c")?);
+        assert!(!json.exists("$.a[?@ > 3]")?);
+        assert!(json.exists("$[").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn single_match_stops_at_the_second_one() -> Queried<()> {
+        let json = Value::Array((0..1000).map(Value::from).collect());
+        let limits = Limits::default().max_results(2);
+        let mut matches = js_path_iter("$[*]", &json)?.with_limits(&limits);
+
+        assert_eq!(
+            single(matches.by_ref()).err(),
+            Some(JsonPathError::MultipleMatches { at_least: 2 })
+        );
+        // a third match would exceed the limit and abort the evaluation
+        matches.check()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient_regex_mode() -> Queried<()> {
+        let doc = json!({"p": "\\w+", "l": ["12", "ab", "1a", "A"]});
+        let lenient = ParserOptions::default().regex_mode(RegexMode::Lenient);
+        let compile = |query: &str, options: &ParserOptions| {
+            CompiledQuery::<Value>::builder(query)
+                .parser_options(options.clone())
+                .build()
+        };
+
+        let query = "$.l[?match(@, '\\\\d+') || search(@, '(?i)^a$')]";
+        assert!(parse_json_path(query).is_err());
+        assert!(parse_json_path_with(query, &lenient).is_ok());
+        let query = compile(query, &lenient)?;
+        assert_eq!(query.query_only_path(&doc)?, vec!["$['l'][0]", "$['l'][3]"]);
+        assert_eq!(query.iter(&doc).count(), 2);
+
+        // the patterns from the document are read in the mode of the query too
+        let query = compile("$.l[?match(@, $.p)]", &lenient)?;
+        assert_eq!(query.query(&doc)?.len(), 4);
+        let query = compile("$.l[?match(@, $.p)]", &ParserOptions::default())?;
+        assert!(query.query(&doc)?.is_empty());
+
+        Ok(())
+    }
 }
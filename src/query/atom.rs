@@ -17,38 +17,29 @@ impl Query for FilterAtom {
             FilterAtom::Test { expr, not } => {
                 let new_state = |b| State::bool(b, state.root);
                 let res = expr.process(state.clone());
-                if expr.is_res_bool() {
-                    if *not {
-                        invert_bool(res)
+                let struct_check = |s: &T| {
+                    if let Some(arr) = s.as_array() {
+                        !arr.is_empty()
+                    } else if let Some(obj) = s.as_object() {
+                        !obj.is_empty()
+                    } else if let Some(str) = s.as_str() {
+                        !str.is_empty()
                     } else {
-                        res
+                        true
                     }
-                } else {
-                    let struct_check = |s: &T| {
-                        if let Some(arr) = s.as_array() {
-                            !arr.is_empty()
-                        } else if let Some(obj) = s.as_object() {
-                            !obj.is_empty()
-                        } else if let Some(str) = s.as_str() {
-                            !str.is_empty()
-                        } else {
-                            true
-                        }
-                    };
+                };
 
-                    let struct_presented = match res.data {
-                        Data::Ref(v) => struct_check(v.inner),
-                        Data::Refs(e) if e.is_empty() => false,
-                        Data::Refs(elems) => elems.iter().map(|v| v.inner).all(struct_check),
-                        _ => false,
-                    };
+                // a function returning a logical value gives the result of the test right away,
+                // a query or a function returning nodes passes if it selects something
+                let passed = match res.data {
+                    Data::Value(v) if expr.is_res_bool() => v.as_bool().unwrap_or_default(),
+                    Data::Ref(v) => struct_check(v.inner),
+                    Data::Refs(e) if e.is_empty() => false,
+                    Data::Refs(elems) => elems.iter().map(|v| v.inner).all(struct_check),
+                    _ => false,
+                };
 
-                    if struct_presented {
-                        new_state(!*not)
-                    } else {
-                        new_state(*not)
-                    }
-                }
+                new_state(passed != *not)
             }
             FilterAtom::Comparison(cmp) => cmp.process(state),
         }
@@ -1,22 +1,32 @@
 use crate::parser::model::{Comparable, Comparison, Literal, SingularQuery, SingularQuerySegment};
+use crate::query::explain::{trace, TraceKind};
 use crate::query::queryable::Queryable;
 use crate::query::state::{Data, Pointer, State};
 use crate::query::Query;
 
 impl Query for Comparison {
     fn process<'a, T: Queryable>(&self, state: State<'a, T>) -> State<'a, T> {
-        let root = state.root;
-        let (lhs, rhs) = self.vals();
-        let lhs = lhs.process(state.clone());
-        let rhs = rhs.process(state);
-        match self {
-            Comparison::Eq(..) => State::bool(eq(lhs, rhs), root),
-            Comparison::Ne(..) => State::bool(!eq(lhs, rhs), root),
-            Comparison::Gt(..) => State::bool(lt(rhs, lhs), root),
-            Comparison::Gte(..) => State::bool(lt(rhs.clone(), lhs.clone()) || eq(lhs, rhs), root),
-            Comparison::Lt(..) => State::bool(lt(lhs, rhs), root),
-            Comparison::Lte(..) => State::bool(lt(lhs.clone(), rhs.clone()) || eq(lhs, rhs), root),
-        }
+        trace(TraceKind::Comparison, self, state, |state| {
+            let root = state.root;
+            let ctx = state.ctx.clone();
+            let (lhs, rhs) = self.vals();
+            let lhs = lhs.process(state.clone());
+            let rhs = rhs.process(state);
+            ctx.note(&lhs.data);
+            ctx.note(&rhs.data);
+            match self {
+                Comparison::Eq(..) => State::bool(eq(lhs, rhs), root),
+                Comparison::Ne(..) => State::bool(!eq(lhs, rhs), root),
+                Comparison::Gt(..) => State::bool(lt(rhs, lhs), root),
+                Comparison::Gte(..) => {
+                    State::bool(lt(rhs.clone(), lhs.clone()) || eq(lhs, rhs), root)
+                }
+                Comparison::Lt(..) => State::bool(lt(lhs, rhs), root),
+                Comparison::Lte(..) => {
+                    State::bool(lt(lhs.clone(), rhs.clone()) || eq(lhs, rhs), root)
+                }
+            }
+        })
     }
 }
 
@@ -0,0 +1,294 @@
+use crate::parser::functions::BuiltinFunctions;
+use crate::parser::model::JpQuery;
+use crate::parser::options::ParserOptions;
+use crate::parser::{parse_compiled, Parsed};
+use crate::query::explain::{Explanation, Tracer};
+use crate::query::functions::FunctionRegistry;
+use crate::query::iter::QueryIter;
+use crate::query::limits::Limits;
+use crate::query::lines::JsonLines;
+use crate::query::queryable::Queryable;
+use crate::query::regexes::{RegexMode, Regexes};
+use crate::query::state::{Context, State};
+use crate::query::{process_pointers, Queried, QueryPath, QueryRef};
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::sync::Arc;
+
+/// A query parsed once and evaluated against any number of documents.
+///
+/// It owns the parsed [`JpQuery`], the regular expressions of the `match` and `search` calls
+/// with literal patterns, compiled upfront instead of on every evaluation,
+/// and the functions the query can call.
+/// The clones of a compiled query share them.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::compiled::CompiledQuery;
+/// use serde_json::json;
+///
+/// let query = CompiledQuery::new("$.users[?match(@.name, 'a.*')].id").unwrap();
+///
+/// for (doc, expected) in [
+///     (json!({"users": [{"name": "ann", "id": 1}, {"name": "bob", "id": 2}]}), vec![json!(1)]),
+///     (json!({"users": []}), vec![]),
+/// ] {
+///     assert_eq!(query.query(&doc).unwrap(), expected.iter().collect::<Vec<_>>());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CompiledQuery<T: Queryable> {
+    query: Arc<JpQuery>,
+    regexes: Arc<Regexes>,
+    functions: Option<Arc<FunctionRegistry<T>>>,
+    limits: Option<Limits>,
+}
+
+/// The configuration of a [`CompiledQuery`], see [`CompiledQuery::builder`].
+#[derive(Debug, Clone)]
+pub struct CompiledQueryBuilder<T: Queryable> {
+    query: String,
+    functions: Option<FunctionRegistry<T>>,
+    options: ParserOptions,
+    limits: Option<Limits>,
+}
+
+impl<T: Queryable> CompiledQueryBuilder<T> {
+    /// Lets the query call the functions of the registry.
+    pub fn functions(self, functions: FunctionRegistry<T>) -> Self {
+        CompiledQueryBuilder {
+            functions: Some(functions),
+            ..self
+        }
+    }
+
+    /// Sets how many regular expressions built from the document (like `match(@.a, $.pattern)`)
+    /// are kept compiled, the least recently used ones are dropped first.
+    pub fn regex_cache(self, capacity: usize) -> Self {
+        CompiledQueryBuilder {
+            options: self.options.regex_cache(capacity),
+            ..self
+        }
+    }
+
+    /// Sets how the patterns of `match` and `search` are read,
+    /// [`RegexMode::Lenient`] accepts the syntax of the `regex` crate besides I-Regexp.
+    pub fn regex_mode(self, mode: RegexMode) -> Self {
+        CompiledQueryBuilder {
+            options: self.options.regex_mode(mode),
+            ..self
+        }
+    }
+
+    /// Sets the limits and the settings of the parser, see [`ParserOptions`].
+    pub fn parser_options(self, options: ParserOptions) -> Self {
+        CompiledQueryBuilder { options, ..self }
+    }
+
+    /// Bounds every evaluation of the query, see [`Limits`].
+    pub fn limits(self, limits: Limits) -> Self {
+        CompiledQueryBuilder {
+            limits: Some(limits),
+            ..self
+        }
+    }
+
+    /// Parses the query, checks the function calls and compiles the regular expressions.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::parser::parse_json_path_with_functions`].
+    pub fn build(self) -> Parsed<CompiledQuery<T>> {
+        let (query, regexes) = match &self.functions {
+            Some(functions) => parse_compiled(&self.query, functions, &self.options)?,
+            None => parse_compiled(&self.query, &BuiltinFunctions, &self.options)?,
+        };
+        Ok(CompiledQuery {
+            query: Arc::new(query),
+            regexes: Arc::new(regexes),
+            functions: self.functions.map(Arc::new),
+            limits: self.limits,
+        })
+    }
+}
+
+impl<T: Queryable> CompiledQuery<T> {
+    /// Compiles the query with the builtin functions only.
+    pub fn new(query: &str) -> Parsed<Self> {
+        CompiledQuery::builder(query).build()
+    }
+
+    pub fn builder(query: &str) -> CompiledQueryBuilder<T> {
+        CompiledQueryBuilder {
+            query: query.to_string(),
+            functions: None,
+            options: ParserOptions::default(),
+            limits: None,
+        }
+    }
+
+    /// The parsed query.
+    pub fn as_query(&self) -> &JpQuery {
+        &self.query
+    }
+
+    fn context(&self) -> Context<'_, T> {
+        let ctx = match &self.functions {
+            Some(functions) => Context::with_functions(functions),
+            None => Context::default(),
+        };
+        let ctx = ctx.with_regexes(self.regexes.clone());
+        match &self.limits {
+            Some(limits) => ctx.with_limits(limits),
+            None => ctx,
+        }
+    }
+
+    /// Evaluates the query and returns the matches with their paths.
+    pub fn query_with_path<'a>(&'a self, value: &'a T) -> Queried<Vec<QueryRef<'a, T>>> {
+        let state = State::root(value).with_context(self.context());
+        Ok(process_pointers(&self.query, state)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Evaluates the query and returns the matches.
+    pub fn query<'a>(&'a self, value: &'a T) -> Queried<Vec<&'a T>> {
+        let state = State::root(value).with_context(self.context());
+        Ok(process_pointers(&self.query, state)?
+            .into_iter()
+            .map(|p| p.inner)
+            .collect())
+    }
+
+    /// Evaluates the query and returns the paths of the matches.
+    pub fn query_only_path(&self, value: &T) -> Queried<Vec<QueryPath>> {
+        Ok(self
+            .query_with_path(value)?
+            .into_iter()
+            .map(|r| r.path)
+            .collect())
+    }
+
+    /// Evaluates the query lazily, see [`QueryIter`].
+    /// When a limit is exceeded the iteration ends early and [`QueryIter::check`] returns the error.
+    pub fn iter<'a>(&'a self, value: &'a T) -> QueryIter<'a, 'a, T> {
+        QueryIter::new(Cow::Borrowed(&self.query), value).with_context(self.context())
+    }
+
+    /// Checks whether the query selects anything, stopping at the first match.
+    pub fn exists(&self, value: &T) -> Queried<bool> {
+        let mut iter = self.iter(value);
+        let found = iter.next().is_some();
+        iter.check()?;
+        Ok(found)
+    }
+
+    /// Evaluates the query recording the trace of the evaluation, see [`Explanation`].
+    ///
+    /// The evaluation is sequential and keeps the paths of the data of every step, it is meant for debugging.
+    pub fn explain<'a>(&'a self, value: &'a T) -> Explanation<'a, T> {
+        let tracer = Arc::new(Tracer::default());
+        let state = State::root(value).with_context(self.context().with_trace(tracer.clone()));
+        let result = process_pointers(&self.query, state)
+            .map(|found| found.into_iter().map(Into::into).collect());
+        Explanation {
+            result,
+            trace: tracer.take(),
+        }
+    }
+
+    /// Evaluates the query against every line of JSON Lines, see [`JsonLines`].
+    pub fn query_lines<R: BufRead>(&self, reader: R) -> JsonLines<R, T> {
+        JsonLines::new(self.clone(), reader)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Queryable + Send + Sync> CompiledQuery<T> {
+    /// Evaluates the query processing the children of the big arrays and objects on several threads,
+    /// see [`Context::with_parallel`]. The matches come in the same order as with [`CompiledQuery::query_with_path`].
+    pub fn par_query_with_path<'a>(&'a self, value: &'a T) -> Queried<Vec<QueryRef<'a, T>>> {
+        let state = State::root(value).with_context(self.context().with_parallel());
+        Ok(process_pointers(&self.query, state)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Evaluates the query on several threads and returns the matches,
+    /// see [`CompiledQuery::par_query_with_path`].
+    pub fn par_query<'a>(&'a self, value: &'a T) -> Queried<Vec<&'a T>> {
+        Ok(self
+            .par_query_with_path(value)?
+            .into_iter()
+            .map(|r| r.val())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::functions::{FunctionSignature, FunctionType};
+    use crate::query::compiled::CompiledQuery;
+    use crate::query::functions::{FnValue, FunctionRegistry};
+    use crate::query::regexes::{RegexMode, Regexes};
+    use crate::query::Queried;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn compiled_regexes() -> Queried<()> {
+        let query = CompiledQuery::<Value>::new(
+            "$[?match(@.a, 'x+') || search(@[0], '[0-9]') || match(@.a, $.p)]",
+        )?;
+        assert_eq!(query.regexes.literals.len(), 2);
+
+        let doc = json!({"p": "y", "l": [{"a": "xx"}, {"a": "xxa"}, ["b1"], {"a": "y"}]});
+        let found = query.query_only_path(&doc["l"])?;
+        assert_eq!(found, vec!["$[0]", "$[2]"]);
+        assert!(query.exists(&doc["l"])?);
+        #[cfg(feature = "rayon")]
+        assert_eq!(query.par_query(&doc["l"])?, query.query(&doc["l"])?);
+        assert!(!query.exists(&json!([]))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compiled_functions() -> Queried<()> {
+        let mut functions = FunctionRegistry::<Value>::new();
+        functions.register(
+            "even",
+            FunctionSignature::new(vec![FunctionType::Value], FunctionType::Logical),
+            |args| FnValue::Logical(args[0].as_value().and_then(|v| v.as_i64()) == Some(2)),
+        )?;
+
+        assert!(CompiledQuery::<Value>::new("$[?even(@)]").is_err());
+        let query = CompiledQuery::builder("$[?even(@)]")
+            .functions(functions)
+            .build()?;
+        let doc = json!([1, 2, 3]);
+        assert_eq!(query.query(&doc)?, vec![&json!(2)]);
+        assert_eq!(query.iter(&doc).count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn compiled_regex_modes() -> Queried<()> {
+        let doc = json!(["a\r", "A", "^a$", "ab"]);
+
+        let query = CompiledQuery::<Value>::new("$[?match(@, 'a.') || match(@, '^a$')]")?;
+        assert_eq!(query.query_only_path(&doc)?, vec!["$[2]", "$[3]"]);
+        assert!(CompiledQuery::<Value>::new("$[?search(@, '(?i)a')]").is_err());
+
+        let query = CompiledQuery::builder("$[?search(@, '(?i)^a$')]")
+            .regex_mode(RegexMode::Lenient)
+            .build()?;
+        assert_eq!(query.query_only_path(&doc)?, vec!["$[1]"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,411 @@
+use crate::query::queryable::Queryable;
+use crate::query::state::{Data, Pointer, State};
+use crate::query::{Queried, QueryPath, QueryRef};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt::{Display, Formatter};
+use std::sync::Mutex;
+
+/// How long a value gets in the text of a trace before it is cut.
+const MAX_TEXT_VALUE: usize = 64;
+/// How many nodes of a set get in the text of a trace.
+const MAX_TEXT_NODES: usize = 4;
+
+/// The kind of the evaluated expression of a [`TraceNode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TraceKind {
+    /// A segment of the query or of a query in a filter, applied to a set of nodes.
+    Segment,
+    /// A logical expression of a filter, applied to a single node.
+    Filter,
+    /// A comparison, the operands are the values compared.
+    Comparison,
+    /// A function call.
+    Function,
+}
+
+impl Display for TraceKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceKind::Segment => write!(f, "segment"),
+            TraceKind::Filter => write!(f, "filter"),
+            TraceKind::Comparison => write!(f, "comparison"),
+            TraceKind::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// The data an expression is given or gives back, the nodes are borrowed from the document
+/// and serialized only when the trace is rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Traced<'a, T: Queryable> {
+    /// The nodes with their paths, the nodes inside a filter have no path.
+    Nodes(Vec<(Option<QueryPath>, &'a T)>),
+    /// A value computed by the expression.
+    Value(T),
+    Nothing,
+}
+
+impl<'a, T: Queryable> From<&Data<'a, T>> for Traced<'a, T> {
+    fn from(data: &Data<'a, T>) -> Self {
+        let node = |p: &Pointer<'a, T>| {
+            let path = (!p.path.in_filter()).then(|| p.path.to_path());
+            (path, p.inner)
+        };
+        match data {
+            Data::Ref(p) => Traced::Nodes(vec![node(p)]),
+            Data::Refs(ps) => Traced::Nodes(ps.iter().map(node).collect()),
+            Data::Value(v) => Traced::Value(v.clone()),
+            Data::Nothing => Traced::Nothing,
+        }
+    }
+}
+
+impl<T: Queryable + Serialize> Traced<'_, T> {
+    /// `{"nodes": [{"path": .., "value": ..}]}`, `{"value": ..}` or `null`.
+    pub fn to_json(&self) -> Value {
+        match self {
+            Traced::Nodes(nodes) => json!({
+                "nodes": nodes
+                    .iter()
+                    .map(|(path, value)| json!({
+                        "path": path.as_ref().map(|p| p.to_string()),
+                        "value": to_json(value),
+                    }))
+                    .collect::<Vec<_>>()
+            }),
+            Traced::Value(value) => json!({ "value": to_json(value) }),
+            Traced::Nothing => Value::Null,
+        }
+    }
+}
+
+impl<T: Queryable + Serialize> Display for Traced<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let text = |value: &T| {
+            let text = to_json(value).to_string();
+            match text.char_indices().nth(MAX_TEXT_VALUE) {
+                Some((cut, _)) => format!("{}...", &text[..cut]),
+                None => text,
+            }
+        };
+        match self {
+            Traced::Nodes(nodes) => {
+                let mut shown = nodes
+                    .iter()
+                    .take(MAX_TEXT_NODES)
+                    .map(|(path, value)| match path {
+                        Some(path) => format!("{}={}", path, text(value)),
+                        None => text(value),
+                    })
+                    .collect::<Vec<_>>();
+                if nodes.len() > MAX_TEXT_NODES {
+                    shown.push(format!("{} more", nodes.len() - MAX_TEXT_NODES));
+                }
+                write!(f, "[{}]", shown.join(", "))
+            }
+            Traced::Value(value) => write!(f, "{}", text(value)),
+            Traced::Nothing => write!(f, "nothing"),
+        }
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+/// An expression evaluated on the way to the result of a query, see [`Explanation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceNode<'a, T: Queryable> {
+    pub kind: TraceKind,
+    /// The expression as it is written in the query.
+    pub expr: String,
+    /// The data the expression is applied to, `@` for the filters, comparisons and functions.
+    pub input: Traced<'a, T>,
+    /// The operands of a comparison, evaluated.
+    pub operands: Vec<Traced<'a, T>>,
+    pub output: Traced<'a, T>,
+    /// The logical outcome of a filter, a comparison or a function returning a logical value.
+    pub outcome: Option<bool>,
+    /// The expressions evaluated as a part of this one, in the order of the evaluation.
+    pub children: Vec<TraceNode<'a, T>>,
+}
+
+impl<T: Queryable + Serialize> TraceNode<'_, T> {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "kind": self.kind.to_string(),
+            "expr": self.expr,
+            "input": self.input.to_json(),
+            "operands": self.operands.iter().map(Traced::to_json).collect::<Vec<_>>(),
+            "output": self.output.to_json(),
+            "outcome": self.outcome,
+            "children": self.children.iter().map(TraceNode::to_json).collect::<Vec<_>>(),
+        })
+    }
+
+    fn write(&self, f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+        write!(
+            f,
+            "{:indent$}{} {}",
+            "",
+            self.kind,
+            self.expr,
+            indent = indent * 2
+        )?;
+        if !self.operands.is_empty() {
+            let operands = self.operands.iter().map(|o| o.to_string());
+            write!(f, " with {}", operands.collect::<Vec<_>>().join(" and "))?;
+        }
+        writeln!(f, ": {} -> {}", self.input, self.output)?;
+        self.children
+            .iter()
+            .try_for_each(|c| c.write(f, indent + 1))
+    }
+}
+
+/// The result of a query with the trace of its evaluation, see [`crate::query::compiled::CompiledQuery::explain`].
+///
+/// The trace is a tree of the segments, the filters, the comparisons and the function calls
+/// in the order they are evaluated, with the data every one of them gets and gives back.
+/// It is rendered as an indented text by [`Display`] and as JSON by [`Explanation::to_json`].
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::compiled::CompiledQuery;
+/// use jsonpath_rust::query::explain::TraceKind;
+/// use serde_json::json;
+///
+/// let query = CompiledQuery::new("$.items[?@.price < $.limit && match(@.sku, 'A.*')]").unwrap();
+/// let doc = json!({"limit": 10, "items": [{"price": 12, "sku": "A1"}, {"price": 5, "sku": "B1"}]});
+///
+/// let explained = query.explain(&doc);
+/// assert_eq!(explained.result, Ok(vec![]));
+///
+/// // the first item fails the comparison, the second one the pattern
+/// let items = &explained.trace[1].children;
+/// let failed = items
+///     .iter()
+///     .map(|item| item.children.last().map(|c| (c.children[0].kind, c.outcome)))
+///     .collect::<Vec<_>>();
+/// assert_eq!(failed, vec![
+///     Some((TraceKind::Comparison, Some(false))),
+///     Some((TraceKind::Function, Some(false))),
+/// ]);
+///
+/// println!("{}", explained);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation<'a, T: Queryable> {
+    /// The matches, the same as [`crate::query::compiled::CompiledQuery::query_with_path`] gives.
+    pub result: Queried<Vec<QueryRef<'a, T>>>,
+    /// The segments of the query with the expressions evaluated in them.
+    pub trace: Vec<TraceNode<'a, T>>,
+}
+
+impl<'a, T: Queryable + Serialize> Explanation<'a, T> {
+    /// `{"result": [{"path": .., "value": ..}] or {"error": ..}, "trace": [..]}`.
+    pub fn to_json(&self) -> Value {
+        let result = match &self.result {
+            Ok(found) => json!(found
+                .iter()
+                .map(|r| json!({"path": r.path.to_string(), "value": to_json(r.val)}))
+                .collect::<Vec<_>>()),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        json!({
+            "result": result,
+            "trace": self.trace.iter().map(TraceNode::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl<'a, T: Queryable + Serialize> Display for Explanation<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.trace.iter().try_for_each(|node| node.write(f, 0))?;
+        match &self.result {
+            Ok(found) => writeln!(f, "{} matches", found.len()),
+            Err(e) => writeln!(f, "error: {}", e),
+        }
+    }
+}
+
+/// The trace of an evaluation being recorded, it is shared by the states of the evaluation.
+///
+/// Every expression opens a frame collecting the expressions evaluated inside of it,
+/// so the evaluation is sequential when it is traced.
+#[derive(Debug)]
+pub struct Tracer<'a, T: Queryable> {
+    frames: Mutex<Vec<Frame<'a, T>>>,
+}
+
+#[derive(Debug)]
+struct Frame<'a, T: Queryable> {
+    operands: Vec<Traced<'a, T>>,
+    children: Vec<TraceNode<'a, T>>,
+}
+
+impl<T: Queryable> Default for Frame<'_, T> {
+    fn default() -> Self {
+        Frame {
+            operands: vec![],
+            children: vec![],
+        }
+    }
+}
+
+impl<T: Queryable> Default for Tracer<'_, T> {
+    fn default() -> Self {
+        Tracer {
+            frames: Mutex::new(vec![Frame::default()]),
+        }
+    }
+}
+
+impl<'a, T: Queryable> Tracer<'a, T> {
+    /// Records an operand of the expression being evaluated.
+    pub(crate) fn note(&self, data: &Data<'a, T>) {
+        if let Some(frame) = self.lock().last_mut() {
+            frame.operands.push(data.into())
+        }
+    }
+
+    /// The expressions recorded at the top.
+    pub(crate) fn take(&self) -> Vec<TraceNode<'a, T>> {
+        self.lock()
+            .first_mut()
+            .map(|frame| std::mem::take(&mut frame.children))
+            .unwrap_or_default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Frame<'a, T>>> {
+        // the frames are consistent even if an evaluation panicked
+        self.frames.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Evaluates an expression, recording it in the trace if the evaluation is traced.
+pub(crate) fn trace<'a, T, E, F>(
+    kind: TraceKind,
+    expr: &E,
+    state: State<'a, T>,
+    f: F,
+) -> State<'a, T>
+where
+    T: Queryable,
+    E: Display + ?Sized,
+    F: FnOnce(State<'a, T>) -> State<'a, T>,
+{
+    let Some(tracer) = state.ctx.trace.clone() else {
+        return f(state);
+    };
+    let input = Traced::from(&state.data);
+    tracer.lock().push(Frame::default());
+    let state = f(state);
+    let output = Traced::from(&state.data);
+    let outcome = match &state.data {
+        Data::Value(v) if kind != TraceKind::Segment => v.as_bool(),
+        _ => None,
+    };
+    let mut frames = tracer.lock();
+    let frame = frames.pop().unwrap_or_default();
+    if let Some(parent) = frames.last_mut() {
+        parent.children.push(TraceNode {
+            kind,
+            expr: expr.to_string(),
+            input,
+            operands: frame.operands,
+            output,
+            outcome,
+            children: frame.children,
+        });
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::compiled::CompiledQuery;
+    use crate::query::explain::{TraceKind, Traced};
+    use crate::query::Queried;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn trace_tree() -> Queried<()> {
+        let doc =
+            json!({"limit": 10, "items": [{"price": 12, "sku": "A1"}, {"price": 5, "sku": "A2"}]});
+        let query = CompiledQuery::new("$.items[?@.price < $.limit && match(@.sku, 'A.*')].sku")?;
+
+        let explained = query.explain(&doc);
+        assert_eq!(explained.result, query.query_with_path(&doc));
+        let kinds = explained.trace.iter().map(|n| n.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![TraceKind::Segment; 3]);
+
+        let items = &explained.trace[1].children;
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|item| item.kind == TraceKind::Filter));
+        assert_eq!(items[0].outcome, Some(false));
+        assert_eq!(
+            items[0].children.len(),
+            1,
+            "`&&` stops at the first failure"
+        );
+        assert_eq!(items[1].outcome, Some(true));
+        assert_eq!(
+            items[1].input,
+            Traced::Nodes(vec![(
+                Some("$['items'][1]".try_into()?),
+                &json!({"price": 5, "sku": "A2"})
+            )])
+        );
+        // the nodes are borrowed from the document
+        assert!(
+            matches!(&items[1].input, Traced::Nodes(nodes) if std::ptr::eq(nodes[0].1, &doc["items"][1]))
+        );
+
+        let comparison = &items[0].children[0].children[0];
+        assert_eq!(comparison.kind, TraceKind::Comparison);
+        assert_eq!(
+            comparison.operands,
+            vec![
+                Traced::Nodes(vec![(None, &json!(12))]),
+                Traced::Nodes(vec![(Some("$['limit']".try_into()?), &json!(10))])
+            ]
+        );
+        let function = &items[1].children[1].children[0];
+        assert_eq!(function.kind, TraceKind::Function);
+        assert_eq!(function.outcome, Some(true));
+        assert_eq!(function.children[0].kind, TraceKind::Segment);
+
+        let text = explained.to_string();
+        assert!(text.contains("  filter @.price < $.limit && "), "{}", text);
+        assert!(text.contains("with [12] and [$['limit']=10]"), "{}", text);
+        assert!(text.ends_with("1 matches\n"), "{}", text);
+
+        Ok(())
+    }
+
+    #[test]
+    fn trace_json() -> Queried<()> {
+        let doc = json!({"a": [1, 2]});
+        let query = CompiledQuery::<Value>::new("$.a[?@ > 1]")?;
+        let explained = query.explain(&doc);
+
+        let json = explained.to_json();
+        assert_eq!(json["result"], json!([{"path": "$['a'][1]", "value": 2}]));
+        let filter = &json["trace"][1]["children"][0];
+        assert_eq!(filter["kind"], json!("filter"));
+        assert_eq!(filter["outcome"], json!(false));
+        assert_eq!(
+            filter["input"],
+            json!({"nodes": [{"path": "$['a'][0]", "value": 1}]})
+        );
+        assert_eq!(
+            filter["children"][0]["operands"],
+            json!([{"nodes": [{"path": null, "value": 1}]}, {"value": 1}])
+        );
+
+        Ok(())
+    }
+}
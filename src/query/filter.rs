@@ -1,39 +1,49 @@
 use crate::parser::model::Filter;
+use crate::query::explain::{trace, TraceKind};
+use crate::query::parallel::{map_ordered, run_ordered, Task};
 use crate::query::queryable::Queryable;
-use crate::query::state::{Data, Pointer, State};
+use crate::query::state::{Context, Data, Pointer, State};
 use crate::query::Query;
 
 impl Query for Filter {
     fn process<'a, T: Queryable>(&self, state: State<'a, T>) -> State<'a, T> {
         let root = state.root;
-        state.flat_map(|p| {
-            if p.is_internal() {
-                Data::Value(self.filter_item(p, root).into())
-            } else if let Some(items) = p.inner.as_array() {
-                Data::Refs(
-                    items
-                        .into_iter()
-                        .enumerate()
-                        .filter(|(_, item)| self.filter_item(Pointer::empty(*item), root))
-                        .map(|(idx, item)| Pointer::idx(item, p.path.clone(), idx))
-                        .collect(),
-                )
-            } else if let Some(items) = p.inner.as_object() {
-                Data::Refs(
-                    items
-                        .into_iter()
-                        .filter(|(_, item)| self.filter_item(Pointer::empty(*item), root))
-                        .map(|(key, item)| Pointer::key(item, p.path.clone(), key))
-                        .collect(),
-                )
-            } else {
-                return Data::Nothing;
-            }
-        })
+        state.run(Task::Filter(self, root))
     }
 }
 
 impl Filter {
+    /// The children of the node the filter selects,
+    /// or whether the filter holds on the node if it is the `@` of an enclosing filter.
+    pub(crate) fn children<'a, T: Queryable>(
+        &self,
+        p: Pointer<'a, T>,
+        root: &'a T,
+        ctx: &Context<'a, T>,
+    ) -> Data<'a, T> {
+        if p.is_internal() {
+            Data::Value(self.filter_item(p, root, ctx).into())
+        } else if let Some(items) = p.inner.as_array() {
+            let items = items.iter().enumerate().map(|(idx, item)| (item, idx));
+            self.filter_items(
+                items.collect(),
+                |item, idx| Pointer::idx(item, p.path.clone(), idx),
+                root,
+                ctx,
+            )
+        } else if let Some(items) = p.inner.as_object() {
+            let items = items.into_iter().map(|(key, item)| (item, key));
+            self.filter_items(
+                items.collect(),
+                |item, key| Pointer::key(item, p.path.clone(), key),
+                root,
+                ctx,
+            )
+        } else {
+            Data::Nothing
+        }
+    }
+
     fn process_elem<'a, T: Queryable>(&self, state: State<'a, T>) -> State<'a, T> {
         let process_cond = |filter: &Filter| {
             filter
@@ -49,11 +59,68 @@ impl Filter {
         }
     }
 
-    fn filter_item<'a, T: Queryable>(&self, item: Pointer<'a, T>, root: &T) -> bool {
-        self.process_elem(State::data(root, Data::Ref(item.clone())))
-            .ok_val()
-            .and_then(|v| v.as_bool())
-            .unwrap_or_default()
+    /// Checks the filter on a node that is also the root, see [`crate::query::stream`].
+    pub(crate) fn test_node<'a, T: Queryable>(&self, node: &'a T, ctx: &Context<'a, T>) -> bool {
+        self.filter_item(Pointer::empty(node), node, ctx)
+    }
+
+    /// The children the filter selects, in their order, `pointer` points to a child by its key or index.
+    /// Only the children that are kept get their pointers, unless the trace shows the path of every one.
+    fn filter_items<'a, T: Queryable, K>(
+        &self,
+        items: Vec<(&'a T, K)>,
+        pointer: impl Fn(&'a T, K) -> Pointer<'a, T>,
+        root: &'a T,
+        ctx: &Context<'a, T>,
+    ) -> Data<'a, T> {
+        let task = Task::Keep(self, root);
+        if ctx.trace.is_some() {
+            let items = items.into_iter().map(|(item, k)| pointer(item, k));
+            return run_ordered(task, Data::Refs(items.collect()), ctx);
+        }
+        let nodes = items.iter().map(|(item, _)| Pointer::empty(*item));
+        let kept = map_ordered(task, nodes.collect(), ctx);
+        Data::Refs(
+            items
+                .into_iter()
+                .zip(kept)
+                .filter(|(_, kept)| matches!(kept, Data::Ref(_)))
+                .map(|((item, k), _)| pointer(item, k))
+                .collect(),
+        )
+    }
+
+    /// The child if the filter holds on it.
+    pub(crate) fn keep<'a, T: Queryable>(
+        &self,
+        item: Pointer<'a, T>,
+        root: &'a T,
+        ctx: &Context<'a, T>,
+    ) -> Data<'a, T> {
+        if ctx.visit() && self.filter_item(item.clone(), root, ctx) && ctx.keep() {
+            Data::Ref(item)
+        } else {
+            Data::Nothing
+        }
+    }
+
+    fn filter_item<'a, T: Queryable>(
+        &self,
+        item: Pointer<'a, T>,
+        root: &'a T,
+        ctx: &Context<'a, T>,
+    ) -> bool {
+        let node = item.inner;
+        let state = State::data(root, Data::Ref(item)).with_context(ctx.nested());
+        // the path is there for the trace only, `@` is evaluated as a node of its own
+        trace(TraceKind::Filter, self, state, |state| {
+            self.process_elem(
+                State::data(root, Data::Ref(Pointer::empty(node))).with_context(state.ctx),
+            )
+        })
+        .ok_val()
+        .and_then(|v| v.as_bool())
+        .unwrap_or_default()
     }
 }
 
@@ -69,8 +136,8 @@ mod tests {
         assert_eq!(
             js_path("$.a[? @ > 1]", &json),
             Ok(vec![
-                (&json!(2), "$['a'][1]".to_string()).into(),
-                (&json!(3), "$['a'][2]".to_string()).into(),
+                (&json!(2), "$['a'][1]".to_string()).try_into().unwrap(),
+                (&json!(3), "$['a'][2]".to_string()).try_into().unwrap(),
             ])
         );
     }
@@ -91,8 +158,12 @@ mod tests {
         assert_eq!(
             js_path("$.a[?@.b]", &json),
             Ok(vec![
-                (&json!({"b":1}), "$['a']['a']".to_string()).into(),
-                (&json!({"b":2}), "$['a']['c']".to_string()).into(),
+                (&json!({"b":1}), "$['a']['a']".to_string())
+                    .try_into()
+                    .unwrap(),
+                (&json!({"b":2}), "$['a']['c']".to_string())
+                    .try_into()
+                    .unwrap(),
             ])
         );
     }
@@ -116,9 +187,15 @@ mod tests {
         assert_eq!(
             js_path("$.a[?@.b || @.b1]", &json),
             Ok(vec![
-                (&json!({"b":1}), "$['a']['a']".to_string()).into(),
-                (&json!({"b":2}), "$['a']['c']".to_string()).into(),
-                (&json!({"b1":3}), "$['a']['d']".to_string()).into(),
+                (&json!({"b":1}), "$['a']['a']".to_string())
+                    .try_into()
+                    .unwrap(),
+                (&json!({"b":2}), "$['a']['c']".to_string())
+                    .try_into()
+                    .unwrap(),
+                (&json!({"b1":3}), "$['a']['d']".to_string())
+                    .try_into()
+                    .unwrap(),
             ])
         );
     }
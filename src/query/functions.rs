@@ -0,0 +1,427 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::functions::{
+    is_function_name, BuiltinFunctions, FunctionSignature, FunctionSignatures, FunctionType,
+    RFC_FUNCTIONS,
+};
+use crate::parser::model::JpQuery;
+use crate::parser::{parse_json_path_with_functions, Parsed};
+use crate::query::queryable::Queryable;
+use crate::query::state::{Data, Pointer};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+/// An argument or a result of a function extension,
+/// one variant per type of the RFC 9535 type system (section 2.4.1).
+#[derive(Debug, Clone, PartialEq)]
+pub enum FnValue<'a, T: Queryable> {
+    /// A value of the `ValueType`, `None` stands for `Nothing`.
+    Value(Option<Cow<'a, T>>),
+    /// A value of the `LogicalType`.
+    Logical(bool),
+    /// A value of the `NodesType`.
+    Nodes(Vec<&'a T>),
+}
+
+impl<'a, T: Queryable> FnValue<'a, T> {
+    /// Returns the value if it is a `ValueType` holding a value.
+    pub fn as_value(&self) -> Option<&T> {
+        match self {
+            FnValue::Value(Some(v)) => Some(v.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Converts the result of an argument expression into the type the parameter is declared with.
+    pub(crate) fn from_data(data: Data<'a, T>, tpe: FunctionType) -> Self {
+        match tpe {
+            FunctionType::Value => FnValue::Value(match data {
+                Data::Value(v) => Some(Cow::Owned(v)),
+                Data::Ref(p) => Some(Cow::Borrowed(p.inner)),
+                Data::Refs(ps) if ps.len() == 1 => Some(Cow::Borrowed(ps[0].inner)),
+                _ => None,
+            }),
+            FunctionType::Logical => FnValue::Logical(match data {
+                Data::Value(v) => v.as_bool().unwrap_or_default(),
+                Data::Ref(_) => true,
+                Data::Refs(ps) => !ps.is_empty(),
+                Data::Nothing => false,
+            }),
+            FunctionType::Nodes => FnValue::Nodes(match data {
+                Data::Ref(p) => vec![p.inner],
+                Data::Refs(ps) => ps.into_iter().map(|p| p.inner).collect(),
+                _ => vec![],
+            }),
+        }
+    }
+
+    pub(crate) fn into_data(self) -> Data<'a, T> {
+        match self {
+            FnValue::Value(Some(v)) => Data::Value(v.into_owned()),
+            FnValue::Value(None) => Data::Nothing,
+            FnValue::Logical(b) => Data::Value(b.into()),
+            FnValue::Nodes(nodes) => Data::Refs(nodes.into_iter().map(Pointer::empty).collect()),
+        }
+    }
+}
+
+type FnBody<T> = Arc<dyn for<'a> Fn(Vec<FnValue<'a, T>>) -> FnValue<'a, T> + Send + Sync>;
+
+struct Function<T: Queryable> {
+    signature: FunctionSignature,
+    body: FnBody<T>,
+}
+
+impl<T: Queryable> Clone for Function<T> {
+    fn clone(&self) -> Self {
+        Function {
+            signature: self.signature.clone(),
+            body: self.body.clone(),
+        }
+    }
+}
+
+/// A set of named function extensions the queries can call in the filters.
+///
+/// Every function declares its signature, that is used by the parser to check the calls,
+/// and gets the arguments converted to the declared types.
+/// The functions of RFC 9535 and the builtin extensions (`in`, `nin`, `none_of`, `any_of`, `subset_of`)
+/// are always available, the latter can be replaced by registering a function with the same name.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::parser::functions::{FunctionSignature, FunctionType};
+/// use jsonpath_rust::query::functions::{FnValue, FunctionRegistry};
+/// use jsonpath_rust::query::js_path_process_with_functions;
+/// use serde_json::{json, Value};
+///
+/// let mut functions = FunctionRegistry::<Value>::new();
+/// functions
+///     .register(
+///         "starts_with",
+///         FunctionSignature::new(vec![FunctionType::Value, FunctionType::Value], FunctionType::Logical),
+///         |args| {
+///             let res = match (args[0].as_value(), args[1].as_value()) {
+///                 (Some(s), Some(p)) => s.as_str().zip(p.as_str()).is_some_and(|(s, p)| s.starts_with(p)),
+///                 _ => false,
+///             };
+///             FnValue::Logical(res)
+///         },
+///     )
+///     .unwrap();
+///
+/// let query = functions.parse("$[?starts_with(@, 'ab')]").unwrap();
+/// let data = json!(["abc", "bcd", "abd"]);
+/// let res = js_path_process_with_functions(&query, &data, &functions).unwrap();
+/// assert_eq!(res.len(), 2);
+/// ```
+pub struct FunctionRegistry<T: Queryable> {
+    functions: HashMap<String, Function<T>>,
+}
+
+impl<T: Queryable> FunctionRegistry<T> {
+    pub fn new() -> Self {
+        FunctionRegistry {
+            functions: HashMap::new(),
+        }
+    }
+
+    /// Adds a function to the registry, replacing the function registered with the same name.
+    ///
+    /// # Errors
+    ///
+    /// The name has to be a valid function name of RFC 9535
+    /// and can not be the name of a function defined by the RFC.
+    pub fn register<F>(&mut self, name: &str, signature: FunctionSignature, body: F) -> Parsed<()>
+    where
+        F: for<'a> Fn(Vec<FnValue<'a, T>>) -> FnValue<'a, T> + Send + Sync + 'static,
+    {
+        if !is_function_name(name) {
+            return Err(JsonPathError::InvalidJsonPath(format!(
+                "Invalid function name `{}`",
+                name
+            )));
+        }
+        if RFC_FUNCTIONS.contains(&name) {
+            return Err(JsonPathError::InvalidJsonPath(format!(
+                "The function `{}` is defined by RFC 9535 and can not be replaced",
+                name
+            )));
+        }
+        self.functions.insert(
+            name.to_string(),
+            Function {
+                signature,
+                body: Arc::new(body),
+            },
+        );
+        Ok(())
+    }
+
+    /// The names of the registered functions, not including the builtin ones.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.functions.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    /// Parses the query accepting the functions of the registry.
+    pub fn parse(&self, query: &str) -> Parsed<JpQuery> {
+        parse_json_path_with_functions(query, self)
+    }
+
+    /// Calls the registered function, falling back to the builtin extensions.
+    pub(crate) fn call<'a>(&self, name: &str, args: Vec<FnValue<'a, T>>) -> FnValue<'a, T> {
+        match self.functions.get(name) {
+            Some(f) => (f.body)(args),
+            None => builtin(name, args),
+        }
+    }
+}
+
+impl<T: Queryable> FunctionSignatures for FunctionRegistry<T> {
+    fn signature(&self, name: &str) -> Option<FunctionSignature> {
+        self.functions
+            .get(name)
+            .map(|f| f.signature.clone())
+            .or_else(|| BuiltinFunctions.signature(name))
+    }
+}
+
+impl<T: Queryable> Default for FunctionRegistry<T> {
+    fn default() -> Self {
+        FunctionRegistry::new()
+    }
+}
+
+impl<T: Queryable> Clone for FunctionRegistry<T> {
+    fn clone(&self) -> Self {
+        FunctionRegistry {
+            functions: self.functions.clone(),
+        }
+    }
+}
+
+impl<T: Queryable> Debug for FunctionRegistry<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.names()
+                    .into_iter()
+                    .map(|n| (n, self.functions[n].signature.to_string())),
+            )
+            .finish()
+    }
+}
+
+/// The extensions shipped with the library.
+///
+/// * `in` - Checks if the first argument is in the array provided as the second argument.
+/// * `nin` - Checks if the first argument is not in the array provided as the second argument.
+/// * `none_of` - Checks if none of the elements in the first array are in the second array.
+/// * `any_of` - Checks if any of the elements in the first array are in the second array.
+/// * `subset_of` - Checks if all elements in the first array are in the second array.
+///
+/// The result is `null` if an argument is `Nothing` or not an array where an array is expected.
+pub(crate) fn builtin<'a, T: Queryable>(name: &str, args: Vec<FnValue<'a, T>>) -> FnValue<'a, T> {
+    let null = || FnValue::Value(Some(Cow::Owned(T::null())));
+    let (lhs, rhs) = match args.as_slice() {
+        [lhs, rhs] => match (lhs.as_value(), rhs.as_value()) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return null(),
+        },
+        _ => return null(),
+    };
+    let contains = |elems: &Vec<T>, elem: &T| elems.iter().any(|e| e == elem);
+
+    let res = match name {
+        "in" => rhs.as_array().map(|rhs| contains(rhs, lhs)),
+        "nin" => rhs.as_array().map(|rhs| !contains(rhs, lhs)),
+        "none_of" => lhs
+            .as_array()
+            .zip(rhs.as_array())
+            .map(|(lhs, rhs)| lhs.iter().all(|e| !contains(rhs, e))),
+        "any_of" => lhs
+            .as_array()
+            .zip(rhs.as_array())
+            .map(|(lhs, rhs)| lhs.iter().any(|e| contains(rhs, e))),
+        "subset_of" => lhs
+            .as_array()
+            .zip(rhs.as_array())
+            .map(|(lhs, rhs)| lhs.iter().all(|e| contains(rhs, e))),
+        _ => None,
+    };
+    res.map_or_else(null, FnValue::Logical)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::errors::JsonPathError;
+    use crate::parser::functions::{FunctionSignature, FunctionType};
+    use crate::parser::parse_json_path;
+    use crate::query::functions::{builtin, FnValue, FunctionRegistry};
+    use crate::query::{js_path, js_path_process_with_functions, QueryRef};
+    use serde_json::{json, Value};
+    use std::borrow::Cow;
+
+    fn registry() -> FunctionRegistry<Value> {
+        let mut functions = FunctionRegistry::<Value>::new();
+        functions
+            .register(
+                "double",
+                FunctionSignature::new(vec![FunctionType::Value], FunctionType::Value),
+                |args| {
+                    FnValue::Value(
+                        args[0]
+                            .as_value()
+                            .and_then(|v| v.as_i64())
+                            .map(|v| Cow::Owned(json!(v * 2))),
+                    )
+                },
+            )
+            .unwrap();
+        functions
+            .register(
+                "longest",
+                FunctionSignature::new(vec![FunctionType::Nodes], FunctionType::Nodes),
+                |args| match args.into_iter().next() {
+                    Some(FnValue::Nodes(nodes)) => FnValue::Nodes(
+                        nodes
+                            .into_iter()
+                            .max_by_key(|n| n.as_str().map(str::len))
+                            .into_iter()
+                            .collect(),
+                    ),
+                    _ => FnValue::Nodes(vec![]),
+                },
+            )
+            .unwrap();
+        functions
+    }
+
+    #[test]
+    fn custom_value_function() {
+        let functions = registry();
+        let json = json!([1, 2, 3]);
+        let query = functions.parse("$[?double(@) > 3]").unwrap();
+
+        assert_eq!(
+            js_path_process_with_functions(&query, &json, &functions),
+            Ok(vec![
+                (&json!(2), "$[1]").try_into().unwrap(),
+                (&json!(3), "$[2]").try_into().unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn custom_nodes_function() {
+        let functions = registry();
+        let json = json!([{"a": ["x", "xyz"]}, {"a": ["x"]}, {"a": []}]);
+        let query = functions.parse("$[?longest(@.a[*])]").unwrap();
+        let res = js_path_process_with_functions(&query, &json, &functions).unwrap();
+
+        assert_eq!(
+            res.into_iter().map(QueryRef::path).collect::<Vec<_>>(),
+            vec!["$[0]", "$[1]"]
+        );
+
+        let query = functions
+            .parse("$[?value(longest(@.a[*])) == 'xyz']")
+            .unwrap();
+        let res = js_path_process_with_functions(&query, &json, &functions).unwrap();
+        assert_eq!(
+            res.into_iter().map(QueryRef::path).collect::<Vec<_>>(),
+            vec!["$[0]"]
+        );
+    }
+
+    #[test]
+    fn unknown_function() {
+        assert_eq!(
+            parse_json_path("$[?double(@) > 3]"),
+            Err(JsonPathError::InvalidJsonPath(
+                "Unknown function `double`".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn arity_and_types() {
+        let functions = registry();
+        assert_eq!(
+            functions.parse("$[?double(@, 1) > 3]"),
+            Err(JsonPathError::InvalidJsonPath(
+                "Invalid number of arguments for the function `double`: expected 1, got 2"
+                    .to_string()
+            ))
+        );
+        assert!(functions.parse("$[?double(@)]").is_err());
+        assert!(functions.parse("$[?in(@, $.a) == true]").is_err());
+    }
+
+    #[test]
+    fn register_rejects_names() {
+        let mut functions = FunctionRegistry::<Value>::new();
+        let sig = FunctionSignature::new(vec![], FunctionType::Logical);
+
+        assert!(functions
+            .register("Upper", sig.clone(), |_| FnValue::Logical(true))
+            .is_err());
+        assert!(functions
+            .register("length", sig.clone(), |_| FnValue::Logical(true))
+            .is_err());
+        assert!(functions
+            .register("in", sig, |_| FnValue::Logical(true))
+            .is_ok());
+        assert_eq!(functions.names(), vec!["in"]);
+    }
+
+    #[test]
+    fn builtin_extensions() {
+        let json = json!({"elems": [1, 2, [1, 3]], "list": [1, 3]});
+
+        assert_eq!(
+            js_path("$.elems[?in(@, $.list)]", &json),
+            Ok(vec![(&json!(1), "$['elems'][0]").try_into().unwrap()])
+        );
+        assert_eq!(
+            js_path("$.elems[?nin(@, $.list)]", &json),
+            Ok(vec![
+                (&json!(2), "$['elems'][1]").try_into().unwrap(),
+                (&json!([1, 3]), "$['elems'][2]").try_into().unwrap()
+            ])
+        );
+        assert_eq!(
+            js_path("$.elems[?subset_of(@, $.list)]", &json),
+            Ok(vec![(&json!([1, 3]), "$['elems'][2]").try_into().unwrap()])
+        );
+        assert_eq!(
+            js_path("$.elems[?!any_of(@, $.list)]", &json),
+            Ok(vec![
+                (&json!(1), "$['elems'][0]").try_into().unwrap(),
+                (&json!(2), "$['elems'][1]").try_into().unwrap()
+            ])
+        );
+    }
+
+    #[test]
+    fn builtin_extensions_without_arrays() {
+        let value = |v: Value| FnValue::Value(Some(Cow::Owned(v)));
+
+        assert_eq!(
+            builtin::<Value>("in", vec![value(json!(1)), value(json!([1, 2]))]),
+            FnValue::Logical(true)
+        );
+        assert_eq!(
+            builtin::<Value>("in", vec![value(json!(1)), value(json!(2))]),
+            value(Value::Null)
+        );
+        assert_eq!(
+            builtin::<Value>("any_of", vec![FnValue::Value(None), value(json!([1]))]),
+            value(Value::Null)
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::Parsed;
+
+/// Translates an I-Regexp (RFC 9485) into the dialect of the `regex` crate.
+///
+/// The pattern is rejected unless it conforms to the grammar of RFC 9485 (section 5.3).
+/// The translation is unanchored, the callers anchor it for `match`.
+/// The constructs that mean something else in the `regex` crate are translated precisely:
+/// `.` matches any character but `\n` and `\r`,
+/// `^` and `$` are ordinary characters, the groups do not capture.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::iregexp::translate;
+///
+/// assert_eq!(translate("a.b").unwrap(), "a[^\\n\\r]b");
+/// assert_eq!(translate("(ab)+$").unwrap(), "(?:ab)+\\$");
+/// assert!(translate("(?i)a").is_err());
+/// assert!(translate("\\d+").is_err());
+/// ```
+pub fn translate(pattern: &str) -> Parsed<String> {
+    let mut translator = Translator {
+        chars: pattern.chars().collect(),
+        pos: 0,
+        out: String::with_capacity(pattern.len() + 8),
+    };
+    let res = translator.regexp().and_then(|_| match translator.peek() {
+        None => Ok(()),
+        Some(_) => Err("unbalanced `)`".to_string()),
+    });
+    match res {
+        Ok(()) => Ok(translator.out),
+        Err(reason) => Err(JsonPathError::InvalidJsonPath(format!(
+            "`{}` is not an I-Regexp: {} at the position {}",
+            pattern, reason, translator.pos
+        ))),
+    }
+}
+
+struct Translator {
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+}
+
+type Step<T> = Result<T, String>;
+
+impl Translator {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Step<char> {
+        let c = self.peek().ok_or("unexpected end of the pattern")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Step<()> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format!("expected `{}`, got `{}`", expected, c)),
+        }
+    }
+
+    /// `i-regexp = branch *( "|" branch )`
+    fn regexp(&mut self) -> Step<()> {
+        self.branch()?;
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            self.out.push('|');
+            self.branch()?;
+        }
+        Ok(())
+    }
+
+    /// `branch = *piece`
+    fn branch(&mut self) -> Step<()> {
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            self.piece()?;
+        }
+        Ok(())
+    }
+
+    /// `piece = atom [ quantifier ]`
+    fn piece(&mut self) -> Step<()> {
+        self.atom()?;
+        match self.peek() {
+            Some(q @ ('*' | '+' | '?')) => {
+                self.pos += 1;
+                self.out.push(q);
+            }
+            Some('{') => {
+                self.pos += 1;
+                let min = self.quant_exact()?;
+                let max = if self.peek() == Some(',') {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some('}') => None,
+                        _ => Some(self.quant_exact()?),
+                    }
+                } else {
+                    Some(min)
+                };
+                self.expect('}')?;
+                match max {
+                    Some(max) if max < min => {
+                        return Err(format!("the range {{{},{}}} is empty", min, max))
+                    }
+                    Some(max) if max == min => self.out.push_str(&format!("{{{}}}", min)),
+                    Some(max) => self.out.push_str(&format!("{{{},{}}}", min, max)),
+                    None => self.out.push_str(&format!("{{{},}}", min)),
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// `QuantExact = 1*%x30-39`
+    fn quant_exact(&mut self) -> Step<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err("expected a number of repetitions".to_string());
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .map_err(|_| "too many repetitions".to_string())
+    }
+
+    /// `atom = NormalChar / charClass / ( "(" i-regexp ")" )`
+    fn atom(&mut self) -> Step<()> {
+        match self.next()? {
+            '(' => {
+                self.out.push_str("(?:");
+                self.regexp()?;
+                self.expect(')')?;
+                self.out.push(')');
+            }
+            '.' => self.out.push_str("[^\\n\\r]"),
+            '\\' => {
+                let escaped = self.escape()?;
+                self.out.push_str(&escaped);
+            }
+            '[' => self.class()?,
+            c @ ('*' | '+' | '?' | '{') => {
+                return Err(format!("the quantifier `{}` does not follow an atom", c))
+            }
+            c @ (']' | '}') => return Err(format!("unescaped `{}`", c)),
+            c => self.out.push_str(&regex::escape(&c.to_string())),
+        }
+        Ok(())
+    }
+
+    /// The escape after `\`: `SingleCharEsc` or `charClassEsc`, translated.
+    fn escape(&mut self) -> Step<String> {
+        match self.next()? {
+            c @ ('p' | 'P') => {
+                self.expect('{')?;
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != '}') {
+                    self.pos += 1;
+                }
+                let property: String = self.chars[start..self.pos].iter().collect();
+                self.expect('}')?;
+                if !is_category(&property) {
+                    return Err(format!("unknown character property `{}`", property));
+                }
+                Ok(format!("\\{}{{{}}}", c, property))
+            }
+            _ => {
+                self.pos -= 1;
+                self.single_char_esc()
+                    .map(|c| regex::escape(&c.to_string()))
+            }
+        }
+    }
+
+    /// `SingleCharEsc` without the leading `\`, returns the character it stands for.
+    fn single_char_esc(&mut self) -> Step<char> {
+        match self.next()? {
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            c @ ('(' | ')' | '*' | '+' | '-' | '.' | '?' | '[' | '\\' | ']' | '^' | '{' | '|'
+            | '}') => Ok(c),
+            c => Err(format!("invalid escape `\\{}`", c)),
+        }
+    }
+
+    /// `charClassExpr = "[" [ "^" ] ( "-" / CCE1 ) *CCE1 [ "-" ] "]"`, the `[` is consumed.
+    fn class(&mut self) -> Step<()> {
+        self.out.push('[');
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            self.out.push('^');
+        }
+        let mut first = true;
+        loop {
+            match self.peek() {
+                None => return Err("unterminated character class".to_string()),
+                Some(']') if !first => {
+                    self.pos += 1;
+                    self.out.push(']');
+                    return Ok(());
+                }
+                Some('-') if first || self.peek_at(1) == Some(']') => {
+                    self.pos += 1;
+                    self.out.push_str("\\-");
+                }
+                Some('\\') if matches!(self.peek_at(1), Some('p' | 'P')) => {
+                    self.pos += 1;
+                    let escaped = self.escape()?;
+                    self.out.push_str(&escaped);
+                }
+                Some(_) => {
+                    let start = self.class_char()?;
+                    if self.peek() == Some('-') && self.peek_at(1) != Some(']') {
+                        self.pos += 1;
+                        let end = self.class_char()?;
+                        if end < start {
+                            return Err(format!(
+                                "the range `{}-{}` is out of order",
+                                start.escape_debug(),
+                                end.escape_debug()
+                            ));
+                        }
+                        self.out.push_str(&regex::escape(&start.to_string()));
+                        self.out.push('-');
+                        self.out.push_str(&regex::escape(&end.to_string()));
+                    } else {
+                        self.out.push_str(&regex::escape(&start.to_string()));
+                    }
+                }
+            }
+            first = false;
+        }
+    }
+
+    /// `CCchar`, returns the character it stands for.
+    fn class_char(&mut self) -> Step<char> {
+        match self.next()? {
+            '\\' => self.single_char_esc(),
+            c @ ('-' | '[' | ']') => Err(format!("unescaped `{}` in a character class", c)),
+            c => Ok(c),
+        }
+    }
+}
+
+/// `IsCategory` of RFC 9485: a general category or a group of them.
+fn is_category(property: &str) -> bool {
+    let mut chars = property.chars();
+    let subs = match chars.next() {
+        Some('L') => "lmotu",
+        Some('M') => "cen",
+        Some('N') => "dlo",
+        Some('P') => "cdefios",
+        Some('Z') => "lps",
+        Some('S') => "ckmo",
+        Some('C') => "cfno",
+        _ => return false,
+    };
+    match (chars.next(), chars.next()) {
+        (None, _) => true,
+        (Some(sub), None) => subs.contains(sub),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::iregexp::translate;
+
+    #[test]
+    fn translation() {
+        for (pattern, expected) in [
+            ("", ""),
+            ("abc", "abc"),
+            ("a|b|", "a|b|"),
+            ("a.c", "a[^\\n\\r]c"),
+            ("^a$", "\\^a\\$"),
+            ("(a(b))*", "(?:a(?:b))*"),
+            ("a{2}b{2,}c{2,3}", "a{2}b{2,}c{2,3}"),
+            ("\\.\\n\\\\\\{", "\\.\n\\\\\\{"),
+            ("\\p{Lu}\\P{N}", "\\p{Lu}\\P{N}"),
+            ("[a-z0-9_]", "[a-z0-9_]"),
+            ("[^-a\\]-]", "[^\\-a\\]\\-]"),
+            ("[\\p{L}.&~]", "[\\p{L}\\.\\&\\~]"),
+            ("#x ", "\\#x "),
+        ] {
+            assert_eq!(translate(pattern).unwrap(), expected, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn rejection() {
+        for pattern in [
+            "(?i)a", "a*?", "a**", "*a", "\\d", "\\w", "\\s", "\\b", "\\1", "(a", "a)", "[a", "[]",
+            "[z-a]", "[a-b-c]", "a{3,2}", "a{,2}", "a{x}", "]", "}", "\\p{Xx}", "\\p{Lx}", "\\",
+        ] {
+            assert!(translate(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
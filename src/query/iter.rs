@@ -0,0 +1,250 @@
+use crate::parser::model::{JpQuery, Segment};
+use crate::parser::parse_json_path;
+use crate::query::functions::FunctionRegistry;
+use crate::query::limits::Limits;
+use crate::query::queryable::Queryable;
+use crate::query::segment::children;
+use crate::query::state::{Context, Data, Pointer, State};
+use crate::query::{Queried, Query, QueryRef};
+use std::borrow::Cow;
+
+/// A lazy evaluation of a query, yielding the matches one by one in the order of the document.
+///
+/// The segments are applied to one node at a time:
+/// the iterator keeps a stack of the nodes still to be processed, one frame per segment,
+/// so the memory it needs depends on the depth of the query and of the document
+/// (and the size of the containers on the way) rather than on the number of matches.
+/// The descendant segment walks the document in preorder without collecting the descendants upfront.
+///
+/// The matches are the same as the ones of [`crate::query::js_path_process`] and come in the same order.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::js_path_iter;
+/// use serde_json::json;
+///
+/// let data = json!({"a": [1, 2, 3, 4]});
+/// let first = js_path_iter("$..*", &data)
+///     .unwrap()
+///     .take(2)
+///     .map(|r| r.path)
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(first, vec!["$['a']", "$['a'][0]"]);
+/// ```
+pub struct QueryIter<'a, 'q, T: Queryable> {
+    query: Cow<'q, JpQuery>,
+    root: &'a T,
+    ctx: Context<'a, T>,
+    stack: Vec<Frame<'a, T>>,
+}
+
+enum Frame<'a, T: Queryable> {
+    /// The nodes the segment with the index is to be applied to.
+    Nodes {
+        segment: usize,
+        nodes: std::vec::IntoIter<Pointer<'a, T>>,
+    },
+    /// The descendants of the nodes (including themselves) the descendant segment
+    /// with the index is to be applied to, the next one is on the top,
+    /// with how deep below the start of the segment they are.
+    Descendants {
+        segment: usize,
+        pending: Vec<(Pointer<'a, T>, usize)>,
+    },
+}
+
+impl<'a, 'q, T: Queryable> QueryIter<'a, 'q, T> {
+    pub fn new(query: Cow<'q, JpQuery>, root: &'a T) -> Self {
+        let ctx = Context::default().or_default_regexes();
+        QueryIter {
+            query,
+            root,
+            ctx,
+            stack: vec![Frame::Nodes {
+                segment: 0,
+                nodes: vec![Pointer::root(root)].into_iter(),
+            }],
+        }
+    }
+
+    /// Lets the query call the functions of the registry.
+    pub fn with_functions(self, functions: &'a FunctionRegistry<T>) -> Self {
+        self.with_context(Context::with_functions(functions))
+    }
+
+    /// Bounds the evaluation, see [`Limits`].
+    /// The iteration ends early when a bound is exceeded, [`QueryIter::check`] tells why.
+    pub fn with_limits(self, limits: &Limits) -> Self {
+        let ctx = self.ctx.clone().with_limits(limits);
+        self.with_context(ctx)
+    }
+
+    pub(crate) fn with_context(self, ctx: Context<'a, T>) -> Self {
+        let ctx = ctx.or_default_regexes();
+        QueryIter { ctx, ..self }
+    }
+
+    /// Returns [`crate::parser::errors::JsonPathError::EvaluationAborted`]
+    /// if the iteration was ended by the limits rather than by running out of the matches.
+    pub fn check(&self) -> Queried<()> {
+        match &self.ctx.budget {
+            Some(budget) => budget.check(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.ctx
+            .budget
+            .as_ref()
+            .is_some_and(|b| b.aborted().is_some())
+    }
+
+    /// Applies a segment that is not a descendant one to a single node.
+    fn apply(&self, segment: &Segment, node: Pointer<'a, T>) -> Vec<Pointer<'a, T>> {
+        let state = State::data(self.root, Data::Ref(node)).with_context(self.ctx.clone());
+        segment.process(state).ok_ref().unwrap_or_default()
+    }
+}
+
+impl<'a, 'q, T: Queryable> Iterator for QueryIter<'a, 'q, T> {
+    type Item = QueryRef<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let segments = &self.query.segments;
+        loop {
+            if self.is_aborted() {
+                return None;
+            }
+            let ctx = &self.ctx;
+            let next = match self.stack.last_mut()? {
+                Frame::Nodes { segment, nodes } => nodes.next().map(|node| (*segment, node, false)),
+                Frame::Descendants { segment, pending } => pending.pop().map(|(node, depth)| {
+                    let container =
+                        node.inner.as_array().is_some() || node.inner.as_object().is_some();
+                    if ctx.visit() && (!container || ctx.descend(depth)) {
+                        // the children go in reversed, so the first one is the next to visit
+                        pending.extend(children(&node).into_iter().rev().map(|c| (c, depth + 1)));
+                    }
+                    (*segment, node, true)
+                }),
+            };
+
+            let frame = match next {
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+                Some((idx, node, _)) if idx == segments.len() => match &self.ctx.budget {
+                    Some(budget) if !budget.select(1) => return None,
+                    _ => return Some(node.into()),
+                },
+                Some((idx, node, descending)) => match &segments[idx] {
+                    Segment::Descendant(inner) if descending => Frame::Nodes {
+                        segment: idx + 1,
+                        nodes: self.apply(inner, node).into_iter(),
+                    },
+                    Segment::Descendant(_) => Frame::Descendants {
+                        segment: idx,
+                        pending: vec![(node, 0)],
+                    },
+                    segment => Frame::Nodes {
+                        segment: idx + 1,
+                        nodes: self.apply(segment, node).into_iter(),
+                    },
+                },
+            };
+            self.stack.push(frame);
+        }
+    }
+}
+
+/// Evaluates the query lazily, see [`QueryIter`].
+pub fn js_path_process_iter<'a, 'q, T: Queryable>(
+    path: &'q JpQuery,
+    value: &'a T,
+) -> QueryIter<'a, 'q, T> {
+    QueryIter::new(Cow::Borrowed(path), value)
+}
+
+/// Parses the query and evaluates it lazily, see [`QueryIter`].
+pub fn js_path_iter<'a, T: Queryable>(
+    path: &str,
+    value: &'a T,
+) -> Queried<QueryIter<'a, 'static, T>> {
+    Ok(QueryIter::new(Cow::Owned(parse_json_path(path)?), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_json_path;
+    use crate::query::iter::{js_path_iter, js_path_process_iter};
+    use crate::query::js_path;
+    use crate::JsonPath;
+    use serde_json::json;
+
+    #[test]
+    fn same_as_eager() {
+        let json = json!({
+            "store": {
+                "book": [
+                    {"category": "reference", "author": "Nigel Rees", "price": 8.95},
+                    {"category": "fiction", "author": "Evelyn Waugh", "price": 12.99,
+                     "isbn": "0-553-21311-3"},
+                    {"category": "fiction", "author": "J. R. R. Tolkien", "price": 22.99}
+                ],
+                "bicycle": {"color": "red", "price": 19.95}
+            },
+            "expensive": 10,
+            "nested": [[1, [2, 3]], {"a": {"a": [4]}}]
+        });
+
+        for query in [
+            "$",
+            "$.store.book[*].author",
+            "$..author",
+            "$.store.*",
+            "$.store..price",
+            "$..book[2]",
+            "$..book[-1:]",
+            "$..book[0,1]",
+            "$..book[?@.isbn]",
+            "$..book[?@.price < $.expensive].author",
+            "$..*",
+            "$..[0]",
+            "$..a..a",
+            "$.nested..[*]",
+            "$..[?@ > 1]",
+            "$.missing..a",
+            "$['store', 'expensive']..price",
+        ] {
+            let eager = js_path(query, &json).unwrap();
+            let lazy = js_path_iter(query, &json).unwrap().collect::<Vec<_>>();
+            assert_eq!(lazy, eager, "{}", query);
+        }
+    }
+
+    #[test]
+    fn take_first() {
+        let json = json!({"a": (0..1000).map(|i| json!({"b": i})).collect::<Vec<_>>()});
+        let query = parse_json_path("$..b").unwrap();
+        let mut iter = js_path_process_iter(&query, &json);
+
+        assert_eq!(
+            iter.next().map(|r| r.path),
+            Some("$['a'][0]['b']".parse().unwrap())
+        );
+        assert_eq!(iter.next().map(|r| r.val), Some(&json!(1)));
+        assert!(iter.stack.len() <= 4, "the stack grows with the depth only");
+        assert_eq!(iter.count(), 998);
+    }
+
+    #[test]
+    fn query_iter() {
+        let json = json!([1, 2, 3]);
+        let found = json.query_iter("$[?@ > 1]").unwrap().next();
+        assert_eq!(found.map(|r| r.val), Some(&json!(2)));
+    }
+}
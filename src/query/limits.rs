@@ -0,0 +1,358 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::Parsed;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+/// How many nodes are visited between two checks of the clock.
+const CLOCK_PERIOD: usize = 256;
+
+/// The bounds of a query evaluation, all of them are off by default.
+///
+/// When a bound is exceeded the evaluation stops
+/// and returns [`JsonPathError::EvaluationAborted`] with the reason.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::parser::errors::JsonPathError;
+/// use jsonpath_rust::query::compiled::CompiledQuery;
+/// use jsonpath_rust::query::limits::{Abort, Limits};
+/// use serde_json::{json, Value};
+///
+/// let query: CompiledQuery<Value> = CompiledQuery::builder("$..*")
+///     .limits(Limits::default().max_nodes(100))
+///     .build()
+///     .unwrap();
+///
+/// let doc = json!((0..1000).collect::<Vec<_>>());
+/// assert_eq!(
+///     query.query(&doc),
+///     Err(JsonPathError::EvaluationAborted(Abort::Nodes(100)))
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Limits {
+    max_nodes: Option<usize>,
+    max_results: Option<usize>,
+    max_depth: Option<usize>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancellation: Option<CancellationToken>,
+}
+
+impl Limits {
+    /// Bounds the number of nodes the evaluation visits:
+    /// the ones the selectors pick, the descendants walked through and the ones the filters look at.
+    pub fn max_nodes(self, max: usize) -> Self {
+        Limits {
+            max_nodes: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds the number of the matches, the evaluation stops at the first match past the bound.
+    pub fn max_results(self, max: usize) -> Self {
+        Limits {
+            max_results: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds how deep below the node it starts from a descendant segment (`..`) goes.
+    pub fn max_depth(self, max: usize) -> Self {
+        Limits {
+            max_depth: Some(max),
+            ..self
+        }
+    }
+
+    /// Bounds the time every evaluation takes.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Limits {
+            timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// Stops the evaluations that are still running at the moment.
+    pub fn deadline(self, deadline: Instant) -> Self {
+        Limits {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Stops the evaluations when the token is cancelled.
+    pub fn cancellation(self, token: CancellationToken) -> Self {
+        Limits {
+            cancellation: Some(token),
+            ..self
+        }
+    }
+}
+
+/// A flag stopping the evaluations it is given to, see [`Limits::cancellation`].
+///
+/// The clones share the flag, so the token can be cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why the evaluation was stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Abort {
+    /// More than the given number of nodes was visited.
+    Nodes(usize),
+    /// The query selects more than the given number of nodes.
+    Results(usize),
+    /// A descendant segment went deeper than the given depth.
+    Depth(usize),
+    /// The timeout or the deadline is reached.
+    Deadline,
+    /// The cancellation token is cancelled.
+    Cancelled,
+}
+
+impl Display for Abort {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Abort::Nodes(max) => write!(f, "more than {} nodes are visited", max),
+            Abort::Results(max) => write!(f, "more than {} nodes are selected", max),
+            Abort::Depth(max) => write!(f, "the descendants deeper than {} are reached", max),
+            Abort::Deadline => write!(f, "the deadline is reached"),
+            Abort::Cancelled => write!(f, "the evaluation is cancelled"),
+        }
+    }
+}
+
+/// The account of a single evaluation against its [`Limits`].
+///
+/// Once a bound is exceeded, every further step of the evaluation is refused,
+/// so the evaluation winds down quickly and the reason is reported at the end.
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    deadline: Option<Instant>,
+    visited: AtomicUsize,
+    results: AtomicUsize,
+    aborted: OnceLock<Abort>,
+}
+
+impl Budget {
+    /// Starts the evaluation, the timeout runs from now.
+    pub fn new(limits: &Limits) -> Self {
+        let timeout = limits.timeout.map(|t| Instant::now() + t);
+        Budget {
+            limits: limits.clone(),
+            deadline: match (timeout, limits.deadline) {
+                (Some(lhs), Some(rhs)) => Some(lhs.min(rhs)),
+                (lhs, rhs) => lhs.or(rhs),
+            },
+            visited: AtomicUsize::new(0),
+            results: AtomicUsize::new(0),
+            aborted: OnceLock::new(),
+        }
+    }
+
+    /// The reason the evaluation was stopped for, if it was.
+    pub fn aborted(&self) -> Option<Abort> {
+        self.aborted.get().copied()
+    }
+
+    /// Accounts for visiting a node, `false` if the evaluation has to stop.
+    pub(crate) fn visit(&self) -> bool {
+        if self.aborted.get().is_some() {
+            return false;
+        }
+        let visited = self.visited.fetch_add(1, Ordering::Relaxed) + 1;
+        match self.limits.max_nodes {
+            Some(max) if visited > max => return self.abort(Abort::Nodes(max)),
+            _ => {}
+        }
+        if let Some(token) = &self.limits.cancellation {
+            if token.is_cancelled() {
+                return self.abort(Abort::Cancelled);
+            }
+        }
+        match self.deadline {
+            Some(deadline) if visited % CLOCK_PERIOD == 0 && Instant::now() >= deadline => {
+                self.abort(Abort::Deadline)
+            }
+            _ => true,
+        }
+    }
+
+    /// Checks the depth of a descendant to look into, `false` if the evaluation has to stop.
+    pub(crate) fn descend(&self, depth: usize) -> bool {
+        match self.limits.max_depth {
+            Some(max) if depth > max => self.abort(Abort::Depth(max)),
+            _ => true,
+        }
+    }
+
+    /// Accounts for the given number of matches, `false` if the evaluation has to stop.
+    pub(crate) fn select(&self, count: usize) -> bool {
+        let results = self.results.fetch_add(count, Ordering::Relaxed) + count;
+        match self.limits.max_results {
+            Some(max) if results > max => self.abort(Abort::Results(max)),
+            _ => self.aborted.get().is_none(),
+        }
+    }
+
+    /// Turns the reason of the stop into the error.
+    pub(crate) fn check(&self) -> Parsed<()> {
+        match self.aborted() {
+            Some(reason) => Err(JsonPathError::EvaluationAborted(reason)),
+            None => Ok(()),
+        }
+    }
+
+    fn abort(&self, reason: Abort) -> bool {
+        let _ = self.aborted.set(reason);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::errors::JsonPathError;
+    use crate::parser::parse_json_path;
+    use crate::query::compiled::CompiledQuery;
+    use crate::query::limits::{Abort, CancellationToken, Limits};
+    use crate::query::state::{Context, State};
+    use crate::query::{process_pointers, Queried};
+    use serde_json::{json, Value};
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    fn query(query: &str, limits: Limits) -> CompiledQuery<Value> {
+        CompiledQuery::builder(query)
+            .limits(limits)
+            .build()
+            .unwrap()
+    }
+
+    fn aborted(reason: Abort) -> Queried<Vec<&'static Value>> {
+        Err(JsonPathError::EvaluationAborted(reason))
+    }
+
+    #[test]
+    fn nodes_and_results() -> Queried<()> {
+        let doc = json!({"a": [1, 2, 3], "b": {"c": [4, 5]}});
+
+        // the walk of the descendants and the wildcard account for the nodes on their own
+        let q = query("$..*", Limits::default().max_nodes(16));
+        assert_eq!(q.query(&doc)?.len(), 8);
+        let q = query("$..*", Limits::default().max_nodes(15));
+        assert_eq!(q.query(&doc), aborted(Abort::Nodes(15)));
+        let q = query("$..*", Limits::default().max_nodes(12));
+        let mut iter = q.iter(&doc);
+        assert_eq!(iter.by_ref().count(), 6);
+        assert_eq!(
+            iter.check(),
+            Err(JsonPathError::EvaluationAborted(Abort::Nodes(12)))
+        );
+
+        let q = query("$.a[?@ > 0]", Limits::default().max_nodes(3));
+        assert_eq!(q.query(&doc), aborted(Abort::Nodes(3)));
+        for (selector, max) in [("[*]", 3), ("[0:3]", 3), ("[::-1]", 3), ("[1]", 1)] {
+            let q = query(
+                &format!("$.a{}", selector),
+                Limits::default().max_nodes(max + 1),
+            );
+            assert!(q.query(&doc).is_ok());
+            let q = query(
+                &format!("$.a{}", selector),
+                Limits::default().max_nodes(max),
+            );
+            assert_eq!(q.query(&doc), aborted(Abort::Nodes(max)), "{}", selector);
+        }
+
+        let q = query("$..[?@ > 2]", Limits::default().max_results(2));
+        assert_eq!(q.query(&doc), aborted(Abort::Results(2)));
+        assert_eq!(q.iter(&doc).count(), 2);
+        assert!(q.exists(&doc)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn results_as_picked() -> Queried<()> {
+        let doc = json!((0..1000).collect::<Vec<_>>());
+        let limits = Limits::default().max_results(10);
+
+        for path in [
+            "$[*]",
+            "$[?@ >= 0]",
+            "$[10:]",
+            "$[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]",
+        ] {
+            let ctx = Context::default().with_limits(&limits);
+            let budget = ctx.budget.clone().expect("limited");
+            let state = State::root(&doc).with_context(ctx);
+            assert_eq!(
+                process_pointers(&parse_json_path(path)?, state),
+                Err(JsonPathError::EvaluationAborted(Abort::Results(10)))
+            );
+            // the selectors stop at the match past the bound
+            assert!(budget.visited.load(Ordering::Relaxed) <= 12, "{}", path);
+        }
+
+        // the nodes the filters look at are not the matches
+        let q = query("$[?count(@[*]) > 2]", limits);
+        assert_eq!(q.query(&json!([[1, 2, 3], [4]]))?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn depth() -> Queried<()> {
+        let doc = json!({"a": {"b": {"c": {"d": 1}}}});
+
+        let q = query("$..d", Limits::default().max_depth(3));
+        assert_eq!(q.query(&doc)?, vec![&json!(1)]);
+        let q = query("$.a..d", Limits::default().max_depth(1));
+        assert_eq!(q.query(&doc), aborted(Abort::Depth(1)));
+        let mut iter = q.iter(&doc);
+        assert_eq!(iter.by_ref().count(), 0);
+        assert_eq!(
+            iter.check(),
+            Err(JsonPathError::EvaluationAborted(Abort::Depth(1)))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn deadline_and_cancellation() -> Queried<()> {
+        let doc = json!((0..1000).collect::<Vec<_>>());
+
+        let q = query("$[*]", Limits::default().timeout(Duration::from_secs(60)));
+        assert_eq!(q.query(&doc)?.len(), 1000);
+        let q = query("$[?@ >= 0]", Limits::default().deadline(Instant::now()));
+        assert_eq!(q.query(&doc), aborted(Abort::Deadline));
+
+        let token = CancellationToken::new();
+        let q = query("$[*]", Limits::default().cancellation(token.clone()));
+        assert_eq!(q.query(&doc)?.len(), 1000);
+        token.cancel();
+        assert_eq!(q.query(&doc), aborted(Abort::Cancelled));
+
+        Ok(())
+    }
+}
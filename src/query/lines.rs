@@ -0,0 +1,374 @@
+use crate::parser::errors::JsonPathError;
+use crate::query::compiled::CompiledQuery;
+use crate::query::path::NormalizedPath;
+use crate::query::queryable::Queryable;
+use crate::query::{Queried, QueryPath};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use std::io::{BufRead, ErrorKind, Lines};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// How many lines every thread gets in a batch by default.
+const DEFAULT_LINES_PER_THREAD: usize = 64;
+
+/// The matches of a line of JSON Lines, or why the line failed.
+pub type LineResult<T> = (usize, Queried<Vec<(QueryPath, T)>>);
+
+/// A query evaluated against every line of JSON Lines (NDJSON), see [`CompiledQuery::query_lines`].
+///
+/// Yields the number of every line (starting with 1) with the matches in it,
+/// the blank lines are skipped.
+/// A line that is not valid JSON fails on its own
+/// with [`JsonPathError::Deserialization`], the next lines are evaluated as usual.
+/// A failure to read the input ends the iteration with [`JsonPathError::Io`]
+/// (a line that is not valid UTF-8 fails on its own).
+///
+/// The lines can be evaluated on several threads, see [`JsonLines::parallel`],
+/// the results come in the order of the lines anyway.
+/// The threads are started with the first batch and stop when the iteration is dropped.
+/// The iteration shares the parsed query with the [`CompiledQuery`] it comes from.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::compiled::CompiledQuery;
+/// use serde_json::{json, Value};
+///
+/// let query = CompiledQuery::<Value>::new("$.user").unwrap();
+/// let input = "{\"user\": \"ann\"}\n\n{\"user\": \n{\"id\": 3}\n";
+///
+/// let results = query
+///     .query_lines(input.as_bytes())
+///     .map(|(line, found)| (line, found.map(|f| f.into_iter().map(|(_, v)| v).collect::<Vec<_>>())))
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(results[0], (1, Ok(vec![json!("ann")])));
+/// assert!(results[1].1.is_err());
+/// assert_eq!(results[2], (4, Ok(vec![])));
+/// ```
+pub struct JsonLines<R, T: Queryable> {
+    query: CompiledQuery<T>,
+    lines: Lines<R>,
+    line: usize,
+    batch: usize,
+    ready: VecDeque<LineResult<T>>,
+    finished: bool,
+    threads: Option<Box<dyn Batches<T>>>,
+}
+
+impl<R: BufRead, T: Queryable> JsonLines<R, T> {
+    pub(crate) fn new(query: CompiledQuery<T>, reader: R) -> Self {
+        JsonLines {
+            query,
+            lines: reader.lines(),
+            line: 0,
+            batch: DEFAULT_LINES_PER_THREAD,
+            ready: VecDeque::new(),
+            finished: false,
+            threads: None,
+        }
+    }
+}
+
+impl<R, T> JsonLines<R, T>
+where
+    R: BufRead,
+    T: Queryable + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Evaluates the lines on the given number of threads,
+    /// reading a batch of `threads * lines_per_thread` lines ahead.
+    pub fn parallel(self, threads: usize, lines_per_thread: usize) -> Self {
+        let (threads, lines_per_thread) = (threads.max(1), lines_per_thread.max(1));
+        JsonLines {
+            batch: threads * lines_per_thread,
+            threads: (threads > 1).then(|| {
+                Box::new(Threads {
+                    threads,
+                    lines_per_thread,
+                    workers: None,
+                }) as Box<dyn Batches<T>>
+            }),
+            ..self
+        }
+    }
+}
+
+impl<R, T> JsonLines<R, T>
+where
+    R: BufRead,
+    T: Queryable + DeserializeOwned,
+{
+    /// Reads the next batch of the lines, the ones that failed to be read are evaluated already.
+    fn read_batch(&mut self) -> Vec<(usize, String)> {
+        let mut batch = vec![];
+        while batch.len() < self.batch && !self.finished {
+            self.line += 1;
+            match self.lines.next() {
+                None => self.finished = true,
+                Some(Ok(text)) if text.trim().is_empty() => {}
+                Some(Ok(text)) => batch.push((self.line, text)),
+                Some(Err(e)) => {
+                    self.finished = e.kind() != ErrorKind::InvalidData;
+                    // the failure goes after the lines read before it
+                    self.ready
+                        .extend(evaluate_batch(&self.query, std::mem::take(&mut batch)));
+                    self.ready.push_back((self.line, Err(e.into())));
+                }
+            }
+        }
+        batch
+    }
+}
+
+impl<R, T> Iterator for JsonLines<R, T>
+where
+    R: BufRead,
+    T: Queryable + DeserializeOwned,
+{
+    type Item = LineResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.ready.is_empty() && !self.finished {
+            let batch = self.read_batch();
+            let evaluated = match &mut self.threads {
+                Some(threads) => threads.evaluate(&self.query, batch),
+                None => evaluate_batch(&self.query, batch),
+            };
+            self.ready.extend(evaluated);
+        }
+        self.ready.pop_front()
+    }
+}
+
+/// The evaluation of the batches on several threads, see [`JsonLines::parallel`].
+trait Batches<T: Queryable> {
+    fn evaluate(
+        &mut self,
+        query: &CompiledQuery<T>,
+        batch: Vec<(usize, String)>,
+    ) -> Vec<LineResult<T>>;
+}
+
+/// The threads evaluating the batches, they are started with the first batch big enough.
+struct Threads<T> {
+    threads: usize,
+    lines_per_thread: usize,
+    workers: Option<Workers<T>>,
+}
+
+impl<T> Batches<T> for Threads<T>
+where
+    T: Queryable + DeserializeOwned + Send + Sync + 'static,
+{
+    fn evaluate(
+        &mut self,
+        query: &CompiledQuery<T>,
+        batch: Vec<(usize, String)>,
+    ) -> Vec<LineResult<T>> {
+        if batch.len() <= self.lines_per_thread {
+            return evaluate_batch(query, batch);
+        }
+        let threads = self.threads;
+        let workers = self
+            .workers
+            .get_or_insert_with(|| Workers::spawn(query, threads));
+        let chunk = batch.len().div_ceil(threads);
+        workers.evaluate(batch, chunk)
+    }
+}
+
+/// A chunk of a batch with its position in the batch.
+type Chunk = (usize, Vec<(usize, String)>);
+
+/// The threads evaluating the chunks of the batches, they take the chunks from a shared queue.
+struct Workers<T> {
+    chunks: Option<Sender<Chunk>>,
+    results: Receiver<(usize, thread::Result<Vec<LineResult<T>>>)>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<T> Workers<T>
+where
+    T: Queryable + DeserializeOwned + Send + Sync + 'static,
+{
+    fn spawn(query: &CompiledQuery<T>, threads: usize) -> Self {
+        let (chunks, queue) = mpsc::channel::<Chunk>();
+        let queue = Arc::new(Mutex::new(queue));
+        let (done, results) = mpsc::channel();
+        let handles = (0..threads)
+            .map(|_| {
+                let (query, queue, done) = (query.clone(), queue.clone(), done.clone());
+                thread::spawn(move || loop {
+                    let next = queue.lock().map(|queue| queue.recv());
+                    let Ok(Ok((idx, lines))) = next else {
+                        break;
+                    };
+                    // the panic goes to the thread waiting for the chunk
+                    let evaluated =
+                        panic::catch_unwind(AssertUnwindSafe(|| evaluate_batch(&query, lines)));
+                    if done.send((idx, evaluated)).is_err() {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        Workers {
+            chunks: Some(chunks),
+            results,
+            handles,
+        }
+    }
+
+    /// Evaluates the batch in chunks of the given size, the results are in the order of the lines.
+    fn evaluate(&self, mut batch: Vec<(usize, String)>, chunk: usize) -> Vec<LineResult<T>> {
+        let chunks = self.chunks.as_ref().expect("the workers are running");
+        let mut sent = 0;
+        while !batch.is_empty() {
+            let rest = batch.split_off(chunk.min(batch.len()));
+            chunks
+                .send((sent, std::mem::replace(&mut batch, rest)))
+                .expect("the workers are running");
+            sent += 1;
+        }
+        let mut evaluated = (0..sent)
+            .map(|_| self.results.recv().expect("the workers are running"))
+            .collect::<Vec<_>>();
+        evaluated.sort_by_key(|(idx, _)| *idx);
+        evaluated
+            .into_iter()
+            .flat_map(|(_, res)| res.unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    }
+}
+
+impl<T> Drop for Workers<T> {
+    fn drop(&mut self) {
+        // the threads stop once the queue is closed
+        self.chunks.take();
+        self.handles.drain(..).for_each(|h| {
+            let _ = h.join();
+        });
+    }
+}
+
+fn evaluate_batch<T: Queryable + DeserializeOwned>(
+    query: &CompiledQuery<T>,
+    lines: Vec<(usize, String)>,
+) -> Vec<LineResult<T>> {
+    lines
+        .into_iter()
+        .map(|(line, text)| (line, evaluate(query, &text)))
+        .collect()
+}
+
+fn evaluate<T: Queryable + DeserializeOwned>(
+    query: &CompiledQuery<T>,
+    text: &str,
+) -> Queried<Vec<(QueryPath, T)>> {
+    let doc: T = serde_json::from_str(text).map_err(|e| JsonPathError::Deserialization {
+        path: NormalizedPath::root(),
+        message: e.to_string(),
+    })?;
+    Ok(query
+        .query_with_path(&doc)?
+        .into_iter()
+        .map(|r| r.into_owned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::errors::JsonPathError;
+    use crate::query::compiled::CompiledQuery;
+    use crate::query::queryable::Queryable;
+    use crate::query::Queried;
+    use serde::de::DeserializeOwned;
+    use serde_json::{json, Value};
+    use std::io::{BufReader, ErrorKind, Read};
+
+    fn values(found: Queried<Vec<(crate::query::QueryPath, Value)>>) -> Queried<Vec<Value>> {
+        found.map(|f| f.into_iter().map(|(_, v)| v).collect())
+    }
+
+    #[test]
+    fn sequential_and_parallel() {
+        let query = CompiledQuery::<Value>::new("$[?@.n > 2].n").unwrap();
+        let input = (0..500)
+            .map(|i| match i % 7 {
+                3 => "{\"n\": ".to_string(),
+                5 => "  ".to_string(),
+                _ => format!("[{{\"n\": {}}}, {{\"n\": 1}}]", i),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let sequential = query.query_lines(input.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(sequential.len(), 500 - 71, "the blank lines are skipped");
+        assert_eq!(sequential[0], (1, Ok(vec![])));
+        assert_eq!(values(sequential[4].1.clone()), Ok(vec![json!(4)]));
+        assert!(matches!(
+            sequential[3],
+            (4, Err(JsonPathError::Deserialization { .. }))
+        ));
+
+        let parallel = query
+            .query_lines(input.as_bytes())
+            .parallel(4, 8)
+            .collect::<Vec<_>>();
+        assert_eq!(parallel, sequential);
+    }
+
+    #[test]
+    fn sequential_without_send() {
+        // the sequential evaluation does not ask the documents to be shared between threads
+        fn count<T: Queryable + DeserializeOwned>(query: &CompiledQuery<T>, input: &str) -> usize {
+            query.query_lines(input.as_bytes()).count()
+        }
+        let query = CompiledQuery::<Value>::new("$.a").unwrap();
+        assert_eq!(count(&query, "{\"a\": 1}\n{}\n"), 2);
+    }
+
+    #[test]
+    fn read_failures() {
+        let query = CompiledQuery::<Value>::new("$.a").unwrap();
+        let input: &[u8] = b"{\"a\": 1}\n\xff\n{\"a\": 2}\n";
+        let found = query
+            .query_lines(BufReader::new(input))
+            .map(|(line, found)| (line, values(found)))
+            .collect::<Vec<_>>();
+        assert_eq!(found[0], (1, Ok(vec![json!(1)])));
+        assert!(matches!(
+            found[1],
+            (
+                2,
+                Err(JsonPathError::Io {
+                    kind: ErrorKind::InvalidData,
+                    ..
+                })
+            )
+        ));
+        assert_eq!(found[2], (3, Ok(vec![json!(2)])));
+
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disconnected"))
+            }
+        }
+        let mut lines = query.query_lines(BufReader::new(Broken));
+        assert!(matches!(
+            lines.next(),
+            Some((
+                1,
+                Err(JsonPathError::Io {
+                    kind: ErrorKind::Other,
+                    ..
+                })
+            ))
+        ));
+        assert!(lines.next().is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! The evaluation of many children at once, on several threads with the `rayon` feature
+//! for the documents that are `Send + Sync`, see [`ParQueryable`].
+use crate::parser::model::{Filter, Selector};
+#[cfg(feature = "rayon")]
+use crate::parser::parse_json_path;
+use crate::query::queryable::Queryable;
+use crate::query::segment::process_descendant;
+use crate::query::selector::select;
+use crate::query::state::{Context, Data, Pointer};
+#[cfg(feature = "rayon")]
+use crate::query::{process_pointers, state::State, Queried, QueryPath, QueryRef};
+
+/// How many items there have to be to split the work between the threads,
+/// the smaller collections are not worth the cost of it.
+pub(crate) const PARALLEL_THRESHOLD: usize = 256;
+
+/// The work done on every node of a step.
+pub(crate) enum Task<'q, 'a, T> {
+    /// The nodes the selector (other than a filter) picks in the node.
+    Select(&'q Selector),
+    /// The children of the node the filter selects, the filters refer to the root.
+    Filter(&'q Filter, &'a T),
+    /// The node if the filter holds on it.
+    Keep(&'q Filter, &'a T),
+    /// The node and its descendants, the node is at the depth below the start of the segment.
+    Descend(usize),
+}
+
+impl<T> Clone for Task<'_, '_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Task<'_, '_, T> {}
+
+impl<'a, T: Queryable> Task<'_, 'a, T> {
+    fn run(self, node: Pointer<'a, T>, ctx: &Context<'a, T>) -> Data<'a, T> {
+        if ctx.budget.as_ref().is_some_and(|b| b.aborted().is_some()) {
+            return Data::Nothing;
+        }
+        match self {
+            Task::Select(selector) => select(selector, node, ctx),
+            Task::Filter(filter, root) => filter.children(node, root, ctx),
+            Task::Keep(filter, root) => filter.keep(node, root, ctx),
+            Task::Descend(depth) if ctx.visit() => process_descendant(node, ctx, depth),
+            Task::Descend(_) => Data::Nothing,
+        }
+    }
+}
+
+/// Runs the tasks of a step, made only for the documents that can be shared between the threads.
+pub(crate) trait Parallel<T: Queryable>: Sync {
+    /// The results of the task on every node, in the order of the nodes.
+    fn run<'a>(
+        &self,
+        task: Task<'_, 'a, T>,
+        nodes: Vec<Pointer<'a, T>>,
+        ctx: &Context<'a, T>,
+    ) -> Vec<Data<'a, T>>;
+}
+
+/// Runs the task on every node joining the results in the order of the nodes, see [`map_ordered`].
+pub(crate) fn run_ordered<'a, T: Queryable>(
+    task: Task<'_, 'a, T>,
+    data: Data<'a, T>,
+    ctx: &Context<'a, T>,
+) -> Data<'a, T> {
+    let nodes = match data {
+        Data::Ref(node) => return task.run(node, ctx),
+        Data::Refs(nodes) => nodes,
+        _ => return Data::Nothing,
+    };
+    Data::Refs(
+        map_ordered(task, nodes, ctx)
+            .into_iter()
+            .flat_map(|data| match data {
+                Data::Ref(data) => vec![data],
+                Data::Refs(data_vec) => data_vec,
+                _ => vec![],
+            })
+            .collect(),
+    )
+}
+
+/// The results of the task on every node in the order of the nodes,
+/// on several threads if the context allows it and there are [`PARALLEL_THRESHOLD`] nodes or more.
+pub(crate) fn map_ordered<'a, T: Queryable>(
+    task: Task<'_, 'a, T>,
+    nodes: Vec<Pointer<'a, T>>,
+    ctx: &Context<'a, T>,
+) -> Vec<Data<'a, T>> {
+    match ctx.parallel() {
+        Some(parallel) if nodes.len() >= PARALLEL_THRESHOLD => parallel.run(task, nodes, ctx),
+        _ => nodes.into_iter().map(|node| task.run(node, ctx)).collect(),
+    }
+}
+
+/// The threads of the `rayon` pool.
+#[cfg(feature = "rayon")]
+struct Rayon;
+
+#[cfg(feature = "rayon")]
+impl<T: Queryable + Send + Sync> Parallel<T> for Rayon {
+    fn run<'a>(
+        &self,
+        task: Task<'_, 'a, T>,
+        nodes: Vec<Pointer<'a, T>>,
+        ctx: &Context<'a, T>,
+    ) -> Vec<Data<'a, T>> {
+        use rayon::prelude::*;
+        nodes
+            .into_par_iter()
+            .map(|node| task.run(node, ctx))
+            .collect()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, T: Queryable + Send + Sync> Context<'a, T> {
+    /// Processes the children of the big arrays and objects on the threads of the `rayon` pool,
+    /// the results come in the same order.
+    pub fn with_parallel(self) -> Self {
+        Context {
+            parallel: Some(&Rayon),
+            ..self
+        }
+    }
+}
+
+/// The queries evaluated on several threads, see [`Context::with_parallel`].
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::parallel::ParQueryable;
+/// use serde_json::json;
+///
+/// let doc = json!({"items": (0..1000).map(|i| json!({"id": i})).collect::<Vec<_>>()});
+/// let ids = doc.par_query("$.items[?@.id < 3].id").unwrap();
+/// assert_eq!(ids, vec![&json!(0), &json!(1), &json!(2)]);
+/// ```
+#[cfg(feature = "rayon")]
+pub trait ParQueryable: Queryable + Send + Sync {
+    /// Evaluates the query and returns the matches with their paths.
+    fn par_query_with_path(&self, path: &str) -> Queried<Vec<QueryRef<Self>>> {
+        let state = State::root(self).with_context(Context::default().with_parallel());
+        Ok(process_pointers(&parse_json_path(path)?, state)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+
+    /// Evaluates the query and returns the matches.
+    fn par_query(&self, path: &str) -> Queried<Vec<&Self>> {
+        Ok(self
+            .par_query_with_path(path)?
+            .into_iter()
+            .map(|r| r.val())
+            .collect())
+    }
+
+    /// Evaluates the query and returns the paths of the matches.
+    fn par_query_only_path(&self, path: &str) -> Queried<Vec<QueryPath>> {
+        Ok(self
+            .par_query_with_path(path)?
+            .into_iter()
+            .map(|r| r.path())
+            .collect())
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T: Queryable + Send + Sync> ParQueryable for T {}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::js_path;
+    use crate::query::parallel::PARALLEL_THRESHOLD;
+    use crate::query::Queried;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn order_above_threshold() -> Queried<()> {
+        let size = PARALLEL_THRESHOLD * 4;
+        let doc = json!({
+            "items": (0..size)
+                .map(|i| json!({"id": i, "name": format!("n{}", i), "tags": [i % 3, i % 5]}))
+                .collect::<Vec<_>>()
+        });
+
+        let ids = |query: &str| -> Queried<Vec<Value>> {
+            Ok(js_path(query, &doc)?
+                .into_iter()
+                .map(|r| r.val().clone())
+                .collect())
+        };
+        let every = |step: usize| {
+            (0..size)
+                .step_by(step)
+                .map(|i| json!(i))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids("$.items[*].id")?, every(1));
+        assert_eq!(ids("$.items[?@.id >= 0].id")?, every(1));
+        assert_eq!(ids("$.items[?@.tags[1] == 0].id")?, every(5));
+        assert_eq!(ids("$.items[?match(@.name, 'n[0-9]*0')].id")?, every(10));
+        assert_eq!(ids("$..id")?, every(1));
+
+        let paths = js_path("$..[?@ == 0]", &doc)?
+            .into_iter()
+            .map(|r| r.path().to_string())
+            .collect::<Vec<_>>();
+        let mut expected = vec![];
+        for i in 0..size {
+            if i == 0 {
+                expected.push("$['items'][0]['id']".to_string());
+            }
+            for (idx, tag) in [i % 3, i % 5].into_iter().enumerate() {
+                if tag == 0 {
+                    expected.push(format!("$['items'][{}]['tags'][{}]", i, idx));
+                }
+            }
+        }
+        assert_eq!(paths, expected);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_as_sequential() -> Queried<()> {
+        use crate::query::parallel::ParQueryable;
+
+        let doc = json!({
+            "items": (0..PARALLEL_THRESHOLD * 4)
+                .map(|i| json!({"id": i, "name": format!("n{}", i), "tags": [i % 3, i % 5]}))
+                .collect::<Vec<_>>()
+        });
+        for query in [
+            "$.items[*].id",
+            "$.items[?@.tags[1] == 0].id",
+            "$.items[?match(@.name, 'n[0-9]*0')].id",
+            "$..id",
+            "$..[?@ == 0]",
+        ] {
+            let expected = js_path(query, &doc)?;
+            assert_eq!(doc.par_query_with_path(query)?, expected, "{}", query);
+        }
+
+        Ok(())
+    }
+}
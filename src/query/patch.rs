@@ -0,0 +1,397 @@
+use crate::parser::errors::JsonPathError;
+use crate::query::path::{array_index, unescape_pointer_token, NormalizedPath};
+use crate::query::queryable::{deepest_first, remove, Queryable};
+use crate::query::Queried;
+use crate::JsonPath;
+use serde_json::{json, Value};
+
+/// An operation of a JSON Patch (RFC 6902), the path is a JSON Pointer (RFC 6901).
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchOperation {
+    /// Adds the value as a member of an object, inserts it into an array
+    /// (the last token `-` appends it) or replaces the root.
+    Add { path: String, value: Value },
+    /// Removes the member of an object or the element of an array.
+    Remove { path: String },
+    /// Replaces the existing value.
+    Replace { path: String, value: Value },
+}
+
+impl PatchOperation {
+    pub fn path(&self) -> &str {
+        match self {
+            PatchOperation::Add { path, .. }
+            | PatchOperation::Remove { path }
+            | PatchOperation::Replace { path, .. } => path,
+        }
+    }
+
+    /// Renders the operation as the JSON object of RFC 6902, like `{"op": "remove", "path": "/a"}`.
+    pub fn to_value(&self) -> Value {
+        match self {
+            PatchOperation::Add { path, value } => {
+                json!({"op": "add", "path": path, "value": value})
+            }
+            PatchOperation::Remove { path } => json!({"op": "remove", "path": path}),
+            PatchOperation::Replace { path, value } => {
+                json!({"op": "replace", "path": path, "value": value})
+            }
+        }
+    }
+
+    /// Reads the JSON object of RFC 6902, only `add`, `remove` and `replace` are supported.
+    pub fn from_value(op: &Value) -> Queried<Self> {
+        let field = |name: &str| {
+            op.get(name)
+                .ok_or_else(|| invalid(format!("the operation {} has no `{}`", op, name)))
+        };
+        let path = field("path")?
+            .as_str()
+            .ok_or_else(|| invalid(format!("the path of the operation {} is not a string", op)))?
+            .to_string();
+        match field("op")?.as_str() {
+            Some("add") => Ok(PatchOperation::Add {
+                path,
+                value: field("value")?.clone(),
+            }),
+            Some("remove") => Ok(PatchOperation::Remove { path }),
+            Some("replace") => Ok(PatchOperation::Replace {
+                path,
+                value: field("value")?.clone(),
+            }),
+            _ => Err(invalid(format!("the operation {} is not supported", op))),
+        }
+    }
+
+    fn apply(&self, doc: &mut Value) -> Queried<()> {
+        let (parent, token) = split_pointer(self.path())?;
+        let Some((parent, token)) = parent.zip(token) else {
+            // the whole document
+            return match self {
+                PatchOperation::Add { value, .. } | PatchOperation::Replace { value, .. } => {
+                    *doc = value.clone();
+                    Ok(())
+                }
+                PatchOperation::Remove { .. } => Err(invalid("the root can not be removed")),
+            };
+        };
+        let parent_path = NormalizedPath::from_json_pointer_in(parent, doc)?;
+        let missing = || invalid(format!("the path `{}` does not exist", self.path()));
+        let parent = doc.reference_mut(&parent_path).ok_or_else(missing)?;
+
+        match (self, parent) {
+            (PatchOperation::Add { value, .. }, Value::Object(obj)) => {
+                obj.insert(token, value.clone());
+            }
+            (PatchOperation::Add { value, .. }, Value::Array(arr)) => {
+                let index = match token.as_str() {
+                    "-" => arr.len(),
+                    t => array_index(t, arr.len() + 1).ok_or_else(missing)?,
+                };
+                arr.insert(index, value.clone());
+            }
+            (PatchOperation::Remove { .. }, Value::Object(obj)) => {
+                obj.remove(&token).ok_or_else(missing)?;
+            }
+            (PatchOperation::Remove { .. }, Value::Array(arr)) => {
+                let index = array_index(&token, arr.len()).ok_or_else(missing)?;
+                arr.remove(index);
+            }
+            (PatchOperation::Replace { value, .. }, Value::Object(obj)) => {
+                *obj.get_mut(&token).ok_or_else(missing)? = value.clone();
+            }
+            (PatchOperation::Replace { value, .. }, Value::Array(arr)) => {
+                let index = array_index(&token, arr.len()).ok_or_else(missing)?;
+                arr[index] = value.clone();
+            }
+            _ => return Err(missing()),
+        }
+        Ok(())
+    }
+}
+
+/// A JSON Patch (RFC 6902): a sequence of operations applied one after another.
+///
+/// The patches are produced by the edits of [`PatchedEdits`]
+/// along with the patches reverting them.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::patch::JsonPatch;
+/// use serde_json::json;
+///
+/// let patch = JsonPatch::from_value(&json!([
+///     {"op": "add", "path": "/tags/-", "value": "new"},
+///     {"op": "remove", "path": "/draft"}
+/// ]))
+/// .unwrap();
+///
+/// let mut doc = json!({"tags": ["old"], "draft": true});
+/// patch.apply(&mut doc).unwrap();
+/// assert_eq!(doc, json!({"tags": ["old", "new"]}));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JsonPatch(Vec<PatchOperation>);
+
+impl JsonPatch {
+    pub fn new(operations: Vec<PatchOperation>) -> Self {
+        JsonPatch(operations)
+    }
+
+    pub fn operations(&self) -> &[PatchOperation] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Renders the patch as the JSON array of RFC 6902.
+    pub fn to_value(&self) -> Value {
+        Value::Array(self.0.iter().map(PatchOperation::to_value).collect())
+    }
+
+    /// Reads the JSON array of RFC 6902, see [`PatchOperation::from_value`].
+    pub fn from_value(patch: &Value) -> Queried<Self> {
+        patch
+            .as_array()
+            .ok_or_else(|| invalid("a patch has to be an array of operations"))?
+            .iter()
+            .map(PatchOperation::from_value)
+            .collect::<Queried<Vec<_>>>()
+            .map(JsonPatch)
+    }
+
+    /// Applies the operations in order.
+    /// If one of them fails the document is left untouched.
+    pub fn apply(&self, doc: &mut Value) -> Queried<()> {
+        let mut patched = doc.clone();
+        for op in self.0.iter() {
+            op.apply(&mut patched)?;
+        }
+        *doc = patched;
+        Ok(())
+    }
+}
+
+impl IntoIterator for JsonPatch {
+    type Item = PatchOperation;
+    type IntoIter = std::vec::IntoIter<PatchOperation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// The outcome of an edit made by [`PatchedEdits`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PatchedEdit {
+    /// The patch making the edit: applied to the document before the edit, it gives the edited one.
+    pub patch: JsonPatch,
+    /// The patch reverting the edit: applied to the edited document, it gives the one before the edit.
+    pub revert: JsonPatch,
+}
+
+impl PatchedEdit {
+    /// The number of elements the edit changed.
+    pub fn count(&self) -> usize {
+        self.patch.0.len()
+    }
+
+    fn record(&mut self, op: PatchOperation, inverse: PatchOperation) {
+        self.patch.0.push(op);
+        self.revert.0.insert(0, inverse);
+    }
+}
+
+/// The edits of [`Queryable`] (`delete_by_path`, `replace_by_path` and `update_by_path`)
+/// recording what they did as JSON Patches.
+///
+/// The matches are edited in the same order, so the patch lists the operations in that order
+/// and replaying it on the original document gives the same result.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::patch::PatchedEdits;
+/// use serde_json::json;
+///
+/// let mut doc = json!({"users": [{"age": 30}, {"age": 25}, {"age": 35}]});
+/// let original = doc.clone();
+///
+/// let edit = doc.delete_by_path_with_patch("$.users[?@.age > 26]").unwrap();
+/// assert_eq!(
+///     edit.patch.to_value(),
+///     json!([
+///         {"op": "remove", "path": "/users/2"},
+///         {"op": "remove", "path": "/users/0"}
+///     ])
+/// );
+///
+/// edit.revert.apply(&mut doc).unwrap();
+/// assert_eq!(doc, original);
+/// ```
+pub trait PatchedEdits {
+    fn delete_by_path_with_patch(&mut self, path: &str) -> Queried<PatchedEdit>;
+
+    fn replace_by_path_with_patch(&mut self, path: &str, value: Value) -> Queried<PatchedEdit> {
+        self.update_by_path_with_patch(path, |_| value.clone())
+    }
+
+    fn update_by_path_with_patch<F>(&mut self, path: &str, f: F) -> Queried<PatchedEdit>
+    where
+        F: FnMut(Value) -> Value;
+}
+
+impl PatchedEdits for Value {
+    fn delete_by_path_with_patch(&mut self, path: &str) -> Queried<PatchedEdit> {
+        let mut edit = PatchedEdit::default();
+        for path in deepest_first(self.query_only_path(path)?) {
+            let pointer = path.to_json_pointer();
+            match remove(self, &path) {
+                // the root is not removed but replaced with null
+                Some(old) if path.is_root() => edit.record(
+                    PatchOperation::Replace {
+                        path: pointer.clone(),
+                        value: Value::Null,
+                    },
+                    PatchOperation::Replace {
+                        path: pointer,
+                        value: old,
+                    },
+                ),
+                Some(old) => edit.record(
+                    PatchOperation::Remove {
+                        path: pointer.clone(),
+                    },
+                    PatchOperation::Add {
+                        path: pointer,
+                        value: old,
+                    },
+                ),
+                None => {}
+            }
+        }
+        Ok(edit)
+    }
+
+    fn update_by_path_with_patch<F>(&mut self, path: &str, mut f: F) -> Queried<PatchedEdit>
+    where
+        F: FnMut(Value) -> Value,
+    {
+        let mut edit = PatchedEdit::default();
+        for path in deepest_first(self.query_only_path(path)?) {
+            if let Some(node) = self.reference_mut(&path) {
+                let old = std::mem::take(node);
+                *node = f(old.clone());
+                let pointer = path.to_json_pointer();
+                edit.record(
+                    PatchOperation::Replace {
+                        path: pointer.clone(),
+                        value: node.clone(),
+                    },
+                    PatchOperation::Replace {
+                        path: pointer,
+                        value: old,
+                    },
+                );
+            }
+        }
+        Ok(edit)
+    }
+}
+
+fn invalid<S: Into<String>>(message: S) -> JsonPathError {
+    JsonPathError::InvalidPatch(message.into())
+}
+
+/// Splits the pointer into the pointer of the parent and the unescaped last token,
+/// both are `None` for the pointer of the whole document.
+fn split_pointer(pointer: &str) -> Queried<(Option<&str>, Option<String>)> {
+    if pointer.is_empty() {
+        return Ok((None, None));
+    }
+    let (parent, token) = pointer
+        .rsplit_once('/')
+        .filter(|_| pointer.starts_with('/'))
+        .ok_or_else(|| invalid(format!("the path `{}` is not a JSON Pointer", pointer)))?;
+    let token = unescape_pointer_token(token)
+        .ok_or_else(|| invalid(format!("invalid escape in the JSON Pointer `{}`", pointer)))?;
+    Ok((Some(parent), Some(token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::patch::{JsonPatch, PatchOperation, PatchedEdits};
+    use crate::query::Queried;
+    use serde_json::json;
+
+    #[test]
+    fn update_and_revert() -> Queried<()> {
+        let mut doc = json!({"a": [1, [2]], "b/c": 3});
+        let original = doc.clone();
+
+        let edit = doc.update_by_path_with_patch("$..*", |v| match v.as_i64() {
+            Some(n) => json!(n * 10),
+            None => v,
+        })?;
+        let edited = json!({"a": [10, [20]], "b/c": 30});
+        assert_eq!(doc, edited);
+        assert_eq!(edit.count(), 5);
+        assert_eq!(edit.patch.operations()[0].path(), "/b~1c");
+
+        let mut replayed = original.clone();
+        edit.patch.apply(&mut replayed)?;
+        assert_eq!(replayed, edited);
+
+        edit.revert.apply(&mut doc)?;
+        assert_eq!(doc, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_and_revert() -> Queried<()> {
+        let mut doc = json!({"a": [1, 2, 3, 4], "1": {"0": true}});
+        let original = doc.clone();
+
+        let edit = doc.delete_by_path_with_patch("$..[?@ == 2 || @ == 4 || @ == true]")?;
+        assert_eq!(doc, json!({"a": [1, 3], "1": {}}));
+
+        let patch = JsonPatch::from_value(&edit.patch.to_value())?;
+        let mut replayed = original.clone();
+        patch.apply(&mut replayed)?;
+        assert_eq!(replayed, doc);
+
+        edit.revert.apply(&mut doc)?;
+        assert_eq!(doc, original);
+
+        let edit = doc.delete_by_path_with_patch("$")?;
+        assert_eq!(doc, json!(null));
+        edit.revert.apply(&mut doc)?;
+        assert_eq!(doc, original);
+
+        Ok(())
+    }
+
+    #[test]
+    fn apply_is_atomic() {
+        let mut doc = json!({"a": [1]});
+        let patch = JsonPatch::new(vec![
+            PatchOperation::Add {
+                path: "/a/0".to_string(),
+                value: json!(0),
+            },
+            PatchOperation::Remove {
+                path: "/a/5".to_string(),
+            },
+        ]);
+
+        assert!(patch.apply(&mut doc).is_err());
+        assert_eq!(doc, json!({"a": [1]}));
+
+        assert!(JsonPatch::from_value(&json!([{"op": "move", "path": "/a"}])).is_err());
+        assert!(JsonPatch::from_value(&json!([{"op": "add", "path": "/a"}])).is_err());
+    }
+}
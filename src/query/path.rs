@@ -0,0 +1,449 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::model::{JpQuery, Segment, Selector};
+use crate::parser::{parse_json_path, Parsed};
+use crate::query::queryable::Queryable;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// An element of a [`NormalizedPath`]: a member name of an object or an index of an array element.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PathElem {
+    Name(String),
+    Index(usize),
+}
+
+impl Display for PathElem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathElem::Name(name) => write!(f, "[{}]", normal_name_selector(name)),
+            PathElem::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+impl From<&str> for PathElem {
+    fn from(name: &str) -> Self {
+        PathElem::Name(name.to_string())
+    }
+}
+
+impl From<String> for PathElem {
+    fn from(name: String) -> Self {
+        PathElem::Name(name)
+    }
+}
+
+impl From<usize> for PathElem {
+    fn from(index: usize) -> Self {
+        PathElem::Index(index)
+    }
+}
+
+/// The location of a node in a document as a list of member names and array indices
+/// leading from the root to the node (RFC 9535, section 2.7).
+///
+/// It is displayed as the normalized path, like `$['store']['book'][0]`,
+/// and can be converted to and from a JSON Pointer (RFC 6901).
+/// The paths are ordered element by element, so a path comes right before its descendants.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::path::NormalizedPath;
+///
+/// let path: NormalizedPath = "$.store.book[0]".parse().unwrap();
+///
+/// assert_eq!(path.to_string(), "$['store']['book'][0]");
+/// assert_eq!(path.to_json_pointer(), "/store/book/0");
+/// assert_eq!(path.parent().unwrap().to_string(), "$['store']['book']");
+/// assert_eq!(path.child("title").to_string(), "$['store']['book'][0]['title']");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NormalizedPath(Vec<PathElem>);
+
+impl NormalizedPath {
+    /// The path of the root node, `$`.
+    pub fn root() -> Self {
+        NormalizedPath(vec![])
+    }
+
+    pub fn new(elems: Vec<PathElem>) -> Self {
+        NormalizedPath(elems)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The number of elements, that is the depth of the node.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn elems(&self) -> &[PathElem] {
+        &self.0
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, PathElem> {
+        self.0.iter()
+    }
+
+    /// The last element of the path, `None` for the root.
+    pub fn last(&self) -> Option<&PathElem> {
+        self.0.last()
+    }
+
+    /// The path of the parent node, `None` for the root.
+    pub fn parent(&self) -> Option<NormalizedPath> {
+        self.0
+            .split_last()
+            .map(|(_, parent)| NormalizedPath(parent.to_vec()))
+    }
+
+    /// The path of a child node.
+    pub fn child<E: Into<PathElem>>(&self, elem: E) -> NormalizedPath {
+        let mut child = self.clone();
+        child.push(elem);
+        child
+    }
+
+    pub fn push<E: Into<PathElem>>(&mut self, elem: E) {
+        self.0.push(elem.into())
+    }
+
+    pub fn pop(&mut self) -> Option<PathElem> {
+        self.0.pop()
+    }
+
+    /// Checks whether the path points at the node itself or at one of its ancestors.
+    pub fn starts_with(&self, prefix: &NormalizedPath) -> bool {
+        self.0.starts_with(&prefix.0)
+    }
+
+    /// Renders the path as a JSON Pointer (RFC 6901).
+    pub fn to_json_pointer(&self) -> String {
+        self.0
+            .iter()
+            .map(|e| match e {
+                PathElem::Name(name) => format!("/{}", pointer_token(name)),
+                PathElem::Index(index) => format!("/{}", index),
+            })
+            .collect()
+    }
+
+    /// Reads a JSON Pointer (RFC 6901).
+    ///
+    /// A pointer does not tell the member names from the indices,
+    /// so a token that is a valid array index (digits without leading zeros) becomes [`PathElem::Index`].
+    pub fn from_json_pointer(pointer: &str) -> Parsed<Self> {
+        if pointer.is_empty() {
+            return Ok(NormalizedPath::root());
+        }
+        let Some(tokens) = pointer.strip_prefix('/') else {
+            return Err(JsonPathError::InvalidJsonPath(format!(
+                "A JSON Pointer has to start with `/`: `{}`",
+                pointer
+            )));
+        };
+        tokens
+            .split('/')
+            .map(|token| {
+                let token = unescape_pointer_token(token).ok_or_else(|| {
+                    JsonPathError::InvalidJsonPath(format!(
+                        "Invalid escape in the JSON Pointer `{}`",
+                        pointer
+                    ))
+                })?;
+                Ok(match array_index(&token, usize::MAX) {
+                    Some(index) => PathElem::Index(index),
+                    None => PathElem::Name(token),
+                })
+            })
+            .collect::<Parsed<Vec<_>>>()
+            .map(NormalizedPath)
+    }
+
+    /// Reads a JSON Pointer (RFC 6901) telling the member names from the indices by the document:
+    /// a token is an index if it points into an array.
+    /// The tokens past the nodes existing in the document are read as in [`NormalizedPath::from_json_pointer`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use jsonpath_rust::query::path::NormalizedPath;
+    /// use serde_json::json;
+    ///
+    /// let doc = json!({"1": ["a", "b"]});
+    /// let path = NormalizedPath::from_json_pointer_in("/1/0", &doc).unwrap();
+    ///
+    /// assert_eq!(path.to_string(), "$['1'][0]");
+    /// ```
+    pub fn from_json_pointer_in<T: Queryable>(pointer: &str, doc: &T) -> Parsed<Self> {
+        let mut node = Some(doc);
+        let elems = NormalizedPath::from_json_pointer(pointer)?
+            .0
+            .into_iter()
+            .map(|elem| {
+                let elem = match (elem, node.and_then(|n| n.as_array())) {
+                    (PathElem::Index(index), None) if node.is_some() => {
+                        PathElem::Name(index.to_string())
+                    }
+                    (PathElem::Name(name), Some(_)) => {
+                        return Err(JsonPathError::InvalidJsonPath(format!(
+                            "The token `{}` of the JSON Pointer `{}` is not an index of the array",
+                            name, pointer
+                        )))
+                    }
+                    (elem, _) => elem,
+                };
+                node = node.and_then(|n| match &elem {
+                    PathElem::Name(name) => n.get(name),
+                    PathElem::Index(index) => n.as_array().and_then(|a| a.get(*index)),
+                });
+                Ok(elem)
+            })
+            .collect::<Parsed<Vec<_>>>()?;
+        Ok(NormalizedPath(elems))
+    }
+}
+
+impl Display for NormalizedPath {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "$")?;
+        for elem in self.0.iter() {
+            write!(f, "{}", elem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a query that consists of name and index selectors only, like a normalized path,
+/// `$.a[1]` is accepted as well as `$['a'][1]`.
+impl FromStr for NormalizedPath {
+    type Err = JsonPathError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let JpQuery { segments, .. } = parse_json_path(path)?;
+        segments
+            .into_iter()
+            .map(|segment| match segment {
+                Segment::Selector(Selector::Name(name)) => Ok(PathElem::Name(name)),
+                Segment::Selector(Selector::Index(index)) if index >= 0 => {
+                    Ok(PathElem::Index(index as usize))
+                }
+                s => Err(JsonPathError::InvalidJsonPath(format!(
+                    "Invalid segment of a normalized path: {:?}",
+                    s
+                ))),
+            })
+            .collect::<Parsed<Vec<_>>>()
+            .map(NormalizedPath)
+    }
+}
+
+impl TryFrom<&str> for NormalizedPath {
+    type Error = JsonPathError;
+
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        path.parse()
+    }
+}
+
+impl TryFrom<String> for NormalizedPath {
+    type Error = JsonPathError;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        path.parse()
+    }
+}
+
+impl TryFrom<&String> for NormalizedPath {
+    type Error = JsonPathError;
+
+    fn try_from(path: &String) -> Result<Self, Self::Error> {
+        path.parse()
+    }
+}
+
+impl From<&NormalizedPath> for NormalizedPath {
+    fn from(path: &NormalizedPath) -> Self {
+        path.clone()
+    }
+}
+
+impl From<Vec<PathElem>> for NormalizedPath {
+    fn from(elems: Vec<PathElem>) -> Self {
+        NormalizedPath(elems)
+    }
+}
+
+impl From<NormalizedPath> for String {
+    fn from(path: NormalizedPath) -> Self {
+        path.to_string()
+    }
+}
+
+impl<'a> IntoIterator for &'a NormalizedPath {
+    type Item = &'a PathElem;
+    type IntoIter = std::slice::Iter<'a, PathElem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl PartialEq<str> for NormalizedPath {
+    fn eq(&self, other: &str) -> bool {
+        self.to_string() == other
+    }
+}
+
+impl PartialEq<&str> for NormalizedPath {
+    fn eq(&self, other: &&str) -> bool {
+        self.to_string() == *other
+    }
+}
+
+impl PartialEq<String> for NormalizedPath {
+    fn eq(&self, other: &String) -> bool {
+        &self.to_string() == other
+    }
+}
+
+/// Renders a member name as a `normal-name-selector` of a Normalized Path (RFC 9535, section 2.7):
+/// single quotes, and only `'`, `\` and the C0 controls escaped.
+fn normal_name_selector(name: &str) -> String {
+    let mut selector = String::with_capacity(name.len() + 2);
+    selector.push('\'');
+    for ch in name.chars() {
+        match ch {
+            '\u{0008}' => selector.push_str("\\b"),
+            '\u{000C}' => selector.push_str("\\f"),
+            '\n' => selector.push_str("\\n"),
+            '\r' => selector.push_str("\\r"),
+            '\t' => selector.push_str("\\t"),
+            '\'' => selector.push_str("\\'"),
+            '\\' => selector.push_str("\\\\"),
+            c if c < '\u{0020}' => selector.push_str(&format!("\\u{:04x}", c as u32)),
+            c => selector.push(c),
+        }
+    }
+    selector.push('\'');
+    selector
+}
+
+/// Encodes a member name as a JSON Pointer reference token (RFC 6901, section 3).
+fn pointer_token(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+/// Decodes a JSON Pointer reference token, `None` if there is a `~` not followed by `0` or `1`.
+pub(crate) fn unescape_pointer_token(token: &str) -> Option<String> {
+    let mut res = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '~' => match chars.next() {
+                Some('0') => res.push('~'),
+                Some('1') => res.push('/'),
+                _ => return None,
+            },
+            c => res.push(c),
+        }
+    }
+    Some(res)
+}
+
+/// An `array-index` of RFC 6901 below the bound: `0` or digits without a leading zero.
+pub(crate) fn array_index(token: &str, bound: usize) -> Option<usize> {
+    let digits = !token.is_empty() && token.chars().all(|c| c.is_ascii_digit());
+    if digits && (token == "0" || !token.starts_with('0')) {
+        token.parse().ok().filter(|i| *i < bound)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::path::{NormalizedPath, PathElem};
+    use crate::query::queryable::Queryable;
+    use crate::JsonPath;
+    use serde_json::json;
+
+    #[test]
+    fn display_and_parse() {
+        let path = NormalizedPath::new(vec!["a".into(), 1.into(), "it's\n".into()]);
+        assert_eq!(path.to_string(), "$['a'][1]['it\\'s\\n']");
+        assert_eq!(path.to_string().parse::<NormalizedPath>(), Ok(path));
+
+        assert_eq!(NormalizedPath::root().to_string(), "$");
+        assert!("$[*]".parse::<NormalizedPath>().is_err());
+        assert!("$[-1]".parse::<NormalizedPath>().is_err());
+    }
+
+    #[test]
+    fn json_pointer() {
+        let path: NormalizedPath = "$['a/b']['~c'][10]['01']".parse().unwrap();
+        assert_eq!(path.to_json_pointer(), "/a~1b/~0c/10/01");
+        assert_eq!(
+            NormalizedPath::from_json_pointer("/a~1b/~0c/10/01"),
+            Ok(path)
+        );
+        assert_eq!(
+            NormalizedPath::from_json_pointer(""),
+            Ok(NormalizedPath::root())
+        );
+        assert!(NormalizedPath::from_json_pointer("a").is_err());
+        assert!(NormalizedPath::from_json_pointer("/~2").is_err());
+    }
+
+    #[test]
+    fn json_pointer_in_document() {
+        let doc = json!({"10": [{"01": 1}], "a": {}});
+        let path = |p| NormalizedPath::from_json_pointer_in(p, &doc).map(|p| p.to_string());
+
+        assert_eq!(path("/10/0/01"), Ok("$['10'][0]['01']".to_string()));
+        assert_eq!(path("/a/1/2"), Ok("$['a']['1'][2]".to_string()));
+        assert!(path("/10/-").is_err());
+        assert!(path("/10/01").is_err());
+    }
+
+    #[test]
+    fn query_pointers() {
+        let doc = json!({"a/b": [1, 2], "~": {"c": 3}});
+        let pointers = doc.query_pointers("$..*").unwrap();
+
+        assert_eq!(
+            pointers,
+            vec!["/a~1b", "/~0", "/a~1b/0", "/a~1b/1", "/~0/c"]
+        );
+        for pointer in pointers {
+            let path = NormalizedPath::from_json_pointer_in(&pointer, &doc).unwrap();
+            assert_eq!(doc.reference(path), doc.pointer(&pointer));
+        }
+    }
+
+    #[test]
+    fn navigation_and_order() {
+        let path: NormalizedPath = "$.a[2]".parse().unwrap();
+        let parent = path.parent().unwrap();
+
+        assert_eq!(parent, "$['a']");
+        assert_eq!(parent.child(2), path);
+        assert_eq!(path.last(), Some(&PathElem::Index(2)));
+        assert!(path.starts_with(&parent));
+        assert!(NormalizedPath::root().parent().is_none());
+
+        let mut paths: Vec<NormalizedPath> = ["$.b", "$.a[10]", "$.a", "$.a[2]"]
+            .into_iter()
+            .map(|p| p.parse().unwrap())
+            .collect();
+        paths.sort();
+        assert_eq!(paths, vec!["$['a']", "$['a'][2]", "$['a'][10]", "$['b']"]);
+    }
+}
@@ -1,9 +1,10 @@
 use crate::parser::errors::JsonPathError;
-use crate::parser::model::{JpQuery, Segment, Selector};
-use crate::parser::{parse_json_path, Parsed};
-use crate::query::{Queried, QueryPath};
+use crate::parser::model::{SingularQuery, SingularQuerySegment};
+use crate::parser::parse_singular_query;
+use crate::query::path::{NormalizedPath, PathElem};
+use crate::query::Queried;
 use crate::JsonPath;
-use serde_json::Value;
+use serde_json::{Map, Value};
 use std::borrow::Cow;
 use std::fmt::Debug;
 
@@ -69,6 +70,12 @@ where
     /// and replaced the escape sequences (RFC 9535, section 2.3.1.2).
     fn get(&self, key: &str) -> Option<&Self>;
 
+    /// Retrieves the value associated with the given key along with the key stored in the object,
+    /// the paths of the matched values borrow it instead of copying the key of the query.
+    fn get_key_value(&self, key: &str) -> Option<(Cow<'_, str>, &Self)> {
+        self.get(key).map(|v| (Cow::Owned(key.to_string()), v))
+    }
+
     fn as_array(&self) -> Option<&Vec<Self>>;
 
     fn as_object(&self) -> Option<Vec<(&str, &Self)>>;
@@ -82,18 +89,15 @@ where
     /// Returns a null value.
     fn null() -> Self;
 
-    fn extension_custom(_name: &str, _args: Vec<Cow<Self>>) -> Self {
-        Self::null()
-    }
-
     /// Retrieves a reference to the element at the specified path.
-    /// The path is specified as a string and can be obtained from the query.
+    /// The path is a [`NormalizedPath`] obtained from the query
+    /// or a string with name and index selectors only, like `$.a[1]`.
     ///
     /// # Arguments
-    /// * `path` -  A json path to the element specified as a string (root, field, index only).
-    fn reference<T>(&self, _path: T) -> Option<&Self>
+    /// * `path` -  A path to the element (root, field, index only).
+    fn reference<P>(&self, _path: P) -> Option<&Self>
     where
-        T: Into<QueryPath>,
+        P: TryInto<NormalizedPath>,
     {
         None
     }
@@ -101,7 +105,7 @@ where
     /// Retrieves a mutable reference to the element at the specified path.
     ///
     /// # Arguments
-    /// * `path` -  A json path to the element specified as a string (root, field, index only).
+    /// * `path` -  A path to the element (root, field, index only), see [`Queryable::reference`].
     ///
     /// # Examples
     ///
@@ -117,7 +121,7 @@ where
     ///             }
     ///         });
     ///         if let Some(path) = json.query_only_path("$.a.b.c").unwrap().first() {
-    ///             if let Some(v) = json.reference_mut("$.a.b.c") {
+    ///             if let Some(v) = json.reference_mut(path) {
     ///                 *v = json!(43);
     ///             }
     ///
@@ -133,9 +137,9 @@ where
     ///             );
     /// }
     //// ```
-    fn reference_mut<T>(&mut self, _path: T) -> Option<&mut Self>
+    fn reference_mut<P>(&mut self, _path: P) -> Option<&mut Self>
     where
-        T: Into<QueryPath>,
+        P: TryInto<NormalizedPath>,
     {
         None
     }
@@ -172,6 +176,143 @@ where
             "Deletion not supported".to_string(),
         ))
     }
+
+    /// Removes all elements matching the given JSONPath and returns them with their paths
+    /// in the order of the document, moving them out without cloning.
+    ///
+    /// The elements are removed as by [`Queryable::delete_by_path`]:
+    /// the root is replaced with `null`,
+    /// and an element matched along with its ancestor is taken out of the ancestor first.
+    ///
+    /// # Examples
+    /// ```
+    /// use serde_json::json;
+    /// use jsonpath_rust::query::queryable::Queryable;
+    ///
+    /// let mut data = json!({"queue": [{"id": 1}, {"id": 2}, {"id": 3}]});
+    ///
+    /// let taken = data.take_by_path("$.queue[:2]").unwrap();
+    /// let ids: Vec<_> = taken.into_iter().map(|(path, v)| (path.to_string(), v["id"].clone())).collect();
+    /// assert_eq!(ids, vec![("$['queue'][0]".to_string(), json!(1)), ("$['queue'][1]".to_string(), json!(2))]);
+    /// assert_eq!(data, json!({"queue": [{"id": 3}]}));
+    /// ```
+    fn take_by_path(&mut self, _path: &str) -> Queried<Vec<(NormalizedPath, Self)>> {
+        Err(JsonPathError::InvalidJsonPath(
+            "Taking not supported".to_string(),
+        ))
+    }
+
+    /// Replaces all elements matching the given JSONPath with a copy of the value.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of elements replaced, see [`Queryable::update_by_path`]
+    /// * `Err(JsonPathError)` - If the path is invalid or replacement is not supported
+    ///
+    /// # Examples
+    /// ```
+    /// use serde_json::json;
+    /// use jsonpath_rust::query::queryable::Queryable;
+    ///
+    /// let mut data = json!({"users": [{"age": 30}, {"age": 25}]});
+    ///
+    /// let replaced = data.replace_by_path("$.users[?@.age > 26].age", json!(31)).unwrap();
+    /// assert_eq!(replaced, 1);
+    /// assert_eq!(data, json!({"users": [{"age": 31}, {"age": 25}]}));
+    /// ```
+    fn replace_by_path(&mut self, path: &str, value: Self) -> Queried<usize> {
+        self.update_by_path(path, |_| value.clone())
+    }
+
+    /// Replaces all elements matching the given JSONPath with the result of the function
+    /// applied to the element.
+    ///
+    /// The matches are updated one by one, the later siblings and the descendants
+    /// before the earlier siblings and the ancestors,
+    /// so when the query selects a node along with its descendants (like `$..*`)
+    /// the function gets the node with the descendants already updated.
+    /// A node selected several times (like `$[0,0]`) is updated once.
+    ///
+    /// # Returns
+    /// * `Ok(usize)` - Number of elements updated
+    /// * `Err(JsonPathError)` - If the path is invalid or updating is not supported
+    ///
+    /// # Examples
+    /// ```
+    /// use serde_json::json;
+    /// use jsonpath_rust::query::queryable::Queryable;
+    ///
+    /// let mut data = json!({"prices": [10, 15, 20]});
+    ///
+    /// let updated = data
+    ///     .update_by_path("$.prices[1:]", |p| json!(p.as_i64().unwrap_or(0) * 2))
+    ///     .unwrap();
+    /// assert_eq!(updated, 2);
+    /// assert_eq!(data, json!({"prices": [10, 30, 40]}));
+    /// ```
+    fn update_by_path<F>(&mut self, _path: &str, _f: F) -> Queried<usize>
+    where
+        F: FnMut(Self) -> Self,
+    {
+        Err(JsonPathError::InvalidJsonPath(
+            "Update not supported".to_string(),
+        ))
+    }
+
+    /// Sets the element at the singular query creating the missing parents,
+    /// an object for a name segment and an array for an index segment.
+    /// A `null` on the way is replaced with the parent as if it were missing.
+    ///
+    /// The query is evaluated against `self` whether it starts with `$` or `@`.
+    /// A negative index counts from the end of an existing array,
+    /// an index past the end is handled according to `gaps`.
+    ///
+    /// # Returns
+    /// * `Ok(Some(old))` - The element replaced
+    /// * `Ok(None)` - If the element has been inserted
+    /// * `Err(JsonPathError)` - If a node on the way is neither a container of the right kind nor `null`,
+    ///   if an index is out of the bounds the policy allows or upserting is not supported.
+    ///   The structure is left untouched then.
+    fn upsert(
+        &mut self,
+        _query: &SingularQuery,
+        _value: Self,
+        _gaps: GapPolicy,
+    ) -> Queried<Option<Self>> {
+        Err(JsonPathError::InvalidJsonPath(
+            "Upsert not supported".to_string(),
+        ))
+    }
+
+    /// Parses the singular query and sets the element at it creating the missing parents,
+    /// an index is accepted up to the length of the array, see [`Queryable::upsert`].
+    ///
+    /// # Examples
+    /// ```
+    /// use serde_json::json;
+    /// use jsonpath_rust::query::queryable::Queryable;
+    ///
+    /// let mut config = json!({"config": {"name": "default"}});
+    ///
+    /// let old = config.upsert_by_path("$.config.limits.max", json!(10)).unwrap();
+    /// assert_eq!(old, None);
+    /// assert_eq!(config, json!({"config": {"name": "default", "limits": {"max": 10}}}));
+    /// ```
+    fn upsert_by_path(&mut self, path: &str, value: Self) -> Queried<Option<Self>> {
+        self.upsert(&parse_singular_query(path)?, value, GapPolicy::default())
+    }
+}
+
+/// What [`Queryable::upsert`] does with an index past the end of an array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GapPolicy {
+    /// Accepts only the index equal to the length, appending the element.
+    #[default]
+    Reject,
+    /// Fills a gap of up to the given number of nulls, so the element ends up at the index,
+    /// a larger gap is rejected.
+    FillWithNull(usize),
+    /// Appends the element whatever the index.
+    Append,
 }
 
 impl Queryable for Value {
@@ -179,6 +320,12 @@ impl Queryable for Value {
         self.get(key)
     }
 
+    fn get_key_value(&self, key: &str) -> Option<(Cow<'_, str>, &Self)> {
+        self.as_object()?
+            .get_key_value(key)
+            .map(|(k, v)| (Cow::Borrowed(k.as_str()), v))
+    }
+
     fn as_array(&self) -> Option<&Vec<Self>> {
         self.as_array()
     }
@@ -208,303 +355,208 @@ impl Queryable for Value {
         Value::Null
     }
 
-    /// Custom extension function for JSONPath queries.
-    ///
-    /// This function allows for custom operations to be performed on JSON data
-    /// based on the provided `name` and `args`.
-    ///
-    /// # Arguments
-    ///
-    /// * `name` - A string slice that holds the name of the custom function.
-    /// * `args` - A vector of `Cow<Self>` that holds the arguments for the custom function.
-    ///
-    /// # Returns
-    ///
-    /// Returns a `Self` value which is the result of the custom function. If the function
-    /// name is not recognized, it returns `Self::null()`.
-    ///
-    /// # Custom Functions
-    ///
-    /// * `"in"` - Checks if the first argument is in the array provided as the second argument.
-    ///   Example: `$.elems[?in(@, $.list)]` - Returns elements from $.elems that are present in $.list
-    ///
-    /// * `"nin"` - Checks if the first argument is not in the array provided as the second argument.
-    ///   Example: `$.elems[?nin(@, $.list)]` - Returns elements from $.elems that are not present in $.list
-    ///
-    /// * `"none_of"` - Checks if none of the elements in the first array are in the second array.
-    ///   Example: `$.elems[?none_of(@, $.list)]` - Returns arrays from $.elems that have no elements in common with $.list
-    ///
-    /// * `"any_of"` - Checks if any of the elements in the first array are in the second array.
-    ///   Example: `$.elems[?any_of(@, $.list)]` - Returns arrays from $.elems that have at least one element in common with $.list
-    ///
-    /// * `"subset_of"` - Checks if all elements in the first array are in the second array.
-    ///   Example: `$.elems[?subset_of(@, $.list)]` - Returns arrays from $.elems where all elements are present in $.list
-    fn extension_custom(name: &str, args: Vec<Cow<Self>>) -> Self {
-        match name {
-            "in" => match args.as_slice() {
-                [lhs, rhs] => match rhs.as_array() {
-                    Some(elements) => elements.iter().any(|item| item == lhs.as_ref()).into(),
-                    None => Self::null(),
-                },
-                _ => Self::null(),
-            },
-            "nin" => match args.as_slice() {
-                [lhs, rhs] => match rhs.as_array() {
-                    Some(elements) => (!elements.iter().any(|item| item == lhs.as_ref())).into(),
-                    None => Self::null(),
-                },
-                _ => Self::null(),
-            },
-            "none_of" => match args.as_slice() {
-                [lhs, rhs] => match (lhs.as_array(), rhs.as_array()) {
-                    (Some(lhs_arr), Some(rhs_arr)) => lhs_arr
-                        .iter()
-                        .all(|lhs| !rhs_arr.iter().any(|rhs| lhs == rhs))
-                        .into(),
-                    _ => Self::null(),
-                },
-                _ => Self::null(),
-            },
-            "any_of" => match args.as_slice() {
-                [lhs, rhs] => match (lhs.as_array(), rhs.as_array()) {
-                    (Some(lhs_arr), Some(rhs_arr)) => lhs_arr
-                        .iter()
-                        .any(|lhs| rhs_arr.iter().any(|rhs| lhs == rhs))
-                        .into(),
-                    _ => Self::null(),
-                },
-                _ => Self::null(),
-            },
-            "subset_of" => match args.as_slice() {
-                [lhs, rhs] => match (lhs.as_array(), rhs.as_array()) {
-                    (Some(lhs_arr), Some(rhs_arr)) => lhs_arr
-                        .iter()
-                        .all(|lhs| rhs_arr.iter().any(|rhs| lhs == rhs))
-                        .into(),
-                    _ => Self::null(),
-                },
-                _ => Self::null(),
-            },
-            _ => Self::null(),
-        }
-    }
-
-    fn reference<T>(&self, path: T) -> Option<&Self>
+    fn reference<P>(&self, path: P) -> Option<&Self>
     where
-        T: Into<QueryPath>,
+        P: TryInto<NormalizedPath>,
     {
-        convert_js_path(&path.into())
-            .ok()
-            .and_then(|p| self.pointer(p.as_str()))
+        let path = path.try_into().ok()?;
+        path.iter().try_fold(self, |node, elem| match elem {
+            PathElem::Name(name) => node.as_object()?.get(name.as_str()),
+            PathElem::Index(index) => node.as_array()?.get(*index),
+        })
     }
 
-    fn reference_mut<T>(&mut self, path: T) -> Option<&mut Self>
+    fn reference_mut<P>(&mut self, path: P) -> Option<&mut Self>
     where
-        T: Into<QueryPath>,
+        P: TryInto<NormalizedPath>,
     {
-        convert_js_path(&path.into())
-            .ok()
-            .and_then(|p| self.pointer_mut(p.as_str()))
+        let path = path.try_into().ok()?;
+        path.iter().try_fold(self, |node, elem| match elem {
+            PathElem::Name(name) => node.as_object_mut()?.get_mut(name.as_str()),
+            PathElem::Index(index) => node.as_array_mut()?.get_mut(*index),
+        })
     }
 
     fn delete_by_path(&mut self, path: &str) -> Queried<usize> {
-        let mut deletions = Vec::new();
-        for query_path in &self.query_only_path(path)? {
-            if let Some(deletion_info) = parse_deletion_path(query_path)? {
-                deletions.push(deletion_info);
-            }
-        }
-
-        // Sort deletions to handle array indices correctly (delete from end to start)
-        deletions.sort_by(|a, b| {
-            b.path_depth()
-                .cmp(&a.path_depth())
-                .then_with(|| match (a, b) {
-                    (
-                        DeletionInfo::ArrayIndex { index: idx_a, .. },
-                        DeletionInfo::ArrayIndex { index: idx_b, .. },
-                    ) => idx_b.cmp(idx_a),
-                    _ => std::cmp::Ordering::Equal,
-                })
-        });
-
-        // Perform deletions
-        let deleted_count = deletions.iter().try_fold(0, |c, d| {
-            execute_deletion(self, d).map(|deleted| if deleted { c + 1 } else { c })
-        })?;
+        let paths = deepest_first(self.query_only_path(path)?);
 
-        Ok(deleted_count)
+        Ok(paths
+            .into_iter()
+            .filter(|path| remove(self, path).is_some())
+            .count())
     }
-}
-
-#[derive(Debug, Clone)]
-enum DeletionInfo {
-    ObjectField {
-        parent_path: String,
-        field_name: String,
-    },
-    ArrayIndex {
-        parent_path: String,
-        index: usize,
-    },
-    Root,
-}
 
-impl DeletionInfo {
-    fn path_depth(&self) -> usize {
-        match self {
-            DeletionInfo::Root => 0,
-            DeletionInfo::ObjectField { parent_path, .. }
-            | DeletionInfo::ArrayIndex { parent_path, .. } => parent_path.matches('/').count(),
+    fn take_by_path(&mut self, path: &str) -> Queried<Vec<(NormalizedPath, Self)>> {
+        let mut taken = vec![];
+        for path in deepest_first(self.query_only_path(path)?) {
+            if let Some(v) = remove(self, &path) {
+                taken.push((path, v));
+            }
         }
-    }
-}
-
-/// Encodes a member name as a JSON Pointer reference token (RFC 6901, section 3).
-fn pointer_token(name: &str) -> String {
-    name.replace('~', "~0").replace('/', "~1")
-}
-
-fn parse_deletion_path(query_path: &str) -> Result<Option<DeletionInfo>, JsonPathError> {
-    if query_path == "$" {
-        return Ok(Some(DeletionInfo::Root));
-    }
-
-    let JpQuery { segments } = parse_json_path(query_path)?;
-
-    if segments.is_empty() {
-        return Ok(None);
-    }
-
-    let mut parent_path = String::new();
-    let mut segments_iter = segments.iter().peekable();
-
-    while let Some(segment) = segments_iter.next() {
-        if segments_iter.peek().is_some() {
-            // Not the last segment, add to parent path
-            match segment {
-                Segment::Selector(Selector::Name(name)) => {
-                    parent_path.push_str(&format!("/{}", pointer_token(name)));
+        taken.reverse();
+        Ok(taken)
+    }
+
+    fn upsert(
+        &mut self,
+        query: &SingularQuery,
+        value: Self,
+        gaps: GapPolicy,
+    ) -> Queried<Option<Self>> {
+        let (SingularQuery::Current(segments) | SingularQuery::Root(segments)) = query;
+
+        // the longest part of the path existing in the structure, nothing is changed until it is checked
+        let mut existing = NormalizedPath::root();
+        let mut node = &*self;
+        for segment in segments {
+            let next = match (node, segment) {
+                (Value::Object(obj), SingularQuerySegment::Name(name)) => {
+                    obj.get(name).map(|n| (PathElem::Name(name.clone()), n))
                 }
-                Segment::Selector(Selector::Index(index)) => {
-                    parent_path.push_str(&format!("/{}", index));
+                (Value::Array(arr), SingularQuerySegment::Index(index)) => {
+                    let len = arr.len() as i64;
+                    let i = if *index < 0 { len + index } else { *index };
+                    (0..len)
+                        .contains(&i)
+                        .then(|| (PathElem::Index(i as usize), &arr[i as usize]))
                 }
-                e => {
+                (Value::Null, _) => None,
+                (_, segment) => {
                     return Err(JsonPathError::InvalidJsonPath(format!(
-                        "Unsupported segment to be deleted: {:?}",
-                        e
-                    )));
+                        "The element at {} is not {} to take the segment `{}`",
+                        existing,
+                        match segment {
+                            SingularQuerySegment::Name(_) => "an object",
+                            SingularQuerySegment::Index(_) => "an array",
+                        },
+                        segment
+                    )))
                 }
-            }
-        } else {
-            match segment {
-                Segment::Selector(Selector::Name(name)) => {
-                    let field_name = name.to_string();
-                    return Ok(Some(DeletionInfo::ObjectField {
-                        parent_path,
-                        field_name,
-                    }));
-                }
-                Segment::Selector(Selector::Index(index)) => {
-                    return Ok(Some(DeletionInfo::ArrayIndex {
-                        parent_path,
-                        index: *index as usize,
-                    }));
-                }
-                e => {
-                    return Err(JsonPathError::InvalidJsonPath(format!(
-                        "Unsupported segment to be deleted: {:?}",
-                        e
-                    )));
+            };
+            match next {
+                Some((elem, n)) => {
+                    existing.push(elem);
+                    node = n;
                 }
+                None => break,
             }
         }
-    }
 
-    Ok(None)
-}
-
-fn execute_deletion(value: &mut Value, deletion: &DeletionInfo) -> Queried<bool> {
-    match deletion {
-        DeletionInfo::Root => {
-            *value = Value::Null;
-            Ok(true)
+        let Some((first, missing)) = segments[existing.len()..].split_first() else {
+            let node = self.reference_mut(&existing);
+            return Ok(node.map(|n| std::mem::replace(n, value)));
+        };
+        let value = missing
+            .iter()
+            .rev()
+            .try_fold(value, |v, segment| wrap(segment, v, gaps))?;
+        match (self.reference_mut(&existing), first) {
+            (Some(Value::Object(obj)), SingularQuerySegment::Name(name)) => {
+                obj.insert(name.clone(), value);
+            }
+            (Some(Value::Array(arr)), SingularQuerySegment::Index(index)) => {
+                place(arr, *index, value, gaps)?
+            }
+            (Some(node), segment) => *node = wrap(segment, value, gaps)?,
+            (None, _) => {}
         }
-        DeletionInfo::ObjectField {
-            parent_path,
-            field_name,
-        } => {
-            let parent = if parent_path.is_empty() {
-                value
-            } else {
-                value.pointer_mut(parent_path).ok_or_else(|| {
-                    JsonPathError::InvalidJsonPath("Parent path not found".to_string())
-                })?
-            };
+        Ok(None)
+    }
 
-            if let Some(obj) = parent.as_object_mut() {
-                Ok(obj.remove(field_name).is_some())
-            } else {
-                Err(JsonPathError::InvalidJsonPath(
-                    "Parent is not an object".to_string(),
-                ))
+    fn update_by_path<F>(&mut self, path: &str, mut f: F) -> Queried<usize>
+    where
+        F: FnMut(Self) -> Self,
+    {
+        let mut updated = 0;
+        for path in deepest_first(self.query_only_path(path)?) {
+            if let Some(node) = self.reference_mut(path) {
+                *node = f(std::mem::take(node));
+                updated += 1;
             }
         }
-        DeletionInfo::ArrayIndex { parent_path, index } => {
-            let parent = if parent_path.is_empty() {
-                value
-            } else {
-                value.pointer_mut(parent_path).ok_or_else(|| {
-                    JsonPathError::InvalidJsonPath("Parent path not found".to_string())
-                })?
-            };
+        Ok(updated)
+    }
+}
 
-            if let Some(arr) = parent.as_array_mut() {
-                if *index < arr.len() {
-                    arr.remove(*index);
-                    Ok(true)
-                } else {
-                    Ok(false) // Index out of bounds
-                }
-            } else {
-                Err(JsonPathError::InvalidJsonPath(
-                    "Parent is not an array".to_string(),
-                ))
-            }
+/// Puts the element into the array past its end according to the policy.
+fn place(array: &mut Vec<Value>, index: i64, value: Value, gaps: GapPolicy) -> Queried<()> {
+    let len = array.len() as i64;
+    match gaps {
+        _ if index == len => {}
+        GapPolicy::FillWithNull(max) if index > len && index - len <= max as i64 => {
+            array.resize(index as usize, Value::Null)
+        }
+        GapPolicy::Append if index > len => {}
+        _ => {
+            return Err(JsonPathError::InvalidJsonPath(format!(
+                "The index {} is out of the bounds of the array of {} elements",
+                index, len
+            )))
         }
     }
+    array.push(value);
+    Ok(())
 }
 
-fn convert_js_path(path: &str) -> Parsed<String> {
-    let JpQuery { segments } = parse_json_path(path)?;
+/// Builds the parent holding the element under the segment.
+fn wrap(segment: &SingularQuerySegment, value: Value, gaps: GapPolicy) -> Queried<Value> {
+    match segment {
+        SingularQuerySegment::Name(name) => {
+            Ok(Value::Object(Map::from_iter([(name.clone(), value)])))
+        }
+        SingularQuerySegment::Index(index) => {
+            let mut array = vec![];
+            place(&mut array, *index, value, gaps)?;
+            Ok(Value::Array(array))
+        }
+    }
+}
 
-    let mut path = String::new();
-    for segment in segments {
-        match segment {
-            Segment::Selector(Selector::Name(name)) => {
-                path.push_str(&format!("/{}", pointer_token(&name)));
-            }
-            Segment::Selector(Selector::Index(index)) => {
-                path.push_str(&format!("/{}", index));
-            }
-            s => {
-                return Err(JsonPathError::InvalidJsonPath(format!(
-                    "Invalid segment: {:?}",
-                    s
-                )));
-            }
+/// Removes the element at the path from its parent and returns it,
+/// the root is replaced with `null`.
+pub(crate) fn remove(value: &mut Value, path: &NormalizedPath) -> Option<Value> {
+    let Some((last, parent)) = path.elems().split_last() else {
+        return Some(std::mem::take(value));
+    };
+    match (value.reference_mut(parent.to_vec()), last) {
+        (Some(Value::Object(obj)), PathElem::Name(name)) => obj.remove(name),
+        (Some(Value::Array(arr)), PathElem::Index(index)) if *index < arr.len() => {
+            Some(arr.remove(*index))
         }
+        _ => None,
     }
-    Ok(path)
+}
+
+/// Orders the paths so the later siblings and the descendants go first
+/// and dropping or replacing a node keeps the rest of the paths valid.
+pub(crate) fn deepest_first(mut paths: Vec<NormalizedPath>) -> Vec<NormalizedPath> {
+    paths.sort_unstable_by(|a, b| b.cmp(a));
+    paths.dedup();
+    paths
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::parser::parse_singular_query;
     use crate::parser::Parsed;
-    use crate::query::queryable::{convert_js_path, Queryable};
+    use crate::query::path::NormalizedPath;
+    use crate::query::queryable::{GapPolicy, Queryable};
     use crate::query::Queried;
     use crate::JsonPath;
     use serde_json::{json, Value};
+    use std::borrow::Cow;
+
+    #[test]
+    fn key_of_the_object_is_borrowed() {
+        let json = json!({"a": 1});
+
+        assert!(matches!(
+            Queryable::get_key_value(&json, "a"),
+            Some((Cow::Borrowed("a"), v)) if v == &json!(1)
+        ));
+        assert_eq!(Queryable::get_key_value(&json, "b"), None);
+        assert_eq!(Queryable::get_key_value(&json!([1]), "a"), None);
+    }
 
     #[test]
     fn in_smoke() -> Queried<()> {
@@ -574,7 +626,7 @@ mod tests {
 
     #[test]
     fn convert_paths() -> Parsed<()> {
-        let r = convert_js_path("$.a.b[2]")?;
+        let r = "$.a.b[2]".parse::<NormalizedPath>()?.to_json_pointer();
         assert_eq!(r, "/a/b/2");
 
         Ok(())
@@ -590,7 +642,7 @@ mod tests {
             }
         });
 
-        let r = convert_js_path("$.a.b.c")?;
+        let r = "$.a.b.c".parse::<NormalizedPath>()?.to_json_pointer();
 
         if let Some(v) = json.pointer_mut(r.as_str()) {
             *v = json!(43);
@@ -742,6 +794,121 @@ mod tests {
         assert_eq!(data, expected);
     }
 
+    #[test]
+    fn test_update_overlapping() -> Queried<()> {
+        let mut data = json!({"a": [1, [2]], "b": 3});
+
+        let updated = data.update_by_path("$..*", |v| match v {
+            Value::Number(n) => json!(n.as_i64().unwrap_or(0) + 10),
+            Value::Array(items) => json!({"items": items}),
+            v => v,
+        })?;
+        assert_eq!(updated, 5);
+        assert_eq!(
+            data,
+            json!({"a": {"items": [11, {"items": [12]}]}, "b": 13})
+        );
+
+        assert_eq!(data.replace_by_path("$.a.items[0, 0, 5]", json!(0))?, 1);
+        assert_eq!(data.replace_by_path("$", json!(true))?, 1);
+        assert_eq!(data, json!(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_take_by_path() -> Queried<()> {
+        let mut data = json!({"a": [{"b": 1}, {"b": 2}], "c": {"b": 3}});
+
+        let taken = data.take_by_path("$..b")?;
+        let paths: Vec<String> = taken.iter().map(|(p, _)| p.to_string()).collect();
+        assert_eq!(
+            paths,
+            vec!["$['a'][0]['b']", "$['a'][1]['b']", "$['c']['b']"]
+        );
+        assert_eq!(
+            taken.into_iter().map(|(_, v)| v).collect::<Vec<_>>(),
+            vec![json!(1), json!(2), json!(3)]
+        );
+        assert_eq!(data, json!({"a": [{}, {}], "c": {}}));
+
+        let owned = {
+            let doc = json!({"x": [1, 2]});
+            doc.query_owned("$.x[1]")?
+        };
+        assert_eq!(owned, vec![("$['x'][1]".parse()?, json!(2))]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert() -> Queried<()> {
+        let mut data = json!({"config": {"limits": null, "tags": ["a"]}});
+
+        assert_eq!(data.upsert_by_path("$.config.limits.max", json!(10))?, None);
+        assert_eq!(
+            data.upsert_by_path("$.config.tags[-1]", json!("b"))?,
+            Some(json!("a"))
+        );
+        assert_eq!(data.upsert_by_path("$.config.tags[1]", json!("c"))?, None);
+        assert_eq!(data.upsert_by_path("$.new[0][0]['x y']", json!(1))?, None);
+        assert_eq!(
+            data,
+            json!({
+                "config": {"limits": {"max": 10}, "tags": ["b", "c"]},
+                "new": [[{"x y": 1}]]
+            })
+        );
+
+        let before = data.clone();
+        assert!(data.upsert_by_path("$.config.tags[3]", json!("d")).is_err());
+        assert!(data.upsert_by_path("$.config.tags.a", json!(1)).is_err());
+        assert!(data
+            .upsert_by_path("$.config.limits.max.a", json!(1))
+            .is_err());
+        assert!(data.upsert_by_path("$.other[-1].a", json!(1)).is_err());
+        assert!(data.upsert_by_path("$.config[*]", json!(1)).is_err());
+        assert_eq!(data, before);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_upsert_gaps() -> Queried<()> {
+        let query = parse_singular_query("$.a[3]")?;
+
+        let mut data = json!({"a": [0]});
+        data.upsert(&query, json!(3), GapPolicy::FillWithNull(2))?;
+        assert_eq!(data, json!({"a": [0, null, null, 3]}));
+
+        let mut data = json!({"a": [0]});
+        assert!(data
+            .upsert(&query, json!(3), GapPolicy::FillWithNull(1))
+            .is_err());
+        assert!(data
+            .upsert(
+                &parse_singular_query("$.a[9007199254740991]")?,
+                json!(3),
+                GapPolicy::FillWithNull(1024)
+            )
+            .is_err());
+        assert_eq!(data, json!({"a": [0]}));
+
+        let mut data = json!({"a": [0]});
+        data.upsert(&query, json!(3), GapPolicy::Append)?;
+        assert_eq!(data, json!({"a": [0, 3]}));
+
+        let mut data = json!({});
+        data.upsert(
+            &parse_singular_query("@.a[2].b")?,
+            json!(1),
+            GapPolicy::FillWithNull(2),
+        )?;
+        assert_eq!(data, json!({"a": [null, null, {"b": 1}]}));
+
+        Ok(())
+    }
+
     #[test]
     fn test_delete_root() {
         let mut data = json!({
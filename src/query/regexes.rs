@@ -0,0 +1,402 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::model::{
+    Comparable, Filter, FilterAtom, FnArg, JpQuery, Literal, Segment, Selector, Test, TestFunction,
+};
+use crate::parser::Parsed;
+use crate::query::iregexp::translate;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// How many regular expressions built from the documents are kept compiled by default.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+/// How the patterns of `match` and `search` are read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum RegexMode {
+    /// The patterns are I-Regexps (RFC 9485) as RFC 9535 requires,
+    /// the other ones are invalid, see [`crate::query::iregexp::translate`].
+    #[default]
+    IRegexp,
+    /// The patterns are in the dialect of the `regex` crate,
+    /// `match` only adds the missing `^` and `$` anchors.
+    Lenient,
+}
+
+/// The regular expressions of `match` and `search`,
+/// by the pattern and whether it is searched for as a substring.
+///
+/// The literal patterns of a compiled query are compiled along with it,
+/// the patterns taken from the documents are compiled on the first use
+/// and kept in a bounded cache dropping the least recently used ones.
+/// The compiled expressions are shared, so are the buffers `regex` keeps for every thread.
+#[derive(Debug, Default)]
+pub struct Regexes {
+    pub(crate) literals: ByPattern<Arc<Regex>>,
+    cache: Mutex<Lru>,
+    mode: RegexMode,
+}
+
+impl Regexes {
+    /// Compiles the patterns, the invalid ones are left out
+    /// (they never match, so there is nothing to compile).
+    pub fn compile<I: IntoIterator<Item = (String, bool)>>(
+        patterns: I,
+        capacity: usize,
+        mode: RegexMode,
+    ) -> Self {
+        let mut regexes = Regexes {
+            literals: ByPattern::default(),
+            cache: Mutex::new(Lru::new(capacity)),
+            mode,
+        };
+        for (pattern, substr) in patterns {
+            regexes.insert(pattern, substr);
+        }
+        regexes
+    }
+
+    /// Compiles the literal patterns of the query, the invalid ones are left out.
+    pub(crate) fn add_literals(&mut self, query: &JpQuery) {
+        for (pattern, substr) in regex_literals(query) {
+            self.insert(pattern, substr);
+        }
+    }
+
+    fn insert(&mut self, pattern: String, substr: bool) {
+        if let Ok(re) = build(&pattern, substr, self.mode) {
+            self.literals.insert(pattern, substr, Arc::new(re));
+        }
+    }
+
+    /// How the patterns are read.
+    pub fn mode(&self) -> RegexMode {
+        self.mode
+    }
+
+    /// Takes the compiled literal patterns of another query, compiled in the same mode.
+    pub(crate) fn merge(&mut self, other: &Regexes) {
+        for (substr, literals) in [
+            (false, &other.literals.matched),
+            (true, &other.literals.searched),
+        ] {
+            for (pattern, re) in literals {
+                self.literals.insert(pattern.clone(), substr, re.clone());
+            }
+        }
+    }
+
+    /// Returns the compiled pattern, `None` if it is invalid.
+    pub fn get(&self, pattern: &str, substr: bool) -> Option<Arc<Regex>> {
+        if let Some(re) = self.literals.get(pattern, substr) {
+            return Some(re.clone());
+        }
+        // a poisoned cache is still consistent, the entries are replaced as a whole
+        let lock = || self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(re) = lock().get(pattern, substr) {
+            return re;
+        }
+        // the other threads are not held up while the pattern is compiled
+        let re = build(pattern, substr, self.mode).ok().map(Arc::new);
+        lock().insert(pattern, substr, re.clone());
+        re
+    }
+}
+
+impl Clone for Regexes {
+    fn clone(&self) -> Self {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        Regexes {
+            literals: self.literals.clone(),
+            cache: Mutex::new(Lru::new(cache.capacity)),
+            mode: self.mode,
+        }
+    }
+}
+
+/// The values by the pattern, apart for `match` and `search`,
+/// so they are looked up with a borrowed pattern.
+#[derive(Debug, Clone)]
+pub(crate) struct ByPattern<V> {
+    matched: HashMap<String, V>,
+    searched: HashMap<String, V>,
+}
+
+impl<V> Default for ByPattern<V> {
+    fn default() -> Self {
+        ByPattern {
+            matched: HashMap::new(),
+            searched: HashMap::new(),
+        }
+    }
+}
+
+impl<V> ByPattern<V> {
+    fn of(&self, substr: bool) -> &HashMap<String, V> {
+        if substr {
+            &self.searched
+        } else {
+            &self.matched
+        }
+    }
+
+    fn of_mut(&mut self, substr: bool) -> &mut HashMap<String, V> {
+        if substr {
+            &mut self.searched
+        } else {
+            &mut self.matched
+        }
+    }
+
+    pub(crate) fn get(&self, pattern: &str, substr: bool) -> Option<&V> {
+        self.of(substr).get(pattern)
+    }
+
+    fn insert(&mut self, pattern: String, substr: bool, value: V) {
+        self.of_mut(substr).insert(pattern, value);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.matched.len() + self.searched.len()
+    }
+}
+
+/// The least recently used entries go first when the cache is full.
+#[derive(Debug)]
+struct Lru {
+    capacity: usize,
+    tick: u64,
+    entries: ByPattern<(Option<Arc<Regex>>, u64)>,
+}
+
+impl Default for Lru {
+    fn default() -> Self {
+        Lru::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            tick: 0,
+            entries: ByPattern::default(),
+        }
+    }
+
+    /// The cached pattern, `Some(None)` if it is known to be invalid.
+    fn get(&mut self, pattern: &str, substr: bool) -> Option<Option<Arc<Regex>>> {
+        self.tick += 1;
+        let (re, used) = self.entries.of_mut(substr).get_mut(pattern)?;
+        *used = self.tick;
+        Some(re.clone())
+    }
+
+    fn insert(&mut self, pattern: &str, substr: bool, re: Option<Arc<Regex>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && self.entries.get(pattern, substr).is_none() {
+            let oldest = [false, true]
+                .into_iter()
+                .flat_map(|substr| {
+                    self.entries
+                        .of(substr)
+                        .iter()
+                        .map(move |(p, (_, used))| (*used, substr, p))
+                })
+                .min()
+                .map(|(_, substr, p)| (substr, p.clone()));
+            if let Some((substr, pattern)) = oldest {
+                self.entries.of_mut(substr).remove(&pattern);
+            }
+        }
+        self.tick += 1;
+        self.entries
+            .insert(pattern.to_string(), substr, (re, self.tick));
+    }
+}
+
+/// Compiles the pattern, `match` (not `substr`) has to match the whole string.
+fn build(pattern: &str, substr: bool, mode: RegexMode) -> Result<Regex, String> {
+    let re = match mode {
+        RegexMode::IRegexp => {
+            let re = translate(pattern).map_err(|e| match e {
+                JsonPathError::InvalidJsonPath(reason) => reason,
+                e => e.to_string(),
+            })?;
+            if substr {
+                re
+            } else {
+                format!("\\A(?:{})\\z", re)
+            }
+        }
+        RegexMode::Lenient => prepare_regex(pattern.to_string(), substr),
+    };
+    Regex::new(&re).map_err(|e| e.to_string())
+}
+
+/// Compiles the literal patterns of `match` and `search`,
+/// they have to be valid regular expressions.
+pub(crate) fn compile_literals(
+    query: &JpQuery,
+    mode: RegexMode,
+    capacity: usize,
+) -> Parsed<Regexes> {
+    let mut regexes = Regexes::compile([], capacity, mode);
+    for (pattern, substr) in regex_literals(query) {
+        match build(&pattern, substr, mode) {
+            Ok(re) => regexes.literals.insert(pattern, substr, Arc::new(re)),
+            Err(e) => {
+                return Err(JsonPathError::InvalidJsonPath(format!(
+                    "Invalid regular expression `{}` in the function `{}`: {}",
+                    pattern,
+                    if substr { "search" } else { "match" },
+                    e
+                )))
+            }
+        }
+    }
+    Ok(regexes)
+}
+
+/// The patterns of the `match` and `search` calls given as string literals,
+/// with whether the pattern is searched for as a substring.
+pub(crate) fn regex_literals(query: &JpQuery) -> Vec<(String, bool)> {
+    let mut patterns = vec![];
+    segments(&query.segments, &mut patterns);
+    patterns
+}
+
+fn segments(segments: &[Segment], acc: &mut Vec<(String, bool)>) {
+    for segment in segments {
+        match segment {
+            Segment::Descendant(s) => self::segments(std::slice::from_ref(s), acc),
+            Segment::Selector(s) => selector(s, acc),
+            Segment::Selectors(ss) => ss.iter().for_each(|s| selector(s, acc)),
+        }
+    }
+}
+
+fn selector(selector: &Selector, acc: &mut Vec<(String, bool)>) {
+    if let Selector::Filter(f) = selector {
+        filter(f, acc)
+    }
+}
+
+fn filter(filter: &Filter, acc: &mut Vec<(String, bool)>) {
+    match filter {
+        Filter::Or(fs) | Filter::And(fs) => fs.iter().for_each(|f| self::filter(f, acc)),
+        Filter::Atom(FilterAtom::Filter { expr, .. }) => self::filter(expr, acc),
+        Filter::Atom(FilterAtom::Test { expr, .. }) => test(expr, acc),
+        Filter::Atom(FilterAtom::Comparison(cmp)) => {
+            let (lhs, rhs) = cmp.vals();
+            for c in [lhs, rhs] {
+                if let Comparable::Function(f) = c {
+                    function(f, acc)
+                }
+            }
+        }
+    }
+}
+
+fn test(test: &Test, acc: &mut Vec<(String, bool)>) {
+    match test {
+        Test::RelQuery(s) => segments(s, acc),
+        Test::AbsQuery(q) => segments(&q.segments, acc),
+        Test::Function(f) => function(f, acc),
+    }
+}
+
+fn function(function: &TestFunction, acc: &mut Vec<(String, bool)>) {
+    let args = match function {
+        TestFunction::Match(lhs, rhs) | TestFunction::Search(lhs, rhs) => {
+            if let FnArg::Literal(Literal::String(pattern)) = rhs {
+                let substr = matches!(function, TestFunction::Search(..));
+                acc.push((pattern.clone(), substr));
+            }
+            vec![lhs, rhs]
+        }
+        TestFunction::Custom(_, args) => args.iter().collect(),
+        TestFunction::Length(arg) => vec![arg.as_ref()],
+        TestFunction::Value(arg) | TestFunction::Count(arg) => vec![arg],
+    };
+    for arg in args {
+        match arg {
+            FnArg::Literal(_) => {}
+            FnArg::Test(t) => test(t, acc),
+            FnArg::Filter(f) => filter(f, acc),
+        }
+    }
+}
+
+pub(crate) fn prepare_regex(pattern: String, substring: bool) -> String {
+    let pattern = if !substring {
+        let pattern = if pattern.starts_with('^') {
+            pattern
+        } else {
+            format!("^{}", pattern)
+        };
+        let pattern = if pattern.ends_with('$') {
+            pattern
+        } else {
+            format!("{}$", pattern)
+        };
+        pattern
+    } else {
+        pattern.to_string()
+    };
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::regexes::{Lru, RegexMode, Regexes};
+    use std::sync::Arc;
+
+    #[test]
+    fn lru_drops_least_recently_used() {
+        let mut lru = Lru::new(2);
+        let mut built = 0;
+        let mut get = |lru: &mut Lru, p: &str| match lru.get(p, false) {
+            Some(re) => re,
+            None => {
+                built += 1;
+                let re = regex::Regex::new(p).ok().map(Arc::new);
+                lru.insert(p, false, re.clone());
+                re
+            }
+        };
+
+        get(&mut lru, "a");
+        get(&mut lru, "b");
+        get(&mut lru, "a");
+        get(&mut lru, "c");
+        assert!(get(&mut lru, "(").is_none());
+        get(&mut lru, "a");
+        assert_eq!(built, 5);
+        assert!(lru.entries.get("a", false).is_some());
+        assert!(lru.entries.get("b", false).is_none());
+        assert!(lru.entries.get("a", true).is_none());
+    }
+
+    #[test]
+    fn shared_compiled_patterns() {
+        let regexes = Regexes::compile([("a+".to_string(), false)], 4, RegexMode::IRegexp);
+
+        let literal = regexes.get("a+", false).expect("valid");
+        assert!(Arc::ptr_eq(
+            &literal,
+            &regexes.get("a+", false).expect("valid")
+        ));
+        assert!(literal.is_match("aa") && !literal.is_match("ab"));
+
+        let searched = regexes.get("a+", true).expect("valid");
+        assert!(searched.is_match("ab"));
+        assert!(Arc::ptr_eq(
+            &searched,
+            &regexes.get("a+", true).expect("valid")
+        ));
+        assert!(regexes.get("a(", true).is_none());
+    }
+}
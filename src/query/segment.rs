@@ -1,15 +1,20 @@
 use crate::parser::model::{Segment, Selector};
+use crate::query::explain::{trace, TraceKind};
+use crate::query::parallel::{run_ordered, Task};
 use crate::query::queryable::Queryable;
-use crate::query::state::{Data, Pointer, State};
+use crate::query::state::{Context, Data, Pointer, State};
 use crate::query::Query;
 
 impl Query for Segment {
     fn process<'a, T: Queryable>(&self, step: State<'a, T>) -> State<'a, T> {
-        match self {
-            Segment::Descendant(segment) => segment.process(step.flat_map(process_descendant)),
+        trace(TraceKind::Segment, self, step, |step| match self {
+            Segment::Descendant(segment) => {
+                let ctx = step.ctx.clone();
+                segment.process(step.flat_map(|d| process_descendant(d, &ctx, 0)))
+            }
             Segment::Selector(selector) => selector.process(step),
             Segment::Selectors(selectors) => process_selectors(step, selectors),
-        }
+        })
     }
 }
 
@@ -24,30 +29,38 @@ fn process_selectors<'a, T: Queryable>(
         .unwrap_or(step.root.into())
 }
 
-fn process_descendant<T: Queryable>(data: Pointer<T>) -> Data<T> {
+/// The node and its descendants, `depth` is how deep below the start of the segment the node is.
+pub(crate) fn process_descendant<'a, T: Queryable>(
+    data: Pointer<'a, T>,
+    ctx: &Context<'a, T>,
+    depth: usize,
+) -> Data<'a, T> {
+    if data.inner.as_array().is_none() && data.inner.as_object().is_none() || !ctx.descend(depth) {
+        return Data::Nothing;
+    }
+    let children = children(&data);
+    Data::Ref(data).reduce(run_ordered(
+        Task::Descend(depth + 1),
+        Data::new_refs(children),
+        ctx,
+    ))
+}
+
+/// The elements of an array or the members of an object, in the order of the document.
+pub(crate) fn children<'a, T: Queryable>(data: &Pointer<'a, T>) -> Vec<Pointer<'a, T>> {
     if let Some(array) = data.inner.as_array() {
-        Data::Ref(data.clone()).reduce(
-            Data::new_refs(
-                array
-                    .iter()
-                    .enumerate()
-                    .map(|(i, elem)| Pointer::idx(elem, data.path.clone(), i))
-                    .collect(),
-            )
-            .flat_map(process_descendant),
-        )
+        array
+            .iter()
+            .enumerate()
+            .map(|(i, elem)| Pointer::idx(elem, data.path.clone(), i))
+            .collect()
     } else if let Some(object) = data.inner.as_object() {
-        Data::Ref(data.clone()).reduce(
-            Data::new_refs(
-                object
-                    .into_iter()
-                    .map(|(key, value)| Pointer::key(value, data.path.clone(), key))
-                    .collect(),
-            )
-            .flat_map(process_descendant),
-        )
+        object
+            .into_iter()
+            .map(|(key, value)| Pointer::key(value, data.path.clone(), key))
+            .collect()
     } else {
-        Data::Nothing
+        vec![]
     }
 }
 
@@ -1,29 +1,49 @@
 use crate::parser::model::Selector;
+use crate::query::parallel::Task;
 use crate::query::queryable::Queryable;
-use crate::query::state::{Data, Pointer, State};
+use crate::query::state::{Context, Data, Pointer, State};
 use crate::query::Query;
 use std::cmp::{max, min};
 
 impl Query for Selector {
     fn process<'a, T: Queryable>(&self, step: State<'a, T>) -> State<'a, T> {
         match self {
-            Selector::Name(key) => step.flat_map(|d| process_key(d, key)),
-            Selector::Index(idx) => step.flat_map(|d| process_index(d, idx)),
-            Selector::Wildcard => step.flat_map(process_wildcard),
-            Selector::Slice(start, end, sl_step) => {
-                step.flat_map(|d| process_slice(d, start, end, sl_step))
-            }
             Selector::Filter(f) => f.process(step),
+            selector => step.run(Task::Select(selector)),
         }
     }
 }
 
-fn process_wildcard<T: Queryable>(
+/// The nodes the selector picks in the node, the filters are processed on their own.
+pub(crate) fn select<'a, T: Queryable>(
+    selector: &Selector,
+    node: Pointer<'a, T>,
+    ctx: &Context<'a, T>,
+) -> Data<'a, T> {
+    match selector {
+        Selector::Name(key) => picked(process_key(node, key), ctx),
+        Selector::Index(idx) => picked(process_index(node, idx), ctx),
+        Selector::Wildcard => process_wildcard(node, ctx),
+        Selector::Slice(start, end, step) => process_slice(node, start, end, step, ctx),
+        Selector::Filter(_) => Data::Nothing,
+    }
+}
+
+/// Accounts for the node a name or an index selects.
+fn picked<'a, T: Queryable>(data: Data<'a, T>, ctx: &Context<'a, T>) -> Data<'a, T> {
+    match data {
+        Data::Ref(_) if !ctx.pick() => Data::Nothing,
+        data => data,
+    }
+}
+
+fn process_wildcard<'a, T: Queryable>(
     Pointer {
         inner: pointer,
         path,
-    }: Pointer<T>,
-) -> Data<T> {
+    }: Pointer<'a, T>,
+    ctx: &Context<'a, T>,
+) -> Data<'a, T> {
     if let Some(array) = pointer.as_array() {
         if array.is_empty() {
             Data::Nothing
@@ -32,6 +52,7 @@ fn process_wildcard<T: Queryable>(
                 array
                     .iter()
                     .enumerate()
+                    .take_while(|_| ctx.pick())
                     .map(|(i, elem)| Pointer::idx(elem, path.clone(), i))
                     .collect(),
             )
@@ -43,6 +64,7 @@ fn process_wildcard<T: Queryable>(
             Data::new_refs(
                 object
                     .into_iter()
+                    .take_while(|_| ctx.pick())
                     .map(|(key, value)| Pointer::key(value, path.clone(), key))
                     .collect(),
             )
@@ -57,6 +79,7 @@ fn process_slice<'a, T: Queryable>(
     start: &Option<i64>,
     end: &Option<i64>,
     step: &Option<i64>,
+    ctx: &Context<'a, T>,
 ) -> Data<'a, T> {
     let extract_elems = |elements: &'a Vec<T>| -> Vec<(&'a T, usize)> {
         let len = elements.len() as i64;
@@ -77,7 +100,7 @@ fn process_slice<'a, T: Queryable>(
 
                 let mut idx = lower;
                 let mut res = vec![];
-                while idx < upper {
+                while idx < upper && ctx.pick() {
                     let i = idx as usize;
                     if let Some(elem) = elements.get(i) {
                         res.push((elem, i));
@@ -93,7 +116,7 @@ fn process_slice<'a, T: Queryable>(
                 let upper = min(max(n_start, -1), len - 1);
                 let mut idx = upper;
                 let mut res = vec![];
-                while lower < idx {
+                while lower < idx && ctx.pick() {
                     let i = idx as usize;
                     if let Some(elem) = elements.get(i) {
                         res.push((elem, i));
@@ -126,8 +149,8 @@ pub fn process_key<'a, T: Queryable>(
     key: &str,
 ) -> Data<'a, T> {
     inner
-        .get(key)
-        .map(|v| Data::new_ref(Pointer::key(v, path, key)))
+        .get_key_value(key)
+        .map(|(key, v)| Data::new_ref(Pointer::key(v, path, key)))
         .unwrap_or_default()
 }
 
@@ -322,7 +345,10 @@ mod tests {
 
         let vec = js_path("$['a',1]", &json)?;
 
-        assert_eq!(vec, vec![(&json!(1), "$[1]".to_string()).into(),]);
+        assert_eq!(
+            vec,
+            vec![(&json!(1), "$[1]".to_string()).try_into().unwrap(),]
+        );
 
         Ok(())
     }
@@ -338,8 +364,8 @@ mod tests {
         assert_eq!(
             vec,
             vec![
-                (&json!("ab"), "$['a']".to_string()).into(),
-                (&json!("bc"), "$['b']".to_string()).into(),
+                (&json!("ab"), "$['a']".to_string()).try_into().unwrap(),
+                (&json!("bc"), "$['b']".to_string()).try_into().unwrap(),
             ]
         );
 
@@ -0,0 +1,461 @@
+use crate::parser::functions::BuiltinFunctions;
+use crate::parser::model::{JpQuery, Segment, Selector};
+use crate::parser::options::ParserOptions;
+use crate::parser::{parse_compiled, Parsed};
+use crate::query::functions::FunctionRegistry;
+use crate::query::queryable::Queryable;
+use crate::query::regexes::Regexes;
+use crate::query::segment::process_descendant;
+use crate::query::selector::{process_index, process_key};
+use crate::query::state::{Context, Data, Pointer, State};
+use crate::query::{Query, QueryRef};
+use std::sync::Arc;
+
+/// Many queries evaluated together against a document.
+///
+/// The queries are kept in a trie by their segments,
+/// so a prefix the queries have in common (like `$.event.payload` of `$.event.payload.id`
+/// and `$.event.payload.user`) is evaluated once for all of them
+/// and the cost of a new query is only the cost of the segments it does not share.
+/// The segments following a common prefix are evaluated together where it pays off:
+/// the names and the indices are looked up in a single pass over the nodes of the prefix
+/// and the descendant segments (like `$..id` and `$..name`) share a single walk of the descendants.
+/// The other segments following it, like the filters, are evaluated one by one.
+/// The matches of every query are the same and come in the same order
+/// as if the query was evaluated on its own.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::set::QuerySet;
+/// use serde_json::{json, Value};
+///
+/// let mut rules = QuerySet::<Value>::new();
+/// let by_type = rules.add("$.event[?@.type == 'click']").unwrap();
+/// let by_user = rules.add("$.event.user").unwrap();
+///
+/// let doc = json!({"event": {"type": "view", "user": "ann"}});
+/// assert_eq!(rules.matching(&doc), vec![by_user]);
+///
+/// let found = rules.query(&doc);
+/// assert!(found[by_type].is_empty());
+/// assert_eq!(found[by_user], vec![&json!("ann")]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct QuerySet<T: Queryable> {
+    queries: Vec<JpQuery>,
+    trie: Trie,
+    functions: Option<FunctionRegistry<T>>,
+    options: ParserOptions,
+    /// The compiled literal patterns of all the queries.
+    regexes: Arc<Regexes>,
+}
+
+/// The queries sharing a prefix of segments, the path from the root of the trie.
+#[derive(Debug, Clone, Default)]
+struct Trie {
+    /// The queries made of the prefix exactly.
+    ends: Vec<usize>,
+    /// The next segments of the queries, every one is there once.
+    children: Vec<(Segment, Trie)>,
+}
+
+impl Trie {
+    fn insert(&mut self, segments: &[Segment], id: usize) {
+        match segments.split_first() {
+            None => self.ends.push(id),
+            Some((first, rest)) => {
+                let child = match self.children.iter().position(|(s, _)| s == first) {
+                    Some(idx) => &mut self.children[idx].1,
+                    None => {
+                        self.children.push((first.clone(), Trie::default()));
+                        &mut self.children.last_mut().expect("just pushed").1
+                    }
+                };
+                child.insert(rest, id)
+            }
+        }
+    }
+
+    /// Applies the remaining segments to the nodes the prefix selects,
+    /// `found` gets the matches of every query ending here or below.
+    fn process<'a, T: Queryable>(&self, state: State<'a, T>, found: &mut [Vec<Pointer<'a, T>>]) {
+        let nodes = state.data.clone().ok_ref().unwrap_or_default();
+        for id in &self.ends {
+            found[*id] = nodes.clone();
+        }
+
+        let mut looked_up = self.look_up(&nodes);
+        let mut descendants = None;
+        for (i, (segment, child)) in self.children.iter().enumerate() {
+            let next = match segment {
+                Segment::Selector(Selector::Name(_) | Selector::Index(_)) => {
+                    State::data(state.root, Data::Refs(std::mem::take(&mut looked_up[i])))
+                        .with_context(state.ctx.clone())
+                }
+                Segment::Descendant(inner) => {
+                    let walked = descendants.get_or_insert_with(|| {
+                        let ctx = state.ctx.clone();
+                        state.clone().flat_map(|d| process_descendant(d, &ctx, 0))
+                    });
+                    inner.process(walked.clone())
+                }
+                segment => segment.process(state.clone()),
+            };
+            // the queries below select nothing on the nodes they do not get to
+            match &next.data {
+                Data::Nothing => {}
+                Data::Refs(refs) if refs.is_empty() => {}
+                _ => child.process(next, found),
+            }
+        }
+    }
+
+    /// The nodes the name and index segments following the prefix select, by the index of the segment,
+    /// looked up in a single pass over the nodes of the prefix.
+    fn look_up<'a, T: Queryable>(&self, nodes: &[Pointer<'a, T>]) -> Vec<Vec<Pointer<'a, T>>> {
+        let mut looked_up = vec![vec![]; self.children.len()];
+        for node in nodes {
+            for (i, (segment, _)) in self.children.iter().enumerate() {
+                let data = match segment {
+                    Segment::Selector(Selector::Name(key)) => process_key(node.clone(), key),
+                    Segment::Selector(Selector::Index(idx)) => process_index(node.clone(), idx),
+                    _ => continue,
+                };
+                if let Data::Ref(p) = data {
+                    looked_up[i].push(p);
+                }
+            }
+        }
+        looked_up
+    }
+}
+
+impl<T: Queryable> Default for QuerySet<T> {
+    fn default() -> Self {
+        QuerySet {
+            queries: vec![],
+            trie: Trie::default(),
+            functions: None,
+            options: ParserOptions::default(),
+            regexes: Arc::default(),
+        }
+    }
+}
+
+impl<T: Queryable> QuerySet<T> {
+    /// An empty set of the queries calling the builtin functions only.
+    pub fn new() -> Self {
+        QuerySet::default()
+    }
+
+    /// An empty set of the queries that can call the functions of the registry.
+    pub fn with_functions(functions: FunctionRegistry<T>) -> Self {
+        QuerySet {
+            functions: Some(functions),
+            ..QuerySet::default()
+        }
+    }
+
+    /// An empty set of the queries parsed within the limits and with the settings of the options.
+    pub fn with_options(options: ParserOptions) -> Self {
+        QuerySet::default().parser_options(options)
+    }
+
+    /// Parses the queries added from now on with the options, see [`QuerySet::with_options`].
+    pub fn parser_options(self, options: ParserOptions) -> Self {
+        let mut regexes = Regexes::compile([], options.regex_cache, options.regex_mode);
+        for query in self.queries.iter() {
+            regexes.add_literals(query);
+        }
+        QuerySet {
+            options,
+            regexes: Arc::new(regexes),
+            ..self
+        }
+    }
+
+    /// Parses the query and adds it to the set,
+    /// returns the index of its matches in the results of the evaluation.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`crate::parser::parse_json_path_with_functions`]
+    /// and [`crate::parser::parse_json_path_with`].
+    pub fn add(&mut self, query: &str) -> Parsed<usize> {
+        let options = &self.options;
+        let (query, regexes) = match &self.functions {
+            Some(functions) => parse_compiled(query, functions, options)?,
+            None => parse_compiled(query, &BuiltinFunctions, options)?,
+        };
+        Arc::make_mut(&mut self.regexes).merge(&regexes);
+        Ok(self.insert(query))
+    }
+
+    /// Adds a parsed query to the set, see [`QuerySet::add`].
+    ///
+    /// The patterns of `match` and `search` are read in the mode of the options of the set,
+    /// the invalid ones never match.
+    pub fn add_query(&mut self, query: JpQuery) -> usize {
+        Arc::make_mut(&mut self.regexes).add_literals(&query);
+        self.insert(query)
+    }
+
+    fn insert(&mut self, query: JpQuery) -> usize {
+        let id = self.queries.len();
+        self.trie.insert(&query.segments, id);
+        self.queries.push(query);
+        id
+    }
+
+    /// The queries of the set, by their indices.
+    pub fn queries(&self) -> &[JpQuery] {
+        &self.queries
+    }
+
+    pub fn len(&self) -> usize {
+        self.queries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queries.is_empty()
+    }
+
+    fn process<'a>(&'a self, value: &'a T) -> Vec<Vec<Pointer<'a, T>>> {
+        let ctx = match &self.functions {
+            Some(functions) => Context::with_functions(functions),
+            None => Context::default(),
+        };
+        let state = State::root(value).with_context(ctx.with_regexes(self.regexes.clone()));
+        let mut found = vec![vec![]; self.queries.len()];
+        self.trie.process(state, &mut found);
+        found
+    }
+
+    /// Evaluates every query, the matches with their paths go by the indices of the queries.
+    pub fn query_with_path<'a>(&'a self, value: &'a T) -> Vec<Vec<QueryRef<'a, T>>> {
+        self.process(value)
+            .into_iter()
+            .map(|found| found.into_iter().map(Into::into).collect())
+            .collect()
+    }
+
+    /// Evaluates every query, the matches go by the indices of the queries.
+    pub fn query<'a>(&'a self, value: &'a T) -> Vec<Vec<&'a T>> {
+        self.process(value)
+            .into_iter()
+            .map(|found| found.into_iter().map(|p| p.inner).collect())
+            .collect()
+    }
+
+    /// The indices of the queries selecting anything.
+    pub fn matching(&self, value: &T) -> Vec<usize> {
+        self.process(value)
+            .iter()
+            .enumerate()
+            .filter(|(_, found)| !found.is_empty())
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::errors::JsonPathError;
+    use crate::parser::functions::{FunctionSignature, FunctionType};
+    use crate::parser::options::{ParserOptions, QueryLimit};
+    use crate::parser::parse_json_path_with;
+    use crate::query::functions::{FnValue, FunctionRegistry};
+    use crate::query::js_path;
+    use crate::query::regexes::RegexMode;
+    use crate::query::set::QuerySet;
+    use crate::query::Queried;
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn same_as_separate() -> Queried<()> {
+        let doc = json!({
+            "store": {
+                "book": [
+                    {"category": "reference", "author": "Nigel Rees", "price": 8.95},
+                    {"category": "fiction", "author": "Evelyn Waugh", "price": 12.99},
+                    {"category": "fiction", "author": "Tolkien", "price": 22.99, "isbn": "0-3"}
+                ],
+                "bicycle": {"color": "red", "price": 19.95}
+            },
+            "expensive": 10
+        });
+        let queries = [
+            "$",
+            "$.store",
+            "$.store.book[*].author",
+            "$.store.book[*].price",
+            "$.store.book[?@.price < $.expensive].author",
+            "$.store.book[?@.isbn]",
+            "$.store..price",
+            "$..price",
+            "$.store.book[-1:]",
+            "$.store.book[*].author",
+            "$.missing.book",
+            "$[?match(@.color, 'r.d')]",
+            "$..[?search(@.author, 'R')].price",
+        ];
+
+        let mut set = QuerySet::new();
+        for query in queries {
+            set.add(query)?;
+        }
+        assert_eq!(set.len(), queries.len());
+
+        let found = set.query_with_path(&doc);
+        for (query, found) in queries.iter().zip(found) {
+            assert_eq!(found, js_path(query, &doc)?, "{}", query);
+        }
+        assert_eq!(set.matching(&doc), vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 12]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn with_functions() -> Queried<()> {
+        let mut functions = FunctionRegistry::<Value>::new();
+        functions.register(
+            "positive",
+            FunctionSignature::new(vec![FunctionType::Value], FunctionType::Logical),
+            |args| FnValue::Logical(args[0].as_value().and_then(|v| v.as_f64()) > Some(0.0)),
+        )?;
+
+        assert!(QuerySet::<Value>::new().add("$[?positive(@)]").is_err());
+        let mut set = QuerySet::with_functions(functions);
+        let positive = set.add("$[?positive(@)]")?;
+        let all = set.add("$[*]")?;
+
+        let doc = json!([-1, 2, 0, 3]);
+        let found = set.query(&doc);
+        assert_eq!(found[positive], vec![&json!(2), &json!(3)]);
+        assert_eq!(found[all].len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn common_prefix_evaluated_once() -> Queried<()> {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let mut functions = FunctionRegistry::<Value>::new();
+        functions.register(
+            "small",
+            FunctionSignature::new(vec![FunctionType::Value], FunctionType::Logical),
+            move |args| {
+                counted.fetch_add(1, Ordering::Relaxed);
+                FnValue::Logical(args[0].as_value().and_then(|v| v.as_i64()) < Some(3))
+            },
+        )?;
+        let mut set = QuerySet::with_functions(functions);
+        for query in [
+            "$.a[?small(@.v)]",
+            "$.a[?small(@.v)].v",
+            "$.a[?small(@.v)].w",
+        ] {
+            set.add(query)?;
+        }
+
+        let doc = json!({"a": [{"v": 1}, {"v": 5}, {"v": 2, "w": 0}]});
+        let found = set.query(&doc);
+        assert_eq!(found[0], vec![&json!({"v": 1}), &json!({"v": 2, "w": 0})]);
+        assert_eq!(found[1], vec![&json!(1), &json!(2)]);
+        assert_eq!(found[2], vec![&json!(0)]);
+        // the filter is tested once on every element, not once per query
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn regex_mode_of_the_set() -> Queried<()> {
+        // `^` is an ordinary character of I-Regexp and an anchor of the `regex` crate
+        let query = "$[?search(@, '^a')]";
+        let lenient = ParserOptions::default().regex_mode(RegexMode::Lenient);
+        let doc = json!(["ab", "^a"]);
+
+        let mut set = QuerySet::<Value>::new();
+        let parsed = set.add(query)?;
+        let added = set.add_query(parse_json_path_with(query, &lenient)?);
+        let found = set.query(&doc);
+        assert_eq!(found[parsed], vec![&json!("^a")]);
+        assert_eq!(found[added], vec![&json!("^a")]);
+
+        let set = set.parser_options(lenient);
+        let found = set.query(&doc);
+        assert_eq!(found[parsed], vec![&json!("ab")]);
+        assert_eq!(found[added], vec![&json!("ab")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn siblings() -> Queried<()> {
+        let doc = json!({
+            "a": [{"id": 1, "name": "x"}, {"id": 2, "tags": ["t"]}],
+            "b": {"id": 3, "0": "zero"},
+            "c": [[5, 6], [7]]
+        });
+        let queries = [
+            "$.a",
+            "$.b",
+            "$['0']",
+            "$.c[0]",
+            "$.c[-1]",
+            "$.c[*][0]",
+            "$.c[*][1]",
+            "$..id",
+            "$..name",
+            "$..[0]",
+            "$..['0']",
+            "$.a..id",
+            "$.a..tags[0]",
+            "$.missing",
+        ];
+
+        let mut set = QuerySet::new();
+        for query in queries {
+            set.add(query)?;
+        }
+        let found = set.query_with_path(&doc);
+        for (query, found) in queries.iter().zip(found) {
+            assert_eq!(found, js_path(query, &doc)?, "{}", query);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn with_options() -> Queried<()> {
+        let mut set = QuerySet::<Value>::with_options(ParserOptions::default().max_segments(2));
+        assert!(set.add("$.a.b").is_ok());
+        assert_eq!(
+            set.add("$.a.b.c"),
+            Err(JsonPathError::QueryLimitExceeded(QueryLimit::Segments(2)))
+        );
+
+        let lenient = ParserOptions::default().regex_mode(RegexMode::Lenient);
+        assert!(QuerySet::<Value>::new()
+            .add("$[?match(@, '\\\\d+')]")
+            .is_err());
+        let mut set = QuerySet::with_functions(FunctionRegistry::new()).parser_options(lenient);
+        let digits = set.add("$[?match(@, '\\\\d+')]")?;
+        let from_doc = set.add("$.l[?match(@, $.p)]")?;
+
+        let doc = json!({"p": "\\w", "l": ["1", "a", "12"]});
+        assert_eq!(set.query(&doc)[digits], Vec::<&Value>::new());
+        assert_eq!(
+            set.query(&doc["l"])[digits],
+            vec![&json!("1"), &json!("12")]
+        );
+        assert_eq!(set.query(&doc)[from_doc], vec![&json!("1"), &json!("a")]);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,13 @@
+use crate::query::explain::Tracer;
+use crate::query::functions::FunctionRegistry;
+use crate::query::limits::{Budget, Limits};
+use crate::query::parallel::{run_ordered, Parallel, Task};
+use crate::query::path::{NormalizedPath, PathElem};
 use crate::query::queryable::Queryable;
-use crate::query::QueryPath;
-use std::fmt::{Display, Formatter};
+use crate::query::regexes::Regexes;
+use std::borrow::Cow;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
 
 /// Represents the state of a query, including the current data and the root object.
 /// It is used to track the progress of a query as it traverses through the data structure.
@@ -8,6 +15,177 @@ use std::fmt::{Display, Formatter};
 pub struct State<'a, T: Queryable> {
     pub data: Data<'a, T>,
     pub root: &'a T,
+    pub ctx: Context<'a, T>,
+}
+
+/// The settings of the query evaluation that are passed along with the state
+/// down to the nested queries of the filters.
+pub struct Context<'a, T: Queryable> {
+    /// The functions the query can call besides the builtin ones.
+    pub functions: Option<&'a FunctionRegistry<T>>,
+    /// The regular expressions of `match` and `search`, the ones of the query being evaluated.
+    pub regexes: Option<Arc<Regexes>>,
+    /// The account of the evaluation against its limits, shared by the nested queries.
+    pub budget: Option<Arc<Budget>>,
+    /// The trace of the evaluation being recorded, see [`crate::query::explain`].
+    pub trace: Option<Arc<Tracer<'a, T>>>,
+    /// Whether the nodes the selectors pick are the matches of the query,
+    /// they are accounted against the bound of the results as they are picked.
+    pub(crate) results: bool,
+    /// The threads the children can be processed on, see [`Context::with_parallel`].
+    pub(crate) parallel: Option<&'a dyn Parallel<T>>,
+}
+
+impl<'a, T: Queryable> Context<'a, T> {
+    pub fn with_functions(functions: &'a FunctionRegistry<T>) -> Self {
+        Context {
+            functions: Some(functions),
+            ..Context::default()
+        }
+    }
+
+    pub fn with_regexes(self, regexes: Arc<Regexes>) -> Self {
+        Context {
+            regexes: Some(regexes),
+            ..self
+        }
+    }
+
+    /// Compiles the regular expressions on the first use during the evaluation
+    /// unless the compiled ones are given already.
+    pub(crate) fn or_default_regexes(self) -> Self {
+        match self.regexes {
+            Some(_) => self,
+            None => self.with_regexes(Arc::default()),
+        }
+    }
+
+    /// Starts accounting the evaluation against the limits.
+    pub fn with_limits(self, limits: &Limits) -> Self {
+        Context {
+            budget: Some(Arc::new(Budget::new(limits))),
+            ..self
+        }
+    }
+
+    /// Records the trace of the evaluation.
+    pub fn with_trace(self, tracer: Arc<Tracer<'a, T>>) -> Self {
+        Context {
+            trace: Some(tracer),
+            ..self
+        }
+    }
+
+    /// The threads the children can be processed on,
+    /// none if the trace is recorded as it is in the order of the evaluation.
+    pub(crate) fn parallel(&self) -> Option<&'a dyn Parallel<T>> {
+        self.parallel.filter(|_| self.trace.is_none())
+    }
+
+    /// Records an operand of the expression being evaluated, if the evaluation is traced.
+    pub(crate) fn note(&self, data: &Data<'a, T>) {
+        if let Some(tracer) = &self.trace {
+            tracer.note(data)
+        }
+    }
+
+    /// Accounts for visiting a node, `false` if the evaluation has to stop.
+    pub(crate) fn visit(&self) -> bool {
+        self.budget.as_ref().is_none_or(|b| b.visit())
+    }
+
+    /// Accounts for a node a selector picks, a match of the query if the selector picks those.
+    /// `false` if the evaluation has to stop.
+    pub(crate) fn pick(&self) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|b| b.visit() && (!self.results || b.select(1)))
+    }
+
+    /// Accounts for a node a filter keeps, if the filter picks the matches of the query.
+    pub(crate) fn keep(&self) -> bool {
+        match &self.budget {
+            Some(budget) if self.results => budget.select(1),
+            _ => true,
+        }
+    }
+
+    /// The context of the last segment of the query, picking the matches.
+    pub(crate) fn picking_results(self) -> Self {
+        Context {
+            results: true,
+            ..self
+        }
+    }
+
+    /// The context of the queries nested in a filter, their nodes are not the matches.
+    pub(crate) fn nested(&self) -> Self {
+        Context {
+            results: false,
+            ..self.clone()
+        }
+    }
+
+    /// Checks the depth of a descendant to look into, `false` if the evaluation has to stop.
+    pub(crate) fn descend(&self, depth: usize) -> bool {
+        self.budget.as_ref().is_none_or(|b| b.descend(depth))
+    }
+}
+
+impl<'a, T: Queryable> Clone for Context<'a, T> {
+    fn clone(&self) -> Self {
+        Context {
+            functions: self.functions,
+            regexes: self.regexes.clone(),
+            budget: self.budget.clone(),
+            trace: self.trace.clone(),
+            results: self.results,
+            parallel: self.parallel,
+        }
+    }
+}
+
+impl<'a, T: Queryable> Default for Context<'a, T> {
+    fn default() -> Self {
+        Context {
+            functions: None,
+            regexes: None,
+            budget: None,
+            trace: None,
+            results: false,
+            parallel: None,
+        }
+    }
+}
+
+impl<'a, T: Queryable> PartialEq for Context<'a, T> {
+    fn eq(&self, other: &Self) -> bool {
+        fn same<R>(lhs: Option<&R>, rhs: Option<&R>) -> bool {
+            match (lhs, rhs) {
+                (Some(lhs), Some(rhs)) => std::ptr::eq(lhs, rhs),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        same(self.functions, other.functions)
+            && same(self.regexes.as_deref(), other.regexes.as_deref())
+            && same(self.budget.as_deref(), other.budget.as_deref())
+            && same(self.trace.as_deref(), other.trace.as_deref())
+            && self.results == other.results
+            && self.parallel.is_some() == other.parallel.is_some()
+    }
+}
+
+impl<'a, T: Queryable> Debug for Context<'a, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Context")
+            .field("functions", &self.functions.map(|r| r.names()))
+            .field("regexes", &self.regexes)
+            .field("budget", &self.budget)
+            .field("trace", &self.trace.is_some())
+            .field("parallel", &self.parallel.is_some())
+            .finish()
+    }
 }
 
 impl<'a, T: Queryable> Display for State<'a, T> {
@@ -35,13 +213,14 @@ impl<'a, T: Queryable> State<'a, T> {
     }
 
     pub fn shift_to_root(self) -> State<'a, T> {
-        State::root(self.root)
+        State::root(self.root).with_context(self.ctx)
     }
 
     pub fn root(root: &'a T) -> Self {
         State {
             root,
-            data: Data::new_ref(Pointer::new(root, "$".to_string())),
+            data: Data::new_ref(Pointer::root(root)),
+            ctx: Context::default(),
         }
     }
 
@@ -49,11 +228,21 @@ impl<'a, T: Queryable> State<'a, T> {
         State {
             root,
             data: Data::Nothing,
+            ctx: Context::default(),
         }
     }
 
     pub fn data(root: &'a T, data: Data<'a, T>) -> Self {
-        State { root, data }
+        State {
+            root,
+            data,
+            ctx: Context::default(),
+        }
+    }
+
+    /// Replaces the context of the state, keeping the data.
+    pub fn with_context(self, ctx: Context<'a, T>) -> Self {
+        State { ctx, ..self }
     }
 
     pub fn ok_ref(self) -> Option<Vec<Pointer<'a, T>>> {
@@ -75,15 +264,41 @@ impl<'a, T: Queryable> State<'a, T> {
         State {
             root: self.root,
             data: self.data.reduce(other.data),
+            ctx: self.ctx,
         }
     }
     pub fn flat_map<F>(self, f: F) -> State<'a, T>
     where
         F: Fn(Pointer<'a, T>) -> Data<'a, T>,
     {
+        let ctx = &self.ctx;
+        let data =
+            // the nodes are accounted for by the selectors picking them
+            match &ctx.budget {
+                Some(budget) => self.data.flat_map(|p| {
+                    if budget.aborted().is_none() {
+                        f(p)
+                    } else {
+                        Data::Nothing
+                    }
+                }),
+                None => self.data.flat_map(f),
+            };
         State {
             root: self.root,
-            data: self.data.flat_map(f),
+            data,
+            ctx: self.ctx,
+        }
+    }
+
+    /// Runs the task on every node, on several threads if the context allows it,
+    /// see [`crate::query::parallel`].
+    pub(crate) fn run(self, task: Task<'_, 'a, T>) -> State<'a, T> {
+        let data = run_ordered(task, self.data, &self.ctx);
+        State {
+            root: self.root,
+            data,
+            ctx: self.ctx,
         }
     }
 }
@@ -195,29 +410,161 @@ impl<'a, T: Queryable> Data<'a, T> {
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Pointer<'a, T: Queryable> {
     pub inner: &'a T,
-    pub path: QueryPath,
+    pub path: Location<'a>,
+}
+
+/// The location of a node in the document.
+///
+/// A child keeps a link to the location of its parent along with its own key or index,
+/// and the normalized path is rendered only when it is asked for,
+/// so the nodes that are dropped along the way or the queries
+/// that need only the values do not pay for building the paths.
+#[derive(Clone)]
+pub(crate) enum Location<'a> {
+    /// A node inside a filter, it has no path.
+    Internal,
+    /// The root of the document, `$`.
+    Root,
+    /// A node with the path given upfront.
+    Path(Arc<NormalizedPath>),
+    /// A member of an object or an element of an array.
+    Child(Arc<(Location<'a>, Step<'a>)>),
 }
 
-/// Renders a member name as a `normal-name-selector` of a Normalized Path (RFC 9535, section 2.7):
-/// single quotes, and only `'`, `\` and the C0 controls escaped.
-fn normal_name_selector(name: &str) -> String {
-    let mut selector = String::with_capacity(name.len() + 2);
-    selector.push('\'');
-    for ch in name.chars() {
-        match ch {
-            '\u{0008}' => selector.push_str("\\b"),
-            '\u{000C}' => selector.push_str("\\f"),
-            '\n' => selector.push_str("\\n"),
-            '\r' => selector.push_str("\\r"),
-            '\t' => selector.push_str("\\t"),
-            '\'' => selector.push_str("\\'"),
-            '\\' => selector.push_str("\\\\"),
-            c if c < '\u{0020}' => selector.push_str(&format!("\\u{:04x}", c as u32)),
-            c => selector.push(c),
-        }
-    }
-    selector.push('\'');
-    selector
+/// The last step of the path to a child node.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Step<'a> {
+    Key(Cow<'a, str>),
+    Index(usize),
+}
+
+impl<'a> Location<'a> {
+    pub fn key(self, key: Cow<'a, str>) -> Self {
+        Location::Child(Arc::new((self, Step::Key(key))))
+    }
+
+    pub fn idx(self, index: usize) -> Self {
+        Location::Child(Arc::new((self, Step::Index(index))))
+    }
+
+    pub fn is_internal(&self) -> bool {
+        matches!(self, Location::Internal)
+    }
+
+    /// Whether the node is `@` of a filter or below it, the path of such a node is not known.
+    pub fn in_filter(&self) -> bool {
+        self.base().is_internal()
+    }
+}
+
+impl<'a> Location<'a> {
+    /// Builds the path by walking up the chain of the parents.
+    pub fn to_path(&self) -> NormalizedPath {
+        // the chain is walked without recursion, the document can be arbitrarily deep
+        let mut steps = vec![];
+        let mut location = self;
+        while let Location::Child(child) = location {
+            steps.push(&child.1);
+            location = &child.0;
+        }
+        let mut path = match location {
+            Location::Path(path) => path.as_ref().clone(),
+            _ => NormalizedPath::root(),
+        };
+        for step in steps.into_iter().rev() {
+            match step {
+                Step::Key(key) => path.push(key.as_ref()),
+                Step::Index(index) => path.push(*index),
+            }
+        }
+        path
+    }
+}
+
+impl<'a> Display for Location<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Internal => Ok(()),
+            location => write!(f, "{}", location.to_path()),
+        }
+    }
+}
+
+impl<'a> From<NormalizedPath> for Location<'a> {
+    fn from(path: NormalizedPath) -> Self {
+        if path.is_root() {
+            Location::Root
+        } else {
+            Location::Path(Arc::new(path))
+        }
+    }
+}
+
+/// Reads a path written as a string to build the expected pointers in the tests.
+#[cfg(test)]
+impl<'a> From<String> for Location<'a> {
+    fn from(path: String) -> Self {
+        path.parse::<NormalizedPath>()
+            .expect("a path of names and indices")
+            .into()
+    }
+}
+
+impl<'a> Debug for Location<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+impl<'a> Location<'a> {
+    /// The location the chain of the parents starts with.
+    fn base(&self) -> &Location<'a> {
+        let mut location = self;
+        while let Location::Child(child) = location {
+            location = &child.0;
+        }
+        location
+    }
+
+    /// The steps of the path from the node up to the root.
+    fn steps_up(&self) -> impl Iterator<Item = PathStep<'_>> {
+        let children = std::iter::successors(Some(self), |l| match l {
+            Location::Child(child) => Some(&child.0),
+            _ => None,
+        })
+        .filter_map(|l| match l {
+            Location::Child(child) => Some(match &child.1 {
+                Step::Key(key) => PathStep::Key(key.as_ref()),
+                Step::Index(index) => PathStep::Index(*index),
+            }),
+            _ => None,
+        });
+        let given = match self.base() {
+            Location::Path(path) => path.elems(),
+            _ => &[],
+        };
+        children.chain(given.iter().rev().map(|e| match e {
+            PathElem::Name(name) => PathStep::Key(name.as_str()),
+            PathElem::Index(index) => PathStep::Index(*index),
+        }))
+    }
+}
+
+/// A step of a path, whether it comes from the chain or from a path given upfront.
+#[derive(PartialEq)]
+enum PathStep<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+/// The locations are compared step by step from the nodes up,
+/// the paths are not built for that.
+/// A location inside a filter is equal only to another one inside a filter.
+impl<'a> PartialEq for Location<'a> {
+    fn eq(&self, other: &Self) -> bool {
+        self.base().is_internal() == other.base().is_internal()
+            && self.steps_up().eq(other.steps_up())
+    }
 }
 
 impl<'a, T: Queryable> Display for Pointer<'a, T> {
@@ -227,31 +574,101 @@ impl<'a, T: Queryable> Display for Pointer<'a, T> {
 }
 
 impl<'a, T: Queryable> Pointer<'a, T> {
-    pub fn new(inner: &'a T, path: QueryPath) -> Self {
-        Pointer { inner, path }
+    pub fn new<P: Into<Location<'a>>>(inner: &'a T, path: P) -> Self {
+        Pointer {
+            inner,
+            path: path.into(),
+        }
+    }
+
+    pub fn root(inner: &'a T) -> Self {
+        Pointer {
+            inner,
+            path: Location::Root,
+        }
     }
 
-    pub fn key(inner: &'a T, path: QueryPath, key: &str) -> Self {
+    pub fn key<K: Into<Cow<'a, str>>>(inner: &'a T, path: Location<'a>, key: K) -> Self {
         Pointer {
             inner,
-            path: format!("{}[{}]", path, normal_name_selector(key)),
+            path: path.key(key.into()),
         }
     }
-    pub fn idx(inner: &'a T, path: QueryPath, index: usize) -> Self {
+    pub fn idx(inner: &'a T, path: Location<'a>, index: usize) -> Self {
         Pointer {
             inner,
-            path: format!("{}[{}]", path, index),
+            path: path.idx(index),
         }
     }
 
     pub fn empty(inner: &'a T) -> Self {
         Pointer {
             inner,
-            path: String::new(),
+            path: Location::Internal,
         }
     }
 
     pub fn is_internal(&self) -> bool {
-        self.path.is_empty()
+        self.path.is_internal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::path::NormalizedPath;
+    use crate::query::state::Location;
+    use std::borrow::Cow;
+
+    fn path(path: &str) -> NormalizedPath {
+        path.parse().expect("a path of names and indices")
+    }
+
+    #[test]
+    fn paths_of_chains() {
+        let root = Location::Root;
+        assert_eq!(root.to_path(), NormalizedPath::root());
+
+        let item = root.clone().key(Cow::Borrowed("a")).idx(2);
+        assert_eq!(item.to_path(), path("$['a'][2]"));
+        assert_eq!(item.to_string(), "$['a'][2]");
+
+        // a descendant segment continues the chain of the node it starts from
+        let deep = item
+            .clone()
+            .key(Cow::Borrowed("b"))
+            .idx(0)
+            .key(Cow::Owned("c'd".into()));
+        assert_eq!(deep.to_path(), path("$['a'][2]['b'][0]['c\\'d']"));
+
+        let given = Location::from(path("$['x'][1]")).key(Cow::Borrowed("y"));
+        assert_eq!(given.to_path(), path("$['x'][1]['y']"));
+
+        let inner = Location::Internal.idx(1).key(Cow::Borrowed("a"));
+        assert!(inner.in_filter());
+        assert!(!deep.in_filter());
+        assert_eq!(inner.to_string(), "$[1]['a']");
+    }
+
+    #[test]
+    fn equality_of_chains() {
+        let chain = Location::Root.key(Cow::Borrowed("a")).idx(2);
+
+        assert_eq!(chain, Location::from(path("$['a'][2]")));
+        assert_eq!(
+            chain,
+            Location::from(path("$['a']")).idx(2),
+            "a chain over a given path"
+        );
+        assert_eq!(chain, Location::Root.key(Cow::Owned("a".into())).idx(2));
+        assert_ne!(chain, Location::Root.key(Cow::Borrowed("a")).idx(3));
+        assert_ne!(chain, Location::Root.idx(2));
+        assert_ne!(chain, Location::Root.key(Cow::Borrowed("2")));
+        assert_ne!(Location::Root, Location::Root.idx(0));
+        assert_eq!(Location::Root, Location::from(NormalizedPath::root()));
+
+        assert_eq!(Location::Internal, Location::Internal);
+        assert_ne!(Location::Internal, Location::Root);
+        assert_ne!(Location::Internal.idx(2), Location::Root.idx(2));
+        assert_eq!(Location::Internal.idx(2), Location::Internal.idx(2));
     }
 }
@@ -0,0 +1,638 @@
+use crate::parser::errors::JsonPathError;
+use crate::parser::model::{
+    Comparable, Filter, FilterAtom, FnArg, JpQuery, Segment, Selector, SingularQuery, Test,
+    TestFunction,
+};
+use crate::parser::{parse_json_path, Parsed};
+use crate::query::path::{NormalizedPath, PathElem};
+use crate::query::queryable::Queryable;
+use crate::query::regexes::Regexes;
+use crate::query::state::{Context, State};
+use crate::query::{process_pointers, Queried, QueryPath};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess,
+    Visitor,
+};
+use std::fmt::Formatter;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A query evaluated while the document is deserialized, without building it in memory.
+///
+/// Only the subtrees the query needs are built: the matches and the children a filter is tested on.
+/// Everything else is skipped as it is read, so the memory depends on the size
+/// of the biggest of those subtrees rather than on the size of the document.
+///
+/// The query has to be streamable:
+/// the indices and the bounds of the slices are not negative, the steps of the slices are positive
+/// (they do not depend on the length of the array) and the filters do not refer to the root (`$`).
+///
+/// A match is passed on as soon as its subtree is read,
+/// so a match nested in another one (like with `$..*`) comes before it.
+/// A node selected more than once (like with `$[0,0]`) is passed on every time,
+/// as the evaluation in memory returns it.
+///
+/// # Examples
+///
+/// ```
+/// use jsonpath_rust::query::stream::StreamQuery;
+/// use serde_json::{json, Value};
+///
+/// let query = StreamQuery::new("$.logs[?@.level == 'error'].message").unwrap();
+/// let input = r#"{"logs": [
+///     {"level": "info", "message": "started"},
+///     {"level": "error", "message": "failed"}
+/// ]}"#;
+///
+/// let mut found = vec![];
+/// query
+///     .process_reader(input.as_bytes(), |path, value: Value| found.push((path.to_string(), value)))
+///     .unwrap();
+///
+/// assert_eq!(found, vec![("$['logs'][1]['message']".to_string(), json!("failed"))]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct StreamQuery {
+    query: JpQuery,
+    /// The remaining segments from every position, evaluated on the subtrees built in memory.
+    suffixes: Vec<JpQuery>,
+    regexes: Arc<Regexes>,
+}
+
+impl StreamQuery {
+    /// Parses the query and checks that it is streamable.
+    pub fn new(query: &str) -> Parsed<Self> {
+        StreamQuery::from_query(parse_json_path(query)?)
+    }
+
+    /// Checks that the query is streamable.
+    pub fn from_query(query: JpQuery) -> Parsed<Self> {
+        query.segments.iter().try_for_each(streamable)?;
+        let suffixes = (0..query.segments.len())
+            .map(|i| JpQuery::new(query.segments[i..].to_vec()))
+            .collect();
+        let mut regexes = Regexes::default();
+        regexes.add_literals(&query);
+        Ok(StreamQuery {
+            query,
+            suffixes,
+            regexes: Arc::new(regexes),
+        })
+    }
+
+    /// The parsed query.
+    pub fn as_query(&self) -> &JpQuery {
+        &self.query
+    }
+
+    /// Evaluates the query on the document the deserializer reads,
+    /// `on_match` gets every match with its path.
+    pub fn process<'de, D, T, F>(&self, deserializer: D, on_match: F) -> Result<(), D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Queryable + DeserializeOwned,
+        F: FnMut(QueryPath, T),
+    {
+        Run::new(self, on_match).process(deserializer)
+    }
+
+    /// Evaluates the query on the JSON document the reader reads, see [`StreamQuery::process`].
+    ///
+    /// The reader is read byte by byte, a [`std::io::BufReader`] makes it faster.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`JsonPathError::Deserialization`] with the path of the node that fails to be read
+    /// if the document is not valid JSON, and the error of the evaluation if it fails.
+    pub fn process_reader<R, T, F>(&self, reader: R, on_match: F) -> Queried<()>
+    where
+        R: Read,
+        T: Queryable + DeserializeOwned,
+        F: FnMut(QueryPath, T),
+    {
+        let mut deserializer = serde_json::Deserializer::from_reader(reader);
+        let mut run = Run::new(self, on_match);
+        run.process(&mut deserializer)
+            .and_then(|_| deserializer.end())
+            .map_err(|e| match run.error.take() {
+                Some(error) => error,
+                None => JsonPathError::Deserialization {
+                    path: run.failed.take().unwrap_or_else(NormalizedPath::root),
+                    message: e.to_string(),
+                },
+            })
+    }
+}
+
+/// Rejects the selectors that depend on the whole array or on the root.
+fn streamable(segment: &Segment) -> Parsed<()> {
+    let rejected = |reason: &str| {
+        Err(JsonPathError::InvalidJsonPath(format!(
+            "`{}` can not be evaluated in a stream: {}",
+            segment, reason
+        )))
+    };
+    let selectors = match segment {
+        Segment::Descendant(inner) => return streamable(inner),
+        Segment::Selector(s) => std::slice::from_ref(s),
+        Segment::Selectors(ss) => ss.as_slice(),
+    };
+    for selector in selectors {
+        match selector {
+            Selector::Index(i) if *i < 0 => return rejected("the index is negative"),
+            Selector::Slice(start, end, step)
+                if start.unwrap_or(0) < 0 || end.unwrap_or(0) < 0 || step.unwrap_or(1) <= 0 =>
+            {
+                return rejected("the slice depends on the length of the array")
+            }
+            Selector::Filter(f) if filter_refers_to_root(f) => {
+                return rejected("the filter refers to the root")
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn filter_refers_to_root(filter: &Filter) -> bool {
+    match filter {
+        Filter::Or(fs) | Filter::And(fs) => fs.iter().any(filter_refers_to_root),
+        Filter::Atom(FilterAtom::Filter { expr, .. }) => filter_refers_to_root(expr),
+        Filter::Atom(FilterAtom::Test { expr, .. }) => test_refers_to_root(expr),
+        Filter::Atom(FilterAtom::Comparison(cmp)) => {
+            let (lhs, rhs) = cmp.vals();
+            [lhs, rhs].into_iter().any(|c| match c {
+                Comparable::Literal(_) => false,
+                Comparable::Function(f) => function_refers_to_root(f),
+                Comparable::SingularQuery(q) => matches!(q, SingularQuery::Root(_)),
+            })
+        }
+    }
+}
+
+fn test_refers_to_root(test: &Test) -> bool {
+    match test {
+        Test::AbsQuery(_) => true,
+        Test::RelQuery(segments) => segments.iter().any(segment_refers_to_root),
+        Test::Function(f) => function_refers_to_root(f),
+    }
+}
+
+fn segment_refers_to_root(segment: &Segment) -> bool {
+    match segment {
+        Segment::Descendant(inner) => segment_refers_to_root(inner),
+        Segment::Selector(s) => matches!(s, Selector::Filter(f) if filter_refers_to_root(f)),
+        Segment::Selectors(ss) => ss
+            .iter()
+            .any(|s| matches!(s, Selector::Filter(f) if filter_refers_to_root(f))),
+    }
+}
+
+fn function_refers_to_root(function: &TestFunction) -> bool {
+    let args = match function {
+        TestFunction::Custom(_, args) => args.iter().collect(),
+        TestFunction::Length(arg) => vec![arg.as_ref()],
+        TestFunction::Value(arg) | TestFunction::Count(arg) => vec![arg],
+        TestFunction::Search(lhs, rhs) | TestFunction::Match(lhs, rhs) => vec![lhs, rhs],
+    };
+    args.into_iter().any(|arg| match arg {
+        FnArg::Literal(_) => false,
+        FnArg::Test(t) => test_refers_to_root(t),
+        FnArg::Filter(f) => filter_refers_to_root(f),
+    })
+}
+
+/// A single evaluation, the positions in the query a node is at are its states:
+/// the state `i` means the node is selected by the first `i` segments.
+struct Run<'q, T, F> {
+    query: &'q StreamQuery,
+    path: NormalizedPath,
+    on_match: F,
+    node: PhantomData<T>,
+    /// The innermost node that fails to be read.
+    failed: Option<NormalizedPath>,
+    /// The error of the evaluation of a subtree built in memory.
+    error: Option<JsonPathError>,
+}
+
+/// What to do with a child.
+enum Next {
+    /// No segment selects the child or its descendants.
+    Skip,
+    /// The child is read with the states.
+    Stream(Vec<usize>),
+    /// The child is needed in memory: it matches or a filter is tested on it.
+    Buffer,
+}
+
+impl<'q, T: Queryable, F: FnMut(QueryPath, T)> Run<'q, T, F> {
+    fn new(query: &'q StreamQuery, on_match: F) -> Self {
+        Run {
+            query,
+            path: NormalizedPath::root(),
+            on_match,
+            node: PhantomData,
+            failed: None,
+            error: None,
+        }
+    }
+
+    fn process<'de, D: Deserializer<'de>>(&mut self, deserializer: D) -> Result<(), D::Error>
+    where
+        T: DeserializeOwned,
+    {
+        if self.query.query.segments.is_empty() {
+            let root = T::deserialize(deserializer)?;
+            self.buffered(root, &[0])
+        } else {
+            Node {
+                run: self,
+                states: vec![0],
+            }
+            .deserialize(deserializer)
+        }
+    }
+
+    /// Records the path of the node being read if the reading fails,
+    /// the node is the child `elem` of the current one or the current one itself.
+    fn read<V, E>(&mut self, elem: Option<&PathElem>, read: Result<V, E>) -> Result<V, E> {
+        if read.is_err() && self.failed.is_none() {
+            self.failed = Some(match elem {
+                Some(elem) => self.path.child(elem.clone()),
+                None => self.path.clone(),
+            });
+        }
+        read
+    }
+
+    fn next(&self, parent: &[usize], elem: &PathElem) -> Next {
+        match self.child_states(parent, elem, None) {
+            None => Next::Buffer,
+            Some(states) if states.is_empty() => Next::Skip,
+            Some(states) if states.contains(&self.query.query.segments.len()) => Next::Buffer,
+            Some(states) => Next::Stream(states),
+        }
+    }
+
+    /// The states of the child, `None` if a filter has to be tested on it and it is not given.
+    fn child_states(
+        &self,
+        parent: &[usize],
+        elem: &PathElem,
+        child: Option<&T>,
+    ) -> Option<Vec<usize>> {
+        let ctx = Context::default().with_regexes(self.query.regexes.clone());
+        let mut states = vec![];
+        for &i in parent {
+            let segment = &self.query.query.segments[i];
+            if let Segment::Descendant(_) = segment {
+                states.push(i);
+            }
+            let selected = segment_selects(segment, elem, child, &ctx)?;
+            states.extend(std::iter::repeat_n(i + 1, selected));
+        }
+        states.sort_unstable();
+        Some(states)
+    }
+
+    /// Evaluates the rest of the query on a child built in memory, the path points to it.
+    fn buffered<E: de::Error>(&mut self, node: T, states: &[usize]) -> Result<(), E> {
+        let query = self.query;
+        let ctx = Context::default().with_regexes(query.regexes.clone());
+        for &state in states {
+            if state == query.query.segments.len() {
+                (self.on_match)(self.path.clone(), node.clone());
+                continue;
+            }
+            let found = match process_pointers(
+                &query.suffixes[state],
+                State::root(&node).with_context(ctx.clone()),
+            ) {
+                Ok(found) => found,
+                Err(error) => {
+                    let e = E::custom(&error);
+                    self.error.get_or_insert(error);
+                    return Err(e);
+                }
+            };
+            for pointer in found {
+                let mut path = self.path.clone();
+                pointer
+                    .path
+                    .to_path()
+                    .iter()
+                    .for_each(|e| path.push(e.clone()));
+                (self.on_match)(path, pointer.inner.clone());
+            }
+        }
+        Ok(())
+    }
+
+    fn buffer_child<E: de::Error>(
+        &mut self,
+        parent: &[usize],
+        elem: PathElem,
+        child: T,
+    ) -> Result<(), E> {
+        let states = self
+            .child_states(parent, &elem, Some(&child))
+            .unwrap_or_default();
+        if states.is_empty() {
+            return Ok(());
+        }
+        self.path.push(elem);
+        let res = self.buffered(child, &states);
+        self.path.pop();
+        res
+    }
+}
+
+/// How many selectors of the segment select the child,
+/// `None` if it depends on the child that is not given.
+fn segment_selects<'a, T: Queryable>(
+    segment: &Segment,
+    elem: &PathElem,
+    child: Option<&'a T>,
+    ctx: &Context<'a, T>,
+) -> Option<usize> {
+    let selectors = match segment {
+        Segment::Descendant(inner) => return segment_selects(inner, elem, child, ctx),
+        Segment::Selector(s) => std::slice::from_ref(s),
+        Segment::Selectors(ss) => ss.as_slice(),
+    };
+    let mut selected = 0;
+    for selector in selectors {
+        if selects(selector, elem, child, ctx)? {
+            selected += 1;
+        }
+    }
+    Some(selected)
+}
+
+fn selects<'a, T: Queryable>(
+    selector: &Selector,
+    elem: &PathElem,
+    child: Option<&'a T>,
+    ctx: &Context<'a, T>,
+) -> Option<bool> {
+    match (selector, elem) {
+        (Selector::Wildcard, _) => Some(true),
+        (Selector::Name(name), PathElem::Name(key)) => Some(name == key),
+        (Selector::Index(i), PathElem::Index(idx)) => Some(*i as usize == *idx),
+        (Selector::Slice(start, end, step), PathElem::Index(idx)) => {
+            let (idx, start, step) = (*idx as i64, start.unwrap_or(0), step.unwrap_or(1));
+            Some(idx >= start && end.is_none_or(|end| idx < end) && (idx - start) % step == 0)
+        }
+        (Selector::Filter(filter), _) => child.map(|child| filter.test_node(child, ctx)),
+        _ => Some(false),
+    }
+}
+
+/// A node read with its states.
+struct Node<'r, 'q, T, F> {
+    run: &'r mut Run<'q, T, F>,
+    states: Vec<usize>,
+}
+
+impl<'de, 'r, 'q, T, F> DeserializeSeed<'de> for Node<'r, 'q, T, F>
+where
+    T: Queryable + DeserializeOwned,
+    F: FnMut(QueryPath, T),
+{
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'r, 'q, T, F> Visitor<'de> for Node<'r, 'q, T, F>
+where
+    T: Queryable + DeserializeOwned,
+    F: FnMut(QueryPath, T),
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    // the segments select nothing in the scalars
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        for idx in 0.. {
+            let elem = PathElem::Index(idx);
+            let more = match self.run.next(&self.states, &elem) {
+                Next::Skip => {
+                    let skipped = seq.next_element::<IgnoredAny>();
+                    self.run.read(Some(&elem), skipped)?.is_some()
+                }
+                Next::Stream(states) => {
+                    self.run.path.push(elem);
+                    let more = seq.next_element_seed(Node {
+                        run: &mut *self.run,
+                        states,
+                    });
+                    let more = self.run.read(None, more);
+                    self.run.path.pop();
+                    more?.is_some()
+                }
+                Next::Buffer => {
+                    let child = seq.next_element::<T>();
+                    match self.run.read(Some(&elem), child)? {
+                        Some(child) => {
+                            self.run.buffer_child(&self.states, elem, child)?;
+                            true
+                        }
+                        None => false,
+                    }
+                }
+            };
+            if !more {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        loop {
+            let key = map.next_key::<String>();
+            let Some(key) = self.run.read(None, key)? else {
+                break;
+            };
+            let elem = PathElem::Name(key);
+            match self.run.next(&self.states, &elem) {
+                Next::Skip => {
+                    let skipped = map.next_value::<IgnoredAny>();
+                    self.run.read(Some(&elem), skipped)?;
+                }
+                Next::Stream(states) => {
+                    self.run.path.push(elem);
+                    let res = map.next_value_seed(Node {
+                        run: &mut *self.run,
+                        states,
+                    });
+                    let res = self.run.read(None, res);
+                    self.run.path.pop();
+                    res?
+                }
+                Next::Buffer => {
+                    let child = map.next_value::<T>();
+                    let child = self.run.read(Some(&elem), child)?;
+                    self.run.buffer_child(&self.states, elem, child)?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::errors::JsonPathError;
+    use crate::query::js_path;
+    use crate::query::stream::StreamQuery;
+    use crate::query::{Queried, QueryPath};
+    use serde_json::{json, Value};
+
+    fn stream(query: &str, doc: &str) -> Queried<Vec<(QueryPath, Value)>> {
+        let mut found = vec![];
+        StreamQuery::new(query)?.process_reader(doc.as_bytes(), |p, v| found.push((p, v)))?;
+        Ok(found)
+    }
+
+    #[test]
+    fn same_as_in_memory() -> Queried<()> {
+        let doc = json!({
+            "store": {
+                "book": [
+                    {"category": "reference", "author": "Nigel Rees", "price": 8.95},
+                    {"category": "fiction", "author": "Evelyn Waugh", "price": 12.99},
+                    {"category": "fiction", "author": "Tolkien", "price": 22.99, "isbn": "0-3"}
+                ],
+                "bicycle": {"color": "red", "price": 19.95}
+            },
+            "nested": [[1, [2, 3]], {"a": {"a": [4]}}]
+        });
+        let text = doc.to_string();
+
+        for query in [
+            "$",
+            "$.store.book[*].author",
+            "$..author",
+            "$.store.*",
+            "$.store..price",
+            "$..book[2]",
+            "$..book[1:]",
+            "$..book[0,2]",
+            "$..book[::2].price",
+            "$..book[?@.isbn]",
+            "$..book[?@.price < 10 && match(@.category, 'ref.*')].author",
+            "$..[?@.author].price",
+            "$..*",
+            "$..[0]",
+            "$..a..a",
+            "$.nested..[*]",
+            "$..[?@ > 1]",
+            "$.missing..a",
+            "$['store', 'nested']..price",
+            "$..book[0,0].author",
+            "$.store['book', 'book'][?@.isbn, ?@.price > 20]",
+        ] {
+            let mut streamed = stream(query, &text)?;
+            let mut expected = js_path(query, &doc)?
+                .into_iter()
+                .map(|r| (r.path, r.val.clone()))
+                .collect::<Vec<_>>();
+            streamed.sort_by(|a, b| a.0.cmp(&b.0));
+            expected.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(streamed, expected, "{}", query);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn completion_order() -> Queried<()> {
+        let found = stream("$..*", r#"{"a": [1, {"b": 2}], "c": 3}"#)?
+            .into_iter()
+            .map(|(p, _)| p.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                "$['a'][0]",
+                "$['a'][1]",
+                "$['a'][1]['b']",
+                "$['a']",
+                "$['c']"
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn not_streamable() {
+        for query in [
+            "$[-1]",
+            "$..[-2:]",
+            "$[:-1]",
+            "$[::-1]",
+            "$[?@.a == $.b]",
+            "$..[?@[?@ > $.min]]",
+            "$[?length($.a) > 1]",
+        ] {
+            assert!(StreamQuery::new(query).is_err(), "{}", query);
+        }
+        assert!(stream("$.a", "{\"a\": [1, 2}").is_err());
+    }
+
+    #[test]
+    fn failed_path() {
+        for (query, doc, path) in [
+            (
+                "$.a[*].b",
+                r#"{"a": [{"b": 1}, {"b": tru}]}"#,
+                "$['a'][1]['b']",
+            ),
+            ("$.a", r#"{"x": [1, 2 3], "a": 1}"#, "$['x']"),
+            ("$.a..c", r#"{"a": [{"b": [1, {]}}]}"#, "$['a'][0]['b'][1]"),
+            ("$.a", r#"{"a": 1,}"#, "$"),
+            ("$", r#"[1"#, "$"),
+        ] {
+            match stream(query, doc) {
+                Err(JsonPathError::Deserialization { path: failed, .. }) => {
+                    assert_eq!(failed, path, "{}", doc)
+                }
+                res => panic!("{}: {:?}", doc, res),
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use crate::parser::functions::{BuiltinFunctions, FunctionSignatures};
 use crate::parser::model::{FnArg, TestFunction};
+use crate::query::explain::{trace, TraceKind};
+use crate::query::functions::{builtin, FnValue};
 use crate::query::queryable::Queryable;
 use crate::query::state::{Data, Pointer, State};
 use crate::query::Query;
 use regex::Regex;
-use std::borrow::Cow;
 
 impl TestFunction {
     pub fn apply<'a, T: Queryable>(&self, state: State<'a, T>) -> State<'a, T> {
@@ -35,26 +37,34 @@ impl Query for FnArg {
 
 impl Query for TestFunction {
     fn process<'a, T: Queryable>(&self, step: State<'a, T>) -> State<'a, T> {
-        self.apply(step)
+        trace(TraceKind::Function, self, step, |step| self.apply(step))
     }
 }
 
-fn custom<'a, T: Queryable>(name: &str, args: &Vec<FnArg>, state: State<'a, T>) -> State<'a, T> {
+/// Calls a function extension, converting the arguments to the types declared in its signature.
+/// The functions of the registry in the context take precedence over the builtin extensions.
+fn custom<'a, T: Queryable>(name: &str, args: &[FnArg], state: State<'a, T>) -> State<'a, T> {
+    let registry = state.ctx.functions;
+    let signature = match registry {
+        Some(functions) => functions.signature(name),
+        None => BuiltinFunctions.signature(name),
+    };
+    let Some(signature) = signature else {
+        return State::nothing(state.root).with_context(state.ctx);
+    };
+
     let args = args
-        .into_iter()
-        .map(|v| v.process(state.clone()))
-        .flat_map(|v| match v.data {
-            Data::Value(v) => vec![Cow::Owned(v)],
-            Data::Ref(Pointer { inner, .. }) => vec![Cow::Borrowed(inner)],
-            Data::Refs(v) => v.into_iter().map(|v| Cow::Borrowed(v.inner)).collect(),
-            _ => vec![],
-        })
+        .iter()
+        .zip(signature.params)
+        .map(|(arg, tpe)| FnValue::from_data(arg.process(state.clone()).data, tpe))
         .collect::<Vec<_>>();
 
-    State::data(
-        state.root,
-        Data::Value(Queryable::extension_custom(name, args)),
-    )
+    let res = match registry {
+        Some(functions) => functions.call(name, args),
+        None => builtin(name, args),
+    };
+
+    State::data(state.root, res.into_data()).with_context(state.ctx)
 }
 
 /// Returns the length/size of the object.
@@ -118,7 +128,9 @@ fn count<T: Queryable>(state: State<T>) -> State<T> {
 /// if the string matches the I-Regexp and is LogicalFalse otherwise.
 fn regex<'a, T: Queryable>(lhs: State<'a, T>, rhs: State<'a, T>, substr: bool) -> State<'a, T> {
     let to_state = |b| State::bool(b, lhs.root);
-    let regex = |v: &str, r: Regex| {
+    // a state evaluated without a query has nothing to cache the expressions in
+    let regexes = lhs.ctx.regexes.clone().unwrap_or_default();
+    let regex = |v: &str, r: &Regex| {
         if substr {
             r.find(v).is_some()
         } else {
@@ -132,32 +144,14 @@ fn regex<'a, T: Queryable>(lhs: State<'a, T>, rhs: State<'a, T>, substr: bool) -
     };
 
     match (to_str(lhs), to_str(rhs)) {
-        (Some(lhs), Some(rhs)) => Regex::new(&prepare_regex(rhs, substr))
-            .map(|re| to_state(regex(&lhs, re)))
-            .unwrap_or(to_state(false)),
+        (Some(lhs), Some(rhs)) => match regexes.get(&rhs, substr) {
+            Some(re) => to_state(regex(&lhs, &re)),
+            None => to_state(false),
+        },
         _ => to_state(false),
     }
 }
 
-fn prepare_regex(pattern: String, substring: bool) -> String {
-    let pattern = if !substring {
-        let pattern = if pattern.starts_with('^') {
-            pattern
-        } else {
-            format!("^{}", pattern)
-        };
-        let pattern = if pattern.ends_with('$') {
-            pattern
-        } else {
-            format!("{}$", pattern)
-        };
-        pattern
-    } else {
-        pattern.to_string()
-    };
-    pattern
-}
-
 fn value<T: Queryable>(state: State<T>) -> State<T> {
     match state.data {
         Data::Ref(..) | Data::Value(..) => state,